@@ -0,0 +1,89 @@
+//! Compares the plain lexer with `CompiledLexer` on a large generated config file.
+//!
+//! Run with `cargo bench --bench lexer`.
+
+use mcparse::AtomKind;
+use mcparse::compiled_lexer::CompiledLexer;
+use mcparse::define_language;
+use mcparse::lexer::lex;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+define_language! {
+    struct ConfigLang;
+    atoms = [
+        atom Whitespace = regex r"\s+",
+        mcparse::atoms::RegexAtom::new(AtomKind::Other("Comment".into()), r"#[^\n]*").opaque(),
+        atom String = opaque regex r#""([^"\\]|\\.)*""#,
+        atom Number = regex r"-?\d+(\.\d+)?([eE][+-]?\d+)?",
+        keywords [ "true", "false", "null", "include", "section", "import", "export", "default" ],
+        atom Identifier = regex r"[a-zA-Z_][a-zA-Z0-9_\-]*",
+        atom Operator = "==",
+        atom Operator = "!=",
+        atom Operator = "=>",
+        atom Operator = "=",
+        atom Operator = ":",
+        atom Operator = ",",
+        atom Operator = ".",
+        atom Operator = "+",
+        atom Operator = "-",
+        atom Operator = "*",
+        atom Operator = "/",
+    ];
+    delimiters = [
+        delimiter "brace" = "{", "}",
+        delimiter "bracket" = "[", "]",
+        delimiter "paren" = "(", ")",
+    ];
+}
+
+/// Generates roughly `size` bytes of config text.
+fn generate(size: usize) -> String {
+    let mut out = String::with_capacity(size + 256);
+    let mut i = 0;
+    while out.len() < size {
+        out.push_str(&format!(
+            "# entry {i}\nsection item_{i} {{\n    name = \"service-{i}\"\n    port = {}\n    \
+             ratio = {}.5e-3\n    enabled = {}\n    tags = [\"a\", \"b\", default]\n    \
+             route = upstream.host_{i} => (weight * 2 + 1)\n}}\n",
+            8000 + i % 1000,
+            i % 97,
+            i % 2 == 0,
+        ));
+        i += 1;
+    }
+    out
+}
+
+fn time(label: &str, iterations: u32, mut f: impl FnMut() -> usize) -> Duration {
+    f(); // warm up
+    let start = Instant::now();
+    for _ in 0..iterations {
+        black_box(f());
+    }
+    let per_iteration = start.elapsed() / iterations;
+    println!("{label:>10}: {per_iteration:?} per iteration");
+    per_iteration
+}
+
+fn main() {
+    let language = ConfigLang::new();
+    let input = generate(2 * 1024 * 1024);
+    println!("lexing {} bytes", input.len());
+
+    let plain = time("plain", 3, || lex(&input, &language).len());
+
+    let compiled_lexer = CompiledLexer::new(&language);
+    let compiled = time("compiled", 3, || compiled_lexer.lex(&input).len());
+
+    assert_eq!(
+        format!("{:?}", lex(&input, &language)),
+        format!("{:?}", compiled_lexer.lex(&input)),
+        "the compiled lexer must produce the same trees"
+    );
+
+    println!(
+        "   speedup: {:.2}x",
+        plain.as_secs_f64() / compiled.as_secs_f64()
+    );
+}
@@ -0,0 +1,127 @@
+//! Compares a backtracking grammar with and without `memo` on deeply nested input.
+//!
+//! Run with `cargo bench --bench memo`.
+
+use mcparse::define_language;
+use mcparse::language::Delimiter;
+use mcparse::lexer::lex;
+use mcparse::shape::{MatchContext, MatchResult, Shape, enter, memo, term, tuple};
+use mcparse::token::TokenStream;
+use mcparse::{AtomKind, Parser};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+define_language! {
+    struct ArithLang;
+    atoms = [
+        atom Whitespace = regex r"\s+",
+        atom Number = regex r"\d+",
+        atom Operator = "+",
+        atom Operator = "-",
+    ];
+    delimiters = [
+        delimiter "paren" = "(", ")",
+    ];
+}
+
+/// `expr = atom "+" expr | atom "-" expr | atom`: every alternative re-matches
+/// `atom`, so each level of nesting triples the work unless `atom` is memoized.
+#[derive(Debug, Clone, Copy)]
+struct Expr<const MEMO: bool>;
+
+/// `atom = "(" expr ")" | number`
+#[derive(Debug, Clone, Copy)]
+struct Atom<const MEMO: bool>;
+
+fn atom<const MEMO: bool>() -> Box<dyn Shape> {
+    if MEMO {
+        Box::new(memo(Atom::<MEMO>))
+    } else {
+        Box::new(Atom::<MEMO>)
+    }
+}
+
+impl<const MEMO: bool> Shape for Expr<MEMO> {
+    fn match_shape<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+    ) -> MatchResult<'a> {
+        tuple::choice((
+            tuple::seq((atom::<MEMO>(), term("+"), Expr::<MEMO>)),
+            tuple::seq((atom::<MEMO>(), term("-"), Expr::<MEMO>)),
+            atom::<MEMO>(),
+        ))
+        .match_shape(stream, context)
+    }
+}
+
+impl<const MEMO: bool> Shape for Atom<MEMO> {
+    fn match_shape<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+    ) -> MatchResult<'a> {
+        tuple::choice((
+            enter(Delimiter::new("paren", "(", ")"), Expr::<MEMO>),
+            term(AtomKind::Number),
+        ))
+        .match_shape(stream, context)
+    }
+}
+
+const DEPTH: usize = 12;
+
+/// `((1) - 1) - 1 ...`, nested `depth` times.
+fn generate(depth: usize) -> String {
+    let mut out = "1".to_string();
+    for _ in 0..depth {
+        out = format!("({out}) - 1");
+    }
+    out
+}
+
+fn parse<S: Shape>(shape: &S, input: &str, language: &ArithLang) -> String {
+    let trees = lex(input, language);
+    let mut parser = Parser::new(TokenStream::new(&trees), language);
+    let (tree, rest) = shape
+        .match_shape(TokenStream::new(&trees), &mut parser)
+        .expect("the input should parse");
+    assert!(rest.is_empty());
+    tree.to_sexp()
+}
+
+fn time(label: &str, iterations: u32, mut f: impl FnMut() -> usize) -> Duration {
+    f(); // warm up
+    let start = Instant::now();
+    for _ in 0..iterations {
+        black_box(f());
+    }
+    let per_iteration = start.elapsed() / iterations;
+    println!("{label:>10}: {per_iteration:?} per iteration");
+    per_iteration
+}
+
+fn main() {
+    let language = ArithLang::new();
+    let input = generate(DEPTH);
+    println!("parsing {DEPTH} levels of nesting");
+
+    let plain = time("plain", 3, || {
+        parse(&Expr::<false>, &input, &language).len()
+    });
+    let memoized = time("memo", 100, || {
+        parse(&Expr::<true>, &input, &language).len()
+    });
+
+    assert_eq!(
+        parse(&Expr::<false>, &input, &language),
+        parse(&Expr::<true>, &input, &language),
+        "memoizing must not change the result"
+    );
+
+    println!(
+        "   speedup: {:.2}x",
+        plain.as_secs_f64() / memoized.as_secs_f64()
+    );
+}
@@ -3,7 +3,11 @@ use mcparse::{
     define_language,
     language::Delimiter,
     lexer::lex,
-    shape::{MatchContext, MatchResult, Shape, choice, enter, opt, separated, seq, term},
+    lint::{assert_lint_clean, lint_shape},
+    shape::{
+        MatchContext, MatchResult, Shape, Structure, enter, opt, separated, term,
+        tuple::{choice, seq},
+    },
     token::TokenStream,
 };
 
@@ -15,7 +19,7 @@ define_language! {
         atom Whitespace = regex r"\s+",
         atom Operator = ":",
         atom Operator = ",",
-        atom String = regex r#""([^"\\]|\\.)*""#,
+        atom String = opaque regex r#""([^"\\]|\\.)*""#,
         atom Number = regex r"\d+",
     ];
     delimiters = [
@@ -29,42 +33,45 @@ define_language! {
 #[derive(Clone, Copy, Debug)]
 struct JsonValue;
 
-impl Shape for JsonValue {
-    fn match_shape<'a>(
-        &self,
-        stream: TokenStream<'a>,
-        context: &mut dyn MatchContext,
-    ) -> MatchResult<'a> {
+impl JsonValue {
+    fn body(&self) -> impl Shape + use<> {
         let string = term(AtomKind::String);
         let number = term(AtomKind::Number);
 
-        let pair = seq(term(AtomKind::String), seq(term(":"), JsonValue));
+        let pair = seq((term(AtomKind::String), term(":"), JsonValue));
         let object = enter(
-            Delimiter {
-                kind: "brace",
-                open: "{",
-                close: "}",
-            },
+            Delimiter::new("brace", "{", "}"),
             opt(separated(pair, term(","))),
         );
 
         let array = enter(
-            Delimiter {
-                kind: "bracket",
-                open: "[",
-                close: "]",
-            },
+            Delimiter::new("bracket", "[", "]"),
             opt(separated(JsonValue, term(","))),
         );
 
-        let shape = choice(string, choice(number, choice(object, array)));
+        choice((string, number, object, array))
+    }
+}
+
+impl Shape for JsonValue {
+    fn match_shape<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+    ) -> MatchResult<'a> {
+        self.body().match_shape(stream, context)
+    }
 
-        shape.match_shape(stream, context)
+    // Lets the grammar lints follow the recursion.
+    fn structure(&self) -> Structure<'_> {
+        Structure::Rule("JsonValue", Box::new(self.body()))
     }
 }
 
 fn main() {
     let lang = JsonPlusLang::new();
+    assert_lint_clean(&lang);
+    assert!(lint_shape(&JsonValue, "JsonValue").is_empty());
     let input = r#"{ "key": "value", "list": [ 1, 2, 3 ] }"#;
 
     println!("Input: {}", input);
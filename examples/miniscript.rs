@@ -1,5 +1,6 @@
 use mcparse::{
     atom::{Atom, AtomKind},
+    atoms::{IdentifierAtom, NumberAtom, RegexAtom},
     highlighter::{HighlightStyle, Highlighter},
     language::{Delimiter, Language},
     lexer::lex,
@@ -10,82 +11,6 @@ use mcparse::{
 
 // --- Atoms ---
 
-#[derive(Debug)]
-struct Whitespace;
-impl Atom for Whitespace {
-    fn kind(&self) -> AtomKind {
-        AtomKind::Whitespace
-    }
-    fn parse<'a>(&self, input: Cursor<'a>) -> Option<(Token, Cursor<'a>)> {
-        let mut len = 0;
-        for c in input.rest.chars() {
-            if c.is_whitespace() {
-                len += c.len_utf8();
-            } else {
-                break;
-            }
-        }
-        if len > 0 {
-            Some((
-                Token {
-                    kind: AtomKind::Whitespace,
-                    text: input.rest[..len].to_string(),
-                    location: SourceLocation {
-                        span: (input.offset, len).into(),
-                    },
-                    atom_index: None,
-                    binding: None,
-                },
-                input.advance(len),
-            ))
-        } else {
-            None
-        }
-    }
-    fn highlight(&self, token: &Token, highlighter: &mut dyn Highlighter) {
-        highlighter.highlight(token, HighlightStyle::None);
-    }
-}
-
-#[derive(Debug)]
-struct Identifier;
-impl Atom for Identifier {
-    fn kind(&self) -> AtomKind {
-        AtomKind::Identifier
-    }
-    fn parse<'a>(&self, input: Cursor<'a>) -> Option<(Token, Cursor<'a>)> {
-        let mut chars = input.rest.chars();
-        if let Some(c) = chars.next() {
-            if c.is_alphabetic() || c == '_' {
-                let mut len = c.len_utf8();
-                for c in chars {
-                    if c.is_alphanumeric() || c == '_' {
-                        len += c.len_utf8();
-                    } else {
-                        break;
-                    }
-                }
-                return Some((
-                    Token {
-                        kind: AtomKind::Identifier,
-                        text: input.rest[..len].to_string(),
-                        location: SourceLocation {
-                            span: (input.offset, len).into(),
-                        },
-                        atom_index: None,
-                        binding: None,
-                    },
-                    input.advance(len),
-                ));
-            }
-        }
-        None
-    }
-    fn highlight(&self, token: &Token, highlighter: &mut dyn Highlighter) {
-        highlighter.highlight(token, HighlightStyle::Variable);
-    }
-}
-
 #[derive(Debug)]
 struct Operator(String);
 impl Atom for Operator {
@@ -98,11 +23,12 @@ impl Atom for Operator {
                 Token {
                     kind: AtomKind::Operator,
                     text: self.0.clone(),
-                    location: SourceLocation {
-                        span: (input.offset, self.0.len()).into(),
-                    },
+                    location: SourceLocation::new(input.offset, self.0.len()),
                     atom_index: None,
                     binding: None,
+                    leading_trivia: Vec::new(),
+                    trailing_trivia: Vec::new(),
+                    value: None,
                 },
                 input.advance(self.0.len()),
             ))
@@ -115,43 +41,6 @@ impl Atom for Operator {
     }
 }
 
-#[derive(Debug)]
-struct NumberLiteral;
-impl Atom for NumberLiteral {
-    fn kind(&self) -> AtomKind {
-        AtomKind::Number
-    }
-    fn parse<'a>(&self, input: Cursor<'a>) -> Option<(Token, Cursor<'a>)> {
-        let mut len = 0;
-        for c in input.rest.chars() {
-            if c.is_ascii_digit() {
-                len += c.len_utf8();
-            } else {
-                break;
-            }
-        }
-        if len > 0 {
-            Some((
-                Token {
-                    kind: AtomKind::Number,
-                    text: input.rest[..len].to_string(),
-                    location: SourceLocation {
-                        span: (input.offset, len).into(),
-                    },
-                    atom_index: None,
-                    binding: None,
-                },
-                input.advance(len),
-            ))
-        } else {
-            None
-        }
-    }
-    fn highlight(&self, token: &Token, highlighter: &mut dyn Highlighter) {
-        highlighter.highlight(token, HighlightStyle::Number);
-    }
-}
-
 // --- Matchers ---
 
 #[derive(Debug, Clone)]
@@ -217,23 +106,15 @@ impl MiniScriptLang {
     fn new() -> Self {
         Self {
             atoms: vec![
-                Box::new(Whitespace),
+                Box::new(RegexAtom::new(AtomKind::Whitespace, r"\s+")),
                 Box::new(Operator("=".into())),
                 Box::new(Operator(";".into())),
-                Box::new(Identifier),
-                Box::new(NumberLiteral),
+                Box::new(IdentifierAtom),
+                Box::new(NumberAtom::new()),
             ],
             delimiters: vec![
-                Delimiter {
-                    kind: "brace",
-                    open: "{",
-                    close: "}",
-                },
-                Delimiter {
-                    kind: "paren",
-                    open: "(",
-                    close: ")",
-                },
+                Delimiter::new("brace", "{", "}"),
+                Delimiter::new("paren", "(", ")"),
             ],
             macros: vec![Box::new(LetMacro::new())],
         }
@@ -285,7 +166,7 @@ fn main() {
     match signature.match_shape(args_stream, &mut context) {
         Ok((args, _)) => {
             println!("Matched Args: {:?}", args);
-            let context = MacroContext;
+            let context = MacroContext::default();
             match let_macro.expand(args, None, &context) {
                 ExpansionResult::Ok(expanded) => println!("Expanded: {:?}", expanded),
                 ExpansionResult::Error(e) => println!("Expansion Error: {}", e),
@@ -1,6 +1,7 @@
 use mcparse::{
     atom::{Atom, AtomKind},
-    define_atom, define_language,
+    atoms::{IdentifierAtom, NumberAtom},
+    define_language,
     highlighter::{HighlightStyle, Highlighter},
     language::Delimiter,
     lexer::lex,
@@ -12,40 +13,6 @@ use thiserror::Error;
 
 // --- Language Definition (Miniscript) ---
 
-define_atom! {
-    struct Whitespace;
-    kind = AtomKind::Whitespace;
-    parse(input) {
-        let mut len = 0;
-        for c in input.rest.chars() {
-            if c.is_whitespace() {
-                len += c.len_utf8();
-            } else {
-                break;
-            }
-        }
-        if len > 0 {
-            Some((
-                Token {
-                    kind: AtomKind::Whitespace,
-                    text: input.rest[..len].to_string(),
-                    location: SourceLocation {
-                        span: (input.offset, len).into(),
-                    },
-                    atom_index: None,
-                    binding: None,
-                },
-                input.advance(len),
-            ))
-        } else {
-            None
-        }
-    }
-    highlight(token, highlighter) {
-        highlighter.highlight(token, HighlightStyle::None);
-    }
-}
-
 #[derive(Debug)]
 struct Punctuation(String);
 impl Atom for Punctuation {
@@ -58,11 +25,12 @@ impl Atom for Punctuation {
                 Token {
                     kind: AtomKind::Operator,
                     text: self.0.clone(),
-                    location: SourceLocation {
-                        span: (input.offset, self.0.len()).into(),
-                    },
+                    location: SourceLocation::new(input.offset, self.0.len()),
                     atom_index: None,
                     binding: None,
+                    leading_trivia: Vec::new(),
+                    trailing_trivia: Vec::new(),
+                    value: None,
                 },
                 input.advance(self.0.len()),
             ))
@@ -75,100 +43,22 @@ impl Atom for Punctuation {
     }
 }
 
-define_atom! {
-    struct Identifier;
-    kind = AtomKind::Identifier;
-    parse(input) {
-        let mut chars = input.rest.chars();
-        if let Some(c) = chars.next() {
-            if c.is_alphabetic() || c == '_' {
-                let mut len = c.len_utf8();
-                for c in chars {
-                    if c.is_alphanumeric() || c == '_' {
-                        len += c.len_utf8();
-                    } else {
-                        break;
-                    }
-                }
-                return Some((
-                    Token {
-                        kind: AtomKind::Identifier,
-                        text: input.rest[..len].to_string(),
-                        location: SourceLocation {
-                            span: (input.offset, len).into(),
-                        },
-                        atom_index: None,
-                        binding: None,
-                    },
-                    input.advance(len),
-                ));
-            }
-        }
-        None
-    }
-    highlight(token, highlighter) {
-        highlighter.highlight(token, HighlightStyle::Variable);
-    }
-}
-
-define_atom! {
-    struct NumberLiteral;
-    kind = AtomKind::Number;
-    parse(input) {
-        let mut len = 0;
-        for c in input.rest.chars() {
-            if c.is_ascii_digit() {
-                len += c.len_utf8();
-            } else {
-                break;
-            }
-        }
-        if len > 0 {
-            Some((
-                Token {
-                    kind: AtomKind::Number,
-                    text: input.rest[..len].to_string(),
-                    location: SourceLocation {
-                        span: (input.offset, len).into(),
-                    },
-                    atom_index: None,
-                    binding: None,
-                },
-                input.advance(len),
-            ))
-        } else {
-            None
-        }
-    }
-    highlight(token, highlighter) {
-        highlighter.highlight(token, HighlightStyle::Number);
-    }
-}
-
 define_language! {
     struct MiniScriptLang;
     atoms = [
-        Whitespace,
+        atom Whitespace = regex r"\s+",
         Punctuation("=".into()),
         Punctuation(";".into()),
         Punctuation("(".into()),
         Punctuation(")".into()),
         Punctuation("{".into()),
         Punctuation("}".into()),
-        Identifier,
-        NumberLiteral
+        IdentifierAtom,
+        NumberAtom::new()
     ];
     delimiters = [
-        Delimiter {
-            kind: "brace",
-            open: "{",
-            close: "}",
-        },
-        Delimiter {
-            kind: "paren",
-            open: "(",
-            close: ")",
-        },
+        Delimiter::new("brace", "{", "}"),
+        Delimiter::new("paren", "(", ")"),
     ];
 }
 
@@ -267,17 +157,16 @@ fn report_errors(input: &str, lang: &impl mcparse::language::Language) -> miette
 
     // 1. Check for Lexer Errors (Unknown tokens)
     for tree in &trees {
-        if let TokenTree::Token(token) = tree {
-            if let AtomKind::Other(ref s) = token.kind {
-                if s == "Unknown" {
-                    return Err(ParseError {
-                        src: NamedSource::new("input", input.to_string()),
-                        span: token.location.span,
-                        advice: format!("Unexpected character: '{}'", token.text),
-                    }
-                    .into());
-                }
+        if let TokenTree::Token(token) = tree
+            && let AtomKind::Other(ref s) = token.kind
+            && s == "Unknown"
+        {
+            return Err(ParseError {
+                src: NamedSource::new("input", input.to_string()),
+                span: token.location.span,
+                advice: format!("Unexpected character: '{}'", token.text),
             }
+            .into());
         }
     }
 
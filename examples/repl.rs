@@ -5,7 +5,8 @@ use crossterm::{
 };
 use mcparse::{
     atom::{Atom, AtomKind},
-    define_atom, define_language,
+    atoms::{IdentifierAtom, NumberAtom},
+    define_language,
     highlighter::{HighlightStyle, Highlighter},
     language::{Delimiter, Language},
     lexer::lex,
@@ -89,40 +90,6 @@ impl Highlighter for RatatuiHighlighter {
 
 // --- Language Definition (Miniscript) ---
 
-define_atom! {
-    struct Whitespace;
-    kind = AtomKind::Whitespace;
-    parse(input) {
-        let mut len = 0;
-        for c in input.rest.chars() {
-            if c.is_whitespace() {
-                len += c.len_utf8();
-            } else {
-                break;
-            }
-        }
-        if len > 0 {
-            Some((
-                Token {
-                    kind: AtomKind::Whitespace,
-                    text: input.rest[..len].to_string(),
-                    location: SourceLocation {
-                        span: (input.offset, len).into(),
-                    },
-                    atom_index: None,
-                    binding: None,
-                },
-                input.advance(len),
-            ))
-        } else {
-            None
-        }
-    }
-    highlight(token, highlighter) {
-        highlighter.highlight(token, HighlightStyle::None);
-    }
-}
-
 #[derive(Debug)]
 struct Punctuation(String);
 impl Atom for Punctuation {
@@ -135,11 +102,12 @@ impl Atom for Punctuation {
                 Token {
                     kind: AtomKind::Operator,
                     text: self.0.clone(),
-                    location: SourceLocation {
-                        span: (input.offset, self.0.len()).into(),
-                    },
+                    location: SourceLocation::new(input.offset, self.0.len()),
                     atom_index: None,
                     binding: None,
+                    leading_trivia: Vec::new(),
+                    trailing_trivia: Vec::new(),
+                    value: None,
                 },
                 input.advance(self.0.len()),
             ))
@@ -152,100 +120,22 @@ impl Atom for Punctuation {
     }
 }
 
-define_atom! {
-    struct Identifier;
-    kind = AtomKind::Identifier;
-    parse(input) {
-        let mut chars = input.rest.chars();
-        if let Some(c) = chars.next() {
-            if c.is_alphabetic() || c == '_' {
-                let mut len = c.len_utf8();
-                for c in chars {
-                    if c.is_alphanumeric() || c == '_' {
-                        len += c.len_utf8();
-                    } else {
-                        break;
-                    }
-                }
-                return Some((
-                    Token {
-                        kind: AtomKind::Identifier,
-                        text: input.rest[..len].to_string(),
-                        location: SourceLocation {
-                            span: (input.offset, len).into(),
-                        },
-                        atom_index: None,
-                        binding: None,
-                    },
-                    input.advance(len),
-                ));
-            }
-        }
-        None
-    }
-    highlight(token, highlighter) {
-        highlighter.highlight(token, HighlightStyle::Variable);
-    }
-}
-
-define_atom! {
-    struct NumberLiteral;
-    kind = AtomKind::Number;
-    parse(input) {
-        let mut len = 0;
-        for c in input.rest.chars() {
-            if c.is_ascii_digit() {
-                len += c.len_utf8();
-            } else {
-                break;
-            }
-        }
-        if len > 0 {
-            Some((
-                Token {
-                    kind: AtomKind::Number,
-                    text: input.rest[..len].to_string(),
-                    location: SourceLocation {
-                        span: (input.offset, len).into(),
-                    },
-                    atom_index: None,
-                    binding: None,
-                },
-                input.advance(len),
-            ))
-        } else {
-            None
-        }
-    }
-    highlight(token, highlighter) {
-        highlighter.highlight(token, HighlightStyle::Number);
-    }
-}
-
 define_language! {
     struct MiniScriptLang;
     atoms = [
-        Whitespace,
+        atom Whitespace = regex r"\s+",
         Punctuation("=".into()),
         Punctuation(";".into()),
         Punctuation("(".into()),
         Punctuation(")".into()),
         Punctuation("{".into()),
         Punctuation("}".into()),
-        Identifier,
-        NumberLiteral
+        IdentifierAtom,
+        NumberAtom::new()
     ];
     delimiters = [
-        Delimiter {
-            kind: "brace",
-            open: "{",
-            close: "}",
-        },
-        Delimiter {
-            kind: "paren",
-            open: "(",
-            close: ")",
-        },
+        Delimiter::new("brace", "{", "}"),
+        Delimiter::new("paren", "(", ")"),
     ];
     binding_pass = simple("let");
     reference_pass = simple;
@@ -335,24 +225,18 @@ impl App {
                 self.cursor_pos += 1;
                 self.update_completions();
             }
-            KeyCode::Backspace => {
-                if self.cursor_pos > 0 {
-                    self.input.remove(self.cursor_pos - 1);
-                    self.cursor_pos -= 1;
-                    self.update_completions();
-                }
+            KeyCode::Backspace if self.cursor_pos > 0 => {
+                self.input.remove(self.cursor_pos - 1);
+                self.cursor_pos -= 1;
+                self.update_completions();
             }
-            KeyCode::Left => {
-                if self.cursor_pos > 0 {
-                    self.cursor_pos -= 1;
-                    self.update_completions();
-                }
+            KeyCode::Left if self.cursor_pos > 0 => {
+                self.cursor_pos -= 1;
+                self.update_completions();
             }
-            KeyCode::Right => {
-                if self.cursor_pos < self.input.len() {
-                    self.cursor_pos += 1;
-                    self.update_completions();
-                }
+            KeyCode::Right if self.cursor_pos < self.input.len() => {
+                self.cursor_pos += 1;
+                self.update_completions();
             }
             KeyCode::Tab => {
                 if !self.show_completions {
@@ -375,10 +259,10 @@ impl App {
             }
             KeyCode::Enter => {
                 if self.show_completions {
-                    if let Some(i) = self.completion_state.selected() {
-                        if let Some(item) = self.completions.get(i).cloned() {
-                            self.apply_completion(&item);
-                        }
+                    if let Some(i) = self.completion_state.selected()
+                        && let Some(item) = self.completions.get(i).cloned()
+                    {
+                        self.apply_completion(&item);
                     }
                     self.show_completions = false;
                 } else {
@@ -447,15 +331,15 @@ fn main() -> Result<(), Box<dyn Error>> {
     loop {
         terminal.draw(|f| ui(f, &mut app))?;
 
-        if let Event::Key(key) = event::read()? {
-            if key.kind == KeyEventKind::Press {
-                if key.code == KeyCode::Char('c')
-                    && key.modifiers.contains(event::KeyModifiers::CONTROL)
-                {
-                    break;
-                }
-                app.on_key(key.code);
+        if let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+        {
+            if key.code == KeyCode::Char('c')
+                && key.modifiers.contains(event::KeyModifiers::CONTROL)
+            {
+                break;
             }
+            app.on_key(key.code);
         }
     }
 
@@ -492,11 +376,11 @@ fn ui(f: &mut Frame, app: &mut App) {
         match tree {
             TokenTree::Token(t) => {
                 // Use the atom index to find the correct atom for highlighting
-                if let Some(index) = t.atom_index {
-                    if let Some(atom) = lang.atoms().get(index) {
-                        atom.highlight(t, highlighter);
-                        return;
-                    }
+                if let Some(index) = t.atom_index
+                    && let Some(atom) = lang.atom(index)
+                {
+                    atom.highlight(t, highlighter);
+                    return;
                 }
 
                 // Fallback if index is missing or invalid
@@ -508,11 +392,12 @@ fn ui(f: &mut Frame, app: &mut App) {
                     &Token {
                         kind: AtomKind::Operator,
                         text: d.open.to_string(),
-                        location: SourceLocation {
-                            span: (0, 0).into(),
-                        }, // Dummy location
+                        location: SourceLocation::new(0, 0), // Dummy location
                         atom_index: None,
                         binding: None,
+                        leading_trivia: Vec::new(),
+                        trailing_trivia: Vec::new(),
+                        value: None,
                     },
                     HighlightStyle::Punctuation,
                 );
@@ -526,11 +411,12 @@ fn ui(f: &mut Frame, app: &mut App) {
                     &Token {
                         kind: AtomKind::Operator,
                         text: d.close.to_string(),
-                        location: SourceLocation {
-                            span: (0, 0).into(),
-                        }, // Dummy location
+                        location: SourceLocation::new(0, 0), // Dummy location
                         atom_index: None,
                         binding: None,
+                        leading_trivia: Vec::new(),
+                        trailing_trivia: Vec::new(),
+                        value: None,
                     },
                     HighlightStyle::Punctuation,
                 );
@@ -540,7 +426,13 @@ fn ui(f: &mut Frame, app: &mut App) {
                     highlight_tree(child, highlighter, lang);
                 }
             }
-            TokenTree::Error(_msg) => {
+            TokenTree::Node(node) => {
+                for child in &node.children {
+                    highlight_tree(child, highlighter, lang);
+                }
+            }
+            TokenTree::Field(_, value) => highlight_tree(value, highlighter, lang),
+            TokenTree::Error(_msg, _) => {
                 // How to represent error text? The error token doesn't carry the text it skipped easily unless we change TokenTree::Error
                 // But wait, lexer now produces Unknown tokens for skipped text!
                 // So TokenTree::Error might not contain text we want to display.
@@ -595,20 +487,18 @@ fn ui(f: &mut Frame, app: &mut App) {
     fn find_token_at(trees: &[TokenTree], pos: usize) -> Option<&Token> {
         for tree in trees {
             match tree {
-                TokenTree::Token(t) => {
-                    if t.location.contains(pos) {
-                        return Some(t);
-                    }
+                TokenTree::Token(t) if t.location.contains(pos) => {
+                    return Some(t);
                 }
                 TokenTree::Delimited(_, children, loc, _) => {
-                    if loc.contains(pos) {
-                        if let Some(found) = find_token_at(children, pos) {
-                            return Some(found);
-                        }
-                        // If not in children, maybe on delimiters?
-                        // Delimiter tokens are not stored explicitly with location in TokenTree::Delimited
-                        // But the group location covers them.
+                    if loc.contains(pos)
+                        && let Some(found) = find_token_at(children, pos)
+                    {
+                        return Some(found);
                     }
+                    // If not in children, maybe on delimiters?
+                    // Delimiter tokens are not stored explicitly with location in TokenTree::Delimited
+                    // But the group location covers them.
                 }
                 TokenTree::Group(children) => {
                     if let Some(found) = find_token_at(children, pos) {
@@ -40,7 +40,17 @@ fn print_tree(tree: &TokenTree, indent: usize) {
                 print_tree(child, indent + 1);
             }
         }
-        TokenTree::Error(msg) => println!("{}Error: {}", pad, msg),
+        TokenTree::Node(node) => {
+            println!("{}Node({})", pad, node.kind);
+            for child in &node.children {
+                print_tree(child, indent + 1);
+            }
+        }
+        TokenTree::Field(name, value) => {
+            println!("{}Field({})", pad, name);
+            print_tree(value, indent + 1);
+        }
+        TokenTree::Error(msg, _) => println!("{}Error: {}", pad, msg),
         TokenTree::Empty => println!("{}Empty", pad),
     }
 }
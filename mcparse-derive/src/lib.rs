@@ -0,0 +1,212 @@
+//! `#[derive(Shape)]` for typed syntax trees.
+//!
+//! The derive implements `mcparse::ast::Ast` and `mcparse::ast::FromTree`;
+//! see the `mcparse::ast` module for the attributes it understands.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    Attribute, Data, DeriveInput, Error, Fields, GenericArgument, LitStr, PathArguments, Type,
+    parse_macro_input,
+};
+
+#[proc_macro_derive(Shape, attributes(shape, delimited, recover))]
+pub fn derive_shape(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let (shape, from_tree) = match &input.data {
+        Data::Struct(data) => {
+            let kind = name.to_string();
+            let shape = node_shape(&kind, &input.attrs, &data.fields)?;
+            let build = build(quote!(#name), &data.fields);
+            let node = match data.fields {
+                Fields::Unit => quote!(_),
+                _ => quote!(node),
+            };
+            let from_tree = quote! {
+                let #node = ::mcparse::ast::syntax_node(tree, &[#kind])?;
+                ::std::result::Result::Ok(#build)
+            };
+            (shape, from_tree)
+        }
+        Data::Enum(data) => {
+            if data.variants.is_empty() {
+                return Err(Error::new_spanned(
+                    name,
+                    "#[derive(Shape)] needs at least one variant",
+                ));
+            }
+            let mut shapes = Vec::new();
+            let mut kinds = Vec::new();
+            let mut arms = Vec::new();
+            for variant in &data.variants {
+                let ident = &variant.ident;
+                let kind = ident.to_string();
+                shapes.push(node_shape(&kind, &variant.attrs, &variant.fields)?);
+                let build = build(quote!(#name::#ident), &variant.fields);
+                arms.push(quote!(#kind => ::std::result::Result::Ok(#build),));
+                kinds.push(kind);
+            }
+            // choice(a, choice(b, c))
+            let last = shapes.pop().unwrap();
+            let shape = shapes.into_iter().rev().fold(
+                last,
+                |rest, shape| quote!(::mcparse::shape::choice(#shape, #rest)),
+            );
+            let from_tree = quote! {
+                let node = ::mcparse::ast::syntax_node(tree, &[#(#kinds),*])?;
+                match node.kind.as_str() {
+                    #(#arms)*
+                    _ => unreachable!(),
+                }
+            };
+            (shape, from_tree)
+        }
+        Data::Union(_) => {
+            return Err(Error::new_spanned(
+                name,
+                "#[derive(Shape)] is not supported for unions",
+            ));
+        }
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::mcparse::ast::FromTree for #name #ty_generics #where_clause {
+            fn from_tree(
+                tree: &::mcparse::token::TokenTree,
+            ) -> ::std::result::Result<Self, ::mcparse::shape::ParseError> {
+                #from_tree
+            }
+        }
+
+        impl #impl_generics ::mcparse::ast::Ast for #name #ty_generics #where_clause {
+            fn shape() -> ::std::boxed::Box<dyn ::mcparse::shape::Shape> {
+                ::std::boxed::Box::new(#shape)
+            }
+        }
+    })
+}
+
+/// `node(kind, seq(field(..), ..))`, entered if the item is `#[delimited]`.
+fn node_shape(kind: &str, attrs: &[Attribute], fields: &Fields) -> syn::Result<TokenStream2> {
+    let mut shapes = Vec::new();
+    for (index, field) in fields.iter().enumerate() {
+        let name = match &field.ident {
+            Some(ident) => ident.to_string(),
+            None => index.to_string(),
+        };
+        let shape = field_shape(&field.attrs, &field.ty)?;
+        shapes.push(quote!(::mcparse::shape::field(#name, #shape)));
+    }
+
+    // seq(a, seq(b, c)); a unit struct or variant matches its `#[shape]`.
+    let body = match shapes.pop() {
+        Some(last) => shapes.into_iter().rev().fold(
+            last,
+            |rest, shape| quote!(::mcparse::shape::seq(#shape, #rest)),
+        ),
+        None => match shape_attr(attrs)? {
+            Some(shape) => shape,
+            None => quote!(::mcparse::shape::empty()),
+        },
+    };
+    let body = delimited(attrs, body)?;
+    Ok(quote!(::mcparse::shape::node(#kind, #body)))
+}
+
+fn field_shape(attrs: &[Attribute], ty: &Type) -> syn::Result<TokenStream2> {
+    let recover = attrs.iter().find(|attr| attr.path().is_ident("recover"));
+    let shape = match (shape_attr(attrs)?, recover) {
+        (Some(shape), _) => shape,
+        (None, Some(attr)) => {
+            let Some(inner) = recovered_type(ty) else {
+                return Err(Error::new_spanned(
+                    attr,
+                    "#[recover] needs a field of type `Option<T>` or `Result<T, ParseError>`",
+                ));
+            };
+            quote!(::mcparse::ast::ast::<#inner>())
+        }
+        (None, None) => quote!(::mcparse::ast::ast::<#ty>()),
+    };
+    let shape = delimited(attrs, shape)?;
+    match recover {
+        Some(attr) => {
+            let terminator = &attr.meta.require_list()?.tokens;
+            Ok(quote!(::mcparse::shape::recover(#shape, #terminator)))
+        }
+        None => Ok(shape),
+    }
+}
+
+fn shape_attr(attrs: &[Attribute]) -> syn::Result<Option<TokenStream2>> {
+    match attrs.iter().find(|attr| attr.path().is_ident("shape")) {
+        Some(attr) => {
+            let tokens = &attr.meta.require_list()?.tokens;
+            Ok(Some(quote!((#tokens))))
+        }
+        None => Ok(None),
+    }
+}
+
+fn delimited(attrs: &[Attribute], shape: TokenStream2) -> syn::Result<TokenStream2> {
+    match attrs.iter().find(|attr| attr.path().is_ident("delimited")) {
+        // `enter` only compares the delimiter's kind.
+        Some(attr) => {
+            let kind: LitStr = attr.parse_args()?;
+            Ok(quote! {
+                ::mcparse::shape::enter(::mcparse::language::Delimiter::new(#kind, "", ""), #shape)
+            })
+        }
+        None => Ok(shape),
+    }
+}
+
+/// The `T` of an `Option<T>` or `Result<T, E>` field.
+fn recovered_type(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    if segment.ident != "Option" && segment.ident != "Result" {
+        return None;
+    }
+    let PathArguments::AngleBracketed(arguments) = &segment.arguments else {
+        return None;
+    };
+    match arguments.args.first()? {
+        GenericArgument::Type(inner) => Some(inner),
+        _ => None,
+    }
+}
+
+/// `Name { a: field_value(node, "a")?, .. }`, `Name(field_value(node, "0")?, ..)` or `Name`.
+fn build(path: TokenStream2, fields: &Fields) -> TokenStream2 {
+    match fields {
+        Fields::Named(fields) => {
+            let values = fields.named.iter().map(|field| {
+                let ident = field.ident.as_ref().unwrap();
+                let name = ident.to_string();
+                quote!(#ident: ::mcparse::ast::field_value(node, #name)?)
+            });
+            quote!(#path { #(#values),* })
+        }
+        Fields::Unnamed(fields) => {
+            let values = (0..fields.unnamed.len()).map(|index| {
+                let name = index.to_string();
+                quote!(::mcparse::ast::field_value(node, #name)?)
+            });
+            quote!(#path(#(#values),*))
+        }
+        Fields::Unit => quote!(#path),
+    }
+}
@@ -0,0 +1,383 @@
+//! Typed syntax trees.
+//!
+//! An [`Ast`] type knows its shape and how to build itself from the tree that
+//! shape matched, so a grammar can produce Rust structs and enums directly
+//! instead of `TokenTree::Group`s. The usual way to get one is
+//! `#[derive(Shape)]`:
+//!
+//! ```rust
+//! use mcparse::ast::Ast;
+//! use mcparse::atom::AtomKind;
+//! use mcparse::shape::{NoOpMatchContext, term};
+//! use mcparse::token::{Token, TokenStream};
+//! use mcparse::{Shape, define_language, lexer::lex};
+//!
+//! #[derive(Debug, Shape)]
+//! struct Let {
+//!     #[shape(term("let"))]
+//!     _let: (),
+//!     #[shape(term(AtomKind::Identifier))]
+//!     name: Token,
+//!     #[shape(term("="))]
+//!     _eq: (),
+//!     value: Value,
+//! }
+//!
+//! #[derive(Debug, Shape)]
+//! enum Value {
+//!     Number(#[shape(term(AtomKind::Number))] Token),
+//!     #[delimited("bracket")]
+//!     List(Vec<Value>),
+//! }
+//!
+//! define_language! {
+//!     struct Lets;
+//!     atoms = [
+//!         atom Whitespace = regex r"\s+",
+//!         atom Operator = "=",
+//!         atom Identifier = regex r"[a-z]+",
+//!         atom Number = regex r"[0-9]+",
+//!     ];
+//!     delimiters = [
+//!         delimiter "bracket" = "[", "]",
+//!     ];
+//! }
+//!
+//! let trees = lex("let x = [1 [2]]", &Lets::new());
+//! let (binding, _) = Let::parse(TokenStream::new(&trees), &mut NoOpMatchContext).unwrap();
+//! assert_eq!(binding.name.text, "x");
+//! assert!(matches!(&binding.value, Value::List(items) if items.len() == 2));
+//! ```
+//!
+//! The derive turns a struct into `node("Name", ..)` over a `seq` of its
+//! fields, and an enum into a `choice` of one node per variant. Each field
+//! becomes a [`field`](crate::shape::field) named after it (`"0"`, `"1"`, ..
+//! for tuple fields), and its shape comes from:
+//!
+//! - `#[shape(expr)]`, any shape expression, or else
+//! - the field type's [`Ast::shape`]. `Box<T>`, `Option<T>` (as `opt`) and
+//!   `Vec<T>` (as `rep`) follow `T`.
+//!
+//! `#[delimited("kind")]` on a struct, variant or field wraps its shape in
+//! `enter` for delimiters of that kind. `#[recover(terminator)]` on a field
+//! of type `Option<T>` or `Result<T, ParseError>` wraps `T`'s shape in
+//! `recover`, so a syntax error there becomes `None` or `Err` and parsing
+//! continues at the terminator.
+
+use crate::lexer::tree_span;
+use crate::shape::{
+    CompletionItem, MatchContext, MatchResult, ParseError, Shape, Structure, choice, empty, rep,
+};
+use crate::token::{SyntaxNode, Token, TokenStream, TokenTree};
+use std::fmt::{self, Debug};
+use std::marker::PhantomData;
+
+/// Converts a matched tree into a Rust value.
+pub trait FromTree: Sized {
+    fn from_tree(tree: &TokenTree) -> Result<Self, ParseError>;
+}
+
+/// A Rust type with a grammar: a shape, and a conversion from what it matched.
+pub trait Ast: FromTree + 'static {
+    /// The shape of this type's syntax.
+    fn shape() -> Box<dyn Shape>;
+
+    /// Matches [`Ast::shape`] and converts the result. Conversion errors are
+    /// [semantic](ParseError::semantic).
+    fn parse<'a>(
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+    ) -> Result<(Self, TokenStream<'a>), ParseError> {
+        let (tree, rest) = Self::shape().match_shape(stream, context)?;
+        let value = Self::from_tree(&tree).map_err(ParseError::into_semantic)?;
+        Ok((value, rest))
+    }
+}
+
+/// The shape of an [`Ast`] type, built each time it is matched so that types
+/// can refer to themselves.
+pub struct AstShape<T>(PhantomData<fn() -> T>);
+
+/// Matches the shape of `T`, e.g. `rep(ast::<Statement>())`.
+pub fn ast<T: Ast>() -> AstShape<T> {
+    AstShape(PhantomData)
+}
+
+impl<T> Clone for AstShape<T> {
+    fn clone(&self) -> Self {
+        AstShape(PhantomData)
+    }
+}
+
+impl<T> Debug for AstShape<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ast::<{}>()", std::any::type_name::<T>())
+    }
+}
+
+impl<T: Ast> Shape for AstShape<T> {
+    fn match_shape<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+    ) -> MatchResult<'a> {
+        T::shape().match_shape(stream, context)
+    }
+
+    fn complete<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+        cursor: usize,
+    ) -> Vec<CompletionItem> {
+        T::shape().complete(stream, context, cursor)
+    }
+
+    fn structure(&self) -> Structure<'_> {
+        Structure::Rule(std::any::type_name::<T>(), T::shape())
+    }
+}
+
+/// Returns `tree` as a node of one of `kinds`.
+pub fn syntax_node<'t>(tree: &'t TokenTree, kinds: &[&str]) -> Result<&'t SyntaxNode, ParseError> {
+    match tree {
+        TokenTree::Node(node) if kinds.contains(&node.kind.as_str()) => Ok(node),
+        _ => Err(unexpected(
+            tree,
+            &format!("a `{}` node", kinds.join("` or `")),
+        )),
+    }
+}
+
+/// Converts the field `name` of `node`.
+pub fn field_value<T: FromTree>(node: &SyntaxNode, name: &str) -> Result<T, ParseError> {
+    match node.field(name) {
+        Some(value) => T::from_tree(value),
+        None => Err(ParseError::new(
+            node.location.span,
+            format!("`{}` node has no field `{}`", node.kind, name),
+        )),
+    }
+}
+
+fn unexpected(tree: &TokenTree, expected: &str) -> ParseError {
+    let found = match tree {
+        TokenTree::Token(t) => format!("`{}`", t.text),
+        TokenTree::Delimited(d, _, _, _) => format!("delimiter {}", d.kind),
+        TokenTree::Node(node) => format!("a `{}` node", node.kind),
+        TokenTree::Field(name, _) => format!("field {}", name),
+        TokenTree::Group(_) => "a group".into(),
+        TokenTree::Error(message, _) => format!("an error ({})", message),
+        TokenTree::Empty => "nothing".into(),
+    };
+    ParseError::new(
+        tree_span(tree).unwrap_or_else(|| (0, 0).into()),
+        format!("Expected {}, found {}", expected, found),
+    )
+}
+
+impl FromTree for TokenTree {
+    fn from_tree(tree: &TokenTree) -> Result<Self, ParseError> {
+        Ok(tree.clone())
+    }
+}
+
+impl FromTree for Token {
+    fn from_tree(tree: &TokenTree) -> Result<Self, ParseError> {
+        match tree {
+            TokenTree::Token(t) => Ok(t.clone()),
+            _ => Err(unexpected(tree, "a token")),
+        }
+    }
+}
+
+/// Accepts anything, for punctuation and keywords that carry no data.
+impl FromTree for () {
+    fn from_tree(_tree: &TokenTree) -> Result<Self, ParseError> {
+        Ok(())
+    }
+}
+
+impl<T: FromTree> FromTree for Box<T> {
+    fn from_tree(tree: &TokenTree) -> Result<Self, ParseError> {
+        T::from_tree(tree).map(Box::new)
+    }
+}
+
+impl<T: Ast> Ast for Box<T> {
+    fn shape() -> Box<dyn Shape> {
+        Box::new(ast::<T>())
+    }
+}
+
+/// `None` for an empty match or a recovered error.
+impl<T: FromTree> FromTree for Option<T> {
+    fn from_tree(tree: &TokenTree) -> Result<Self, ParseError> {
+        match tree {
+            TokenTree::Empty | TokenTree::Error(..) => Ok(None),
+            tree => T::from_tree(tree).map(Some),
+        }
+    }
+}
+
+impl<T: Ast> Ast for Option<T> {
+    fn shape() -> Box<dyn Shape> {
+        Box::new(choice(ast::<T>(), empty()))
+    }
+}
+
+/// `Err` for a recovered error, with the message from `recover` and the span
+/// of the input it skipped.
+impl<T: FromTree> FromTree for Result<T, ParseError> {
+    fn from_tree(tree: &TokenTree) -> Result<Self, ParseError> {
+        match tree {
+            TokenTree::Error(message, location) => {
+                Ok(Err(ParseError::new(location.span, message.clone())))
+            }
+            tree => T::from_tree(tree).map(Ok),
+        }
+    }
+}
+
+impl<T: FromTree> FromTree for Vec<T> {
+    fn from_tree(tree: &TokenTree) -> Result<Self, ParseError> {
+        match tree {
+            TokenTree::Group(items) => items
+                .iter()
+                .filter(|item| !matches!(item, TokenTree::Empty))
+                .map(T::from_tree)
+                .collect(),
+            TokenTree::Empty => Ok(Vec::new()),
+            tree => Ok(vec![T::from_tree(tree)?]),
+        }
+    }
+}
+
+impl<T: Ast> Ast for Vec<T> {
+    fn shape() -> Box<dyn Shape> {
+        Box::new(rep(ast::<T>()))
+    }
+}
+
+#[cfg(all(test, feature = "derive"))]
+mod tests {
+    use super::*;
+    use crate::atom::AtomKind;
+    use crate::define_language;
+    use crate::lexer::lex;
+    use crate::lint::{Lint, lint_shape};
+    use crate::shape::{NoOpMatchContext, skip_trivia, term};
+
+    define_language! {
+        struct Exprs;
+        atoms = [
+            atom Whitespace = regex r"\s+",
+            atom Operator = regex r"[-;]",
+            atom Identifier = regex r"[a-z]+",
+            atom Number = regex r"[0-9]+",
+        ];
+        delimiters = [
+            delimiter "paren" = "(", ")",
+        ];
+    }
+
+    #[derive(Debug, crate::Shape)]
+    enum Expr {
+        #[shape(term("true"))]
+        True,
+        Number(#[shape(term(AtomKind::Number))] Token),
+        #[delimited("paren")]
+        Paren(Box<Expr>),
+        Neg {
+            #[shape(term("-"))]
+            _minus: (),
+            operand: Box<Expr>,
+        },
+    }
+
+    #[derive(Debug, crate::Shape)]
+    struct Statement {
+        #[recover(";")]
+        expr: Option<Expr>,
+        #[shape(term(";"))]
+        _semi: (),
+    }
+
+    #[derive(Debug, crate::Shape)]
+    struct Program {
+        statements: Vec<Statement>,
+    }
+
+    fn parse<T: Ast>(input: &str) -> T {
+        let trees = lex(input, &Exprs::new());
+        let (value, rest) = T::parse(TokenStream::new(&trees), &mut NoOpMatchContext).unwrap();
+        let rest = skip_trivia(rest, &NoOpMatchContext);
+        assert!(rest.is_empty(), "unparsed input: {:?}", rest);
+        value
+    }
+
+    fn number(expr: &Expr) -> &str {
+        match expr {
+            Expr::Number(token) => &token.text,
+            other => panic!("expected a number, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_derived_structs_and_enums() {
+        let program: Program = parse("1; -(true); ");
+        let exprs: Vec<_> = program.statements.iter().map(|s| &s.expr).collect();
+        assert_eq!(number(exprs[0].as_ref().unwrap()), "1");
+        let Some(Expr::Neg { operand, .. }) = exprs[1] else {
+            panic!("expected a negation, got {:?}", exprs[1]);
+        };
+        assert!(matches!(&**operand, Expr::Paren(inner) if matches!(**inner, Expr::True)));
+    }
+
+    #[test]
+    fn test_recovered_fields() {
+        let program: Program = parse("1; ) ; 2;");
+        let exprs: Vec<_> = program.statements.iter().map(|s| &s.expr).collect();
+        assert_eq!(exprs.len(), 3);
+        assert!(exprs[1].is_none());
+        assert_eq!(number(exprs[2].as_ref().unwrap()), "2");
+
+        #[derive(Debug, crate::Shape)]
+        struct Checked {
+            #[recover(";")]
+            expr: Result<Expr, ParseError>,
+            #[shape(term(";"))]
+            _semi: (),
+        }
+        let checked: Checked = parse("x y ;");
+        let error = checked.expr.unwrap_err();
+        assert_eq!(error.message, "Parse error, skipped 4 tokens");
+        // The error covers the skipped `x y`, without the trivia after it.
+        assert_eq!(error.span, (0, 3).into());
+    }
+
+    #[test]
+    fn test_conversion_errors() {
+        let error = Statement::from_tree(&TokenTree::Empty).unwrap_err();
+        assert_eq!(error.message, "Expected a `Statement` node, found nothing");
+        let trees = lex("x", &Exprs::new());
+        assert!(Expr::parse(TokenStream::new(&trees), &mut NoOpMatchContext).is_err());
+    }
+
+    #[test]
+    fn test_derived_shapes_lint() {
+        assert!(lint_shape(&ast::<Program>(), "Program").is_empty());
+
+        /// `list := list "," "x" | "x"`
+        #[derive(Debug, crate::Shape)]
+        #[allow(dead_code)]
+        enum List {
+            More(Box<List>, #[shape(term(","))] (), #[shape(term("x"))] ()),
+            One(#[shape(term("x"))] ()),
+        }
+        assert!(matches!(
+            &lint_shape(&ast::<List>(), "List")[..],
+            [Lint::LeftRecursion { rule, .. }] if rule.ends_with("List")
+        ));
+    }
+}
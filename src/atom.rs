@@ -1,4 +1,6 @@
 use crate::highlighter::Highlighter;
+use crate::language::ModeTransition;
+use crate::lexer::LexError;
 use crate::token::{Cursor, Token};
 use std::fmt::{self, Debug, Display};
 
@@ -7,6 +9,8 @@ use std::fmt::{self, Debug, Display};
 pub enum AtomKind {
     /// Whitespace (spaces, tabs, newlines). Usually ignored by shapes.
     Whitespace,
+    /// A comment. Like whitespace, usually ignored by shapes.
+    Comment,
     /// An identifier (variable name, function name, etc.).
     Identifier,
     /// A string literal.
@@ -19,10 +23,21 @@ pub enum AtomKind {
     Other(String),
 }
 
+impl AtomKind {
+    /// Whether tokens of this kind are trivia by default: text that is kept in
+    /// the token trees but skipped by shapes and the parser.
+    ///
+    /// Languages can choose different trivia with [`crate::Language::is_trivia`].
+    pub fn is_trivia(&self) -> bool {
+        matches!(self, AtomKind::Whitespace | AtomKind::Comment)
+    }
+}
+
 impl Display for AtomKind {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             AtomKind::Whitespace => write!(f, "whitespace"),
+            AtomKind::Comment => write!(f, "comment"),
             AtomKind::Identifier => write!(f, "identifier"),
             AtomKind::String => write!(f, "string"),
             AtomKind::Number => write!(f, "number"),
@@ -48,4 +63,68 @@ pub trait Atom: Debug + Send + Sync {
 
     /// Applies syntax highlighting to the token.
     fn highlight(&self, token: &Token, highlighter: &mut dyn Highlighter);
+
+    /// Returns `true` if this atom is "opaque".
+    ///
+    /// Opaque atoms (strings, comments, raw literals) are tried before any delimiter
+    /// matching, so their contents can contain delimiter characters (e.g. `"(x"` or
+    /// `// }`) without opening or closing a group.
+    fn is_opaque(&self) -> bool {
+        false
+    }
+
+    /// Returns this atom's priority under [`crate::language::LexStrategy::LongestMatch`].
+    ///
+    /// When several atoms match the same number of bytes, the one with the highest
+    /// priority wins; atoms with equal priority fall back to declaration order.
+    fn priority(&self) -> i32 {
+        0
+    }
+
+    /// Returns a regular expression, anchored with `^`, that matches wherever
+    /// `parse` succeeds.
+    ///
+    /// [`crate::compiled_lexer::CompiledLexer`] fuses the patterns of neighbouring
+    /// atoms into a single automaton to find candidate atoms quickly; unless the
+    /// pattern is exact (see [`Atom::pattern_is_exact`]), `parse` is still called
+    /// to produce the token. The pattern may match more than `parse` accepts, but
+    /// never less. Atoms without a pattern are tried one by one.
+    fn pattern(&self) -> Option<String> {
+        None
+    }
+
+    /// Returns `true` if `parse` accepts exactly the non-empty matches of
+    /// [`Atom::pattern`], producing a plain [`Token`] of [`Atom::kind`] with the
+    /// matched text.
+    ///
+    /// [`crate::compiled_lexer::CompiledLexer`] then builds the token from the
+    /// match it already found, without calling `parse`.
+    fn pattern_is_exact(&self) -> bool {
+        false
+    }
+
+    /// Reports problems with a token this atom produced, such as an invalid escape
+    /// in a string literal.
+    ///
+    /// The lexer calls this for every token the atom produces and collects the
+    /// errors alongside its own (see [`crate::lexer::lex_with_diagnostics`]). Spans
+    /// use the same offsets as the token's location, and may point inside it.
+    fn diagnose(&self, _token: &Token) -> Vec<LexError> {
+        Vec::new()
+    }
+
+    /// The exact strings this atom matches, if it matches a fixed set of them.
+    ///
+    /// Used by [`crate::lint`] to find atoms that another atom always matches
+    /// first. Atoms with open-ended patterns return nothing.
+    fn literals(&self) -> Vec<&str> {
+        Vec::new()
+    }
+
+    /// Returns the mode change to apply after this atom matches, if any.
+    ///
+    /// See [`crate::language::LexerMode`].
+    fn mode_transition(&self) -> Option<ModeTransition> {
+        None
+    }
 }
@@ -1,8 +1,12 @@
 use crate::atom::{Atom, AtomKind};
 use crate::highlighter::{HighlightStyle, Highlighter};
-use crate::token::{Cursor, Token};
+use crate::language::ModeTransition;
+use crate::lexer::LexError;
+use crate::token::{Cursor, LiteralValue, Token};
+use miette::SourceSpan;
 use regex::Regex;
 use std::fmt::Debug;
+use std::num::IntErrorKind;
 
 /// An Atom implementation that uses a regular expression to match tokens.
 ///
@@ -11,6 +15,9 @@ use std::fmt::Debug;
 pub struct RegexAtom {
     kind: AtomKind,
     regex: Regex,
+    opaque: bool,
+    priority: i32,
+    transition: Option<ModeTransition>,
 }
 
 impl Debug for RegexAtom {
@@ -18,6 +25,9 @@ impl Debug for RegexAtom {
         f.debug_struct("RegexAtom")
             .field("kind", &self.kind)
             .field("regex", &self.regex.as_str())
+            .field("opaque", &self.opaque)
+            .field("priority", &self.priority)
+            .field("transition", &self.transition)
             .finish()
     }
 }
@@ -25,19 +35,38 @@ impl Debug for RegexAtom {
 impl RegexAtom {
     /// Creates a new RegexAtom.
     ///
-    /// The pattern is automatically anchored to the start of the string if it isn't already.
+    /// The pattern is automatically anchored to the start of the string.
     pub fn new(kind: AtomKind, pattern: &str) -> Self {
-        let pattern = if pattern.starts_with('^') {
-            pattern.to_string()
-        } else {
-            format!("^{}", pattern)
-        };
+        let pattern = format!("^(?:{})", pattern);
 
         Self {
             kind,
             regex: Regex::new(&pattern).expect("Invalid regex pattern"),
+            opaque: false,
+            priority: 0,
+            transition: None,
         }
     }
+
+    /// Marks this atom as opaque, so it is matched before delimiters.
+    ///
+    /// Use this for strings and comments that may contain delimiter characters.
+    pub fn opaque(mut self) -> Self {
+        self.opaque = true;
+        self
+    }
+
+    /// Sets the tie-breaking priority used by longest-match lexing.
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Changes the lexer mode after this atom matches.
+    pub fn with_transition(mut self, transition: ModeTransition) -> Self {
+        self.transition = Some(transition);
+        self
+    }
 }
 
 impl Atom for RegexAtom {
@@ -67,62 +96,209 @@ impl Atom for RegexAtom {
             AtomKind::Number => HighlightStyle::Number,
             AtomKind::Operator => HighlightStyle::Operator,
             AtomKind::Whitespace => HighlightStyle::None,
+            AtomKind::Comment => HighlightStyle::Comment,
             AtomKind::Identifier => HighlightStyle::Variable,
             _ => HighlightStyle::None,
         };
         highlighter.highlight(token, style);
     }
+
+    fn is_opaque(&self) -> bool {
+        self.opaque
+    }
+
+    fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    fn pattern(&self) -> Option<String> {
+        Some(self.regex.as_str().to_string())
+    }
+
+    fn pattern_is_exact(&self) -> bool {
+        true
+    }
+
+    fn mode_transition(&self) -> Option<ModeTransition> {
+        self.transition.clone()
+    }
 }
 
 /// An Atom implementation that matches a fixed set of keywords.
+///
+/// Keywords have priority 1 by default, so under longest-match lexing they win
+/// over an identifier atom matching the same text.
+///
+/// A keyword ending in an identifier character only matches if the text after
+/// it doesn't continue the identifier, so `in` doesn't match the start of
+/// `index`. See [`KeywordAtom::with_boundary`] to change what counts as an
+/// identifier character.
+///
+/// ```rust
+/// use mcparse::atom::Atom;
+/// use mcparse::atoms::KeywordAtom;
+/// use mcparse::token::Cursor;
+///
+/// let atom = KeywordAtom::new(&["select", "from"]).ignore_ascii_case();
+/// let (token, _) = atom.parse(Cursor::new("SELECT *")).unwrap();
+/// assert_eq!(token.text, "SELECT");
+/// assert!(atom.parse(Cursor::new("selection")).is_none());
+/// ```
 #[derive(Debug)]
 pub struct KeywordAtom {
     keywords: Vec<String>,
+    priority: i32,
+    boundary: Option<fn(char) -> bool>,
+    case: KeywordCase,
+    styles: Vec<(String, HighlightStyle)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeywordCase {
+    Sensitive,
+    Ascii,
+    Unicode,
 }
 
 impl KeywordAtom {
     pub fn new(keywords: &[&str]) -> Self {
         Self {
             keywords: keywords.iter().map(|s| s.to_string()).collect(),
+            priority: 1,
+            boundary: Some(unicode_ident::is_xid_continue),
+            case: KeywordCase::Sensitive,
+            styles: Vec::new(),
         }
     }
-}
 
-impl Atom for KeywordAtom {
-    fn kind(&self) -> AtomKind {
-        AtomKind::Identifier
+    /// Sets the tie-breaking priority used by longest-match lexing.
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
     }
 
-    fn parse<'a>(&self, input: Cursor<'a>) -> Option<(Token, Cursor<'a>)> {
-        // Find the longest matching keyword to avoid prefix issues (e.g. matching "in" inside "int")
+    /// Sets which characters continue an identifier. A keyword ending in one of
+    /// them doesn't match if the next character is one too. Defaults to Unicode's
+    /// `XID_Continue`, which includes `_`.
+    pub fn with_boundary(mut self, is_identifier_char: fn(char) -> bool) -> Self {
+        self.boundary = Some(is_identifier_char);
+        self
+    }
 
-        let mut best_match: Option<&String> = None;
+    /// Only treats ASCII letters, digits, and `_` as identifier characters.
+    pub fn ascii_boundary(self) -> Self {
+        self.with_boundary(|c| c.is_ascii_alphanumeric() || c == '_')
+    }
+
+    /// Matches keywords even at the start of a longer word.
+    pub fn no_boundary(mut self) -> Self {
+        self.boundary = None;
+        self
+    }
+
+    /// Matches keywords regardless of ASCII case, so `select` matches `SELECT`.
+    pub fn ignore_ascii_case(mut self) -> Self {
+        self.case = KeywordCase::Ascii;
+        self
+    }
+
+    /// Matches keywords regardless of case, comparing their Unicode lowercase
+    /// forms.
+    pub fn ignore_case(mut self) -> Self {
+        self.case = KeywordCase::Unicode;
+        self
+    }
 
-        for keyword in &self.keywords {
-            if input.rest.starts_with(keyword) {
-                if let Some(current_best) = best_match {
-                    if keyword.len() > current_best.len() {
-                        best_match = Some(keyword);
+    /// Highlights `keyword` with `style` instead of [`HighlightStyle::Keyword`].
+    pub fn with_style(mut self, keyword: &str, style: HighlightStyle) -> Self {
+        self.styles.push((keyword.to_string(), style));
+        self
+    }
+
+    /// Returns the length of the text at the start of `rest` that spells
+    /// `keyword`, ignoring case as configured.
+    fn match_len(&self, rest: &str, keyword: &str) -> Option<usize> {
+        match self.case {
+            KeywordCase::Sensitive => rest.starts_with(keyword).then_some(keyword.len()),
+            KeywordCase::Ascii => rest
+                .get(..keyword.len())
+                .filter(|prefix| prefix.eq_ignore_ascii_case(keyword))
+                .map(str::len),
+            KeywordCase::Unicode => {
+                let mut expected = keyword.chars().flat_map(char::to_lowercase).peekable();
+                for (i, c) in rest.char_indices() {
+                    if !c.to_lowercase().all(|c| expected.next() == Some(c)) {
+                        return None;
+                    }
+                    if expected.peek().is_none() {
+                        return Some(i + c.len_utf8());
                     }
-                } else {
-                    best_match = Some(keyword);
                 }
+                None
             }
         }
+    }
 
-        if let Some(keyword) = best_match {
-            let len = keyword.len();
-            Some((
-                Token::new(AtomKind::Identifier, keyword, input.offset),
-                input.advance(len),
-            ))
-        } else {
-            None
-        }
+    /// Whether a match of length `len` at the start of `rest` ends at an
+    /// identifier boundary.
+    fn at_boundary(&self, rest: &str, len: usize) -> bool {
+        let Some(is_identifier_char) = self.boundary else {
+            return true;
+        };
+        let ends_word = rest[..len]
+            .chars()
+            .next_back()
+            .is_some_and(is_identifier_char);
+        !ends_word || !rest[len..].chars().next().is_some_and(is_identifier_char)
+    }
+}
+
+impl Atom for KeywordAtom {
+    fn kind(&self) -> AtomKind {
+        AtomKind::Identifier
+    }
+
+    fn literals(&self) -> Vec<&str> {
+        self.keywords.iter().map(String::as_str).collect()
+    }
+
+    fn parse<'a>(&self, input: Cursor<'a>) -> Option<(Token, Cursor<'a>)> {
+        // Take the longest matching keyword, so "integer" wins over "int".
+        let len = self
+            .keywords
+            .iter()
+            .filter_map(|keyword| self.match_len(input.rest, keyword))
+            .filter(|&len| self.at_boundary(input.rest, len))
+            .max()?;
+        Some((
+            Token::new(AtomKind::Identifier, &input.rest[..len], input.offset),
+            input.advance(len),
+        ))
     }
 
     fn highlight(&self, token: &Token, highlighter: &mut dyn Highlighter) {
-        highlighter.highlight(token, HighlightStyle::Keyword);
+        let style = self
+            .styles
+            .iter()
+            .find(|(keyword, _)| self.match_len(&token.text, keyword) == Some(token.text.len()))
+            .map_or(HighlightStyle::Keyword, |&(_, style)| style);
+        highlighter.highlight(token, style);
+    }
+
+    fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    fn pattern(&self) -> Option<String> {
+        let alternatives: Vec<String> = self.keywords.iter().map(|k| regex::escape(k)).collect();
+        let flags = match self.case {
+            KeywordCase::Sensitive => "",
+            KeywordCase::Ascii => "(?i)",
+            // Full lowercase mappings can match text that the regex engine's
+            // simple case folding wouldn't, so these keywords are tried directly.
+            KeywordCase::Unicode => return None,
+        };
+        Some(format!("^{flags}(?:{})", alternatives.join("|")))
     }
 }
 
@@ -131,6 +307,8 @@ impl Atom for KeywordAtom {
 pub struct LiteralAtom {
     kind: AtomKind,
     literal: String,
+    priority: i32,
+    transition: Option<ModeTransition>,
 }
 
 impl LiteralAtom {
@@ -138,8 +316,22 @@ impl LiteralAtom {
         Self {
             kind,
             literal: literal.to_string(),
+            priority: 0,
+            transition: None,
         }
     }
+
+    /// Sets the tie-breaking priority used by longest-match lexing.
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Changes the lexer mode after this atom matches.
+    pub fn with_transition(mut self, transition: ModeTransition) -> Self {
+        self.transition = Some(transition);
+        self
+    }
 }
 
 impl Atom for LiteralAtom {
@@ -147,6 +339,10 @@ impl Atom for LiteralAtom {
         self.kind.clone()
     }
 
+    fn literals(&self) -> Vec<&str> {
+        vec![&self.literal]
+    }
+
     fn parse<'a>(&self, input: Cursor<'a>) -> Option<(Token, Cursor<'a>)> {
         if input.rest.starts_with(&self.literal) {
             let len = self.literal.len();
@@ -166,4 +362,731 @@ impl Atom for LiteralAtom {
         };
         highlighter.highlight(token, style);
     }
+
+    fn priority(&self) -> i32 {
+        self.priority
+    }
+
+    fn pattern(&self) -> Option<String> {
+        Some(format!("^{}", regex::escape(&self.literal)))
+    }
+
+    fn pattern_is_exact(&self) -> bool {
+        true
+    }
+
+    fn mode_transition(&self) -> Option<ModeTransition> {
+        self.transition.clone()
+    }
+}
+
+/// A quoted string literal with backslash escapes, such as `"a\n"`.
+///
+/// The supported escapes are `\n`, `\r`, `\t`, `\\`, `\0`, `\'`, `\"`, `\xHH`
+/// up to `\x7F`, `\u{H...}`, and a backslash before a line break (`\n` or
+/// `\r\n`). Any other escape is reported as [`LexError::InvalidEscape`]. A
+/// string missing its closing quote runs to the end of the input (or line, see
+/// [`StringAtom::single_line`]) and is reported as [`LexError::UnterminatedString`].
+/// [`StringAtom::json_escapes`] switches to the escapes of JSON instead.
+///
+/// ```rust
+/// use mcparse::atom::Atom;
+/// use mcparse::atoms::StringAtom;
+/// use mcparse::token::Cursor;
+///
+/// let atom = StringAtom::new(&['"', '\'']);
+/// let (token, _) = atom.parse(Cursor::new(r#"'it\'s' rest"#)).unwrap();
+/// assert_eq!(token.text, r#"'it\'s'"#);
+/// assert!(atom.diagnose(&token).is_empty());
+/// assert_eq!(token.string_value(), Some("it's"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct StringAtom {
+    quotes: Vec<char>,
+    single_line: bool,
+    json_escapes: bool,
+}
+
+impl StringAtom {
+    /// Creates a string atom for strings delimited by any of `quotes`. A string
+    /// ends at the same quote character it started with.
+    pub fn new(quotes: &[char]) -> Self {
+        Self {
+            quotes: quotes.to_vec(),
+            single_line: false,
+            json_escapes: false,
+        }
+    }
+
+    /// Ends an unterminated string at the end of its line, instead of letting
+    /// it swallow the rest of the input.
+    pub fn single_line(mut self) -> Self {
+        self.single_line = true;
+        self
+    }
+
+    /// Accepts the escapes of JSON instead: `\"`, `\\`, `\/`, `\b`, `\f`, `\n`,
+    /// `\r`, `\t`, and `\uHHHH`, with surrogate pairs for characters outside the
+    /// Basic Multilingual Plane.
+    pub fn json_escapes(mut self) -> Self {
+        self.json_escapes = true;
+        self
+    }
+
+    /// Returns the length and decoded contents of the string at the start of
+    /// `rest`, which is at `offset` in the input, reporting any problems to
+    /// `errors`.
+    fn scan(
+        &self,
+        rest: &str,
+        offset: usize,
+        errors: &mut Vec<LexError>,
+    ) -> Option<(usize, String)> {
+        let quote = rest.chars().next().filter(|c| self.quotes.contains(c))?;
+        let mut value = String::new();
+        let mut chars = rest.char_indices().skip(1).peekable();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                c if c == quote => return Some((i + c.len_utf8(), value)),
+                '\n' if self.single_line => {
+                    errors.push(LexError::UnterminatedString {
+                        span: SourceSpan::new(offset.into(), quote.len_utf8()),
+                    });
+                    return Some((i, value));
+                }
+                '\\' => {
+                    let escape = if self.json_escapes {
+                        scan_json_escape(rest, i, &mut chars).map(Some)
+                    } else {
+                        scan_escape(rest, i, &mut chars)
+                    };
+                    match escape {
+                        Ok(decoded) => value.extend(decoded),
+                        Err(end) => {
+                            errors.push(LexError::InvalidEscape {
+                                escape: rest[i..end].to_string(),
+                                span: SourceSpan::new((offset + i).into(), end - i),
+                            });
+                            value.push_str(&rest[i..end]);
+                        }
+                    }
+                }
+                c => value.push(c),
+            }
+        }
+        errors.push(LexError::UnterminatedString {
+            span: SourceSpan::new(offset.into(), quote.len_utf8()),
+        });
+        Some((rest.len(), value))
+    }
+}
+
+/// Consumes the escape sequence whose backslash is at `start` of `text`.
+///
+/// Returns the character it stands for, which is `None` for an escaped line
+/// break, or the end of the sequence as `Err` if it isn't a valid escape.
+fn scan_escape(
+    text: &str,
+    start: usize,
+    chars: &mut std::iter::Peekable<impl Iterator<Item = (usize, char)>>,
+) -> Result<Option<char>, usize> {
+    let Some((i, c)) = chars.next() else {
+        return Err(start + 1);
+    };
+    let end = i + c.len_utf8();
+    match c {
+        'n' => Ok(Some('\n')),
+        'r' => Ok(Some('\r')),
+        't' => Ok(Some('\t')),
+        '0' => Ok(Some('\0')),
+        '\\' | '\'' | '"' => Ok(Some(c)),
+        '\n' => Ok(None),
+        '\r' if chars.next_if(|&(_, c)| c == '\n').is_some() => Ok(None),
+        'x' => {
+            let mut end = end;
+            for _ in 0..2 {
+                match chars.next_if(|(_, c)| c.is_ascii_hexdigit()) {
+                    Some((i, _)) => end = i + 1,
+                    None => return Err(end),
+                }
+            }
+            // As in Rust, `\x` only reaches ASCII.
+            let byte = u8::from_str_radix(&text[end - 2..end], 16).map_err(|_| end)?;
+            if !byte.is_ascii() {
+                return Err(end);
+            }
+            Ok(Some(char::from(byte)))
+        }
+        'u' => {
+            if chars.next_if(|&(_, c)| c == '{').is_none() {
+                return Err(end);
+            }
+            let digits_start = end + 1;
+            let mut end = digits_start;
+            while let Some((i, _)) = chars.next_if(|(_, c)| c.is_ascii_hexdigit()) {
+                end = i + 1;
+            }
+            let digits = &text[digits_start..end];
+            if chars.next_if(|&(_, c)| c == '}').is_none() {
+                return Err(end);
+            }
+            Some(digits)
+                .filter(|d| (1..=6).contains(&d.len()))
+                .and_then(|d| u32::from_str_radix(d, 16).ok())
+                .and_then(char::from_u32)
+                .map(Some)
+                .ok_or(end + 1)
+        }
+        _ => Err(end),
+    }
+}
+
+/// Like [`scan_escape`], for the escapes of a JSON string.
+fn scan_json_escape(
+    text: &str,
+    start: usize,
+    chars: &mut std::iter::Peekable<impl Iterator<Item = (usize, char)>>,
+) -> Result<char, usize> {
+    let Some((i, c)) = chars.next() else {
+        return Err(start + 1);
+    };
+    let end = i + c.len_utf8();
+    match c {
+        '"' | '\\' | '/' => Ok(c),
+        'b' => Ok('\u{8}'),
+        'f' => Ok('\u{c}'),
+        'n' => Ok('\n'),
+        'r' => Ok('\r'),
+        't' => Ok('\t'),
+        'u' => {
+            let (high, end) = scan_code_unit(end, chars)?;
+            if !(0xD800..0xDC00).contains(&high) {
+                return char::from_u32(high).ok_or(end);
+            }
+            // A high surrogate must be followed by an escaped low surrogate.
+            if !text[end..].starts_with("\\u") {
+                return Err(end);
+            }
+            chars.nth(1);
+            let (low, end) = scan_code_unit(end + 2, chars)?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err(end);
+            }
+            char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)).ok_or(end)
+        }
+        _ => Err(end),
+    }
+}
+
+/// Consumes the four hex digits of a `\u` escape in a JSON string, starting at
+/// `start`. Returns the code unit and the end of the digits.
+fn scan_code_unit(
+    start: usize,
+    chars: &mut std::iter::Peekable<impl Iterator<Item = (usize, char)>>,
+) -> Result<(u32, usize), usize> {
+    let mut unit = 0;
+    let mut end = start;
+    for _ in 0..4 {
+        match chars.next_if(|(_, c)| c.is_ascii_hexdigit()) {
+            Some((i, c)) => {
+                unit = unit * 16 + c.to_digit(16).unwrap();
+                end = i + 1;
+            }
+            None => return Err(end),
+        }
+    }
+    Ok((unit, end))
+}
+
+impl Atom for StringAtom {
+    fn kind(&self) -> AtomKind {
+        AtomKind::String
+    }
+
+    fn parse<'a>(&self, input: Cursor<'a>) -> Option<(Token, Cursor<'a>)> {
+        let (len, value) = self.scan(input.rest, input.offset, &mut Vec::new())?;
+        Some((
+            Token::new(AtomKind::String, &input.rest[..len], input.offset)
+                .with_value(LiteralValue::String(value)),
+            input.advance(len),
+        ))
+    }
+
+    fn highlight(&self, token: &Token, highlighter: &mut dyn Highlighter) {
+        highlighter.highlight(token, HighlightStyle::String);
+    }
+
+    fn is_opaque(&self) -> bool {
+        true
+    }
+
+    fn pattern(&self) -> Option<String> {
+        let quotes: Vec<String> = self
+            .quotes
+            .iter()
+            .map(|q| regex::escape(&q.to_string()))
+            .collect();
+        Some(format!("^(?:{})", quotes.join("|")))
+    }
+
+    fn diagnose(&self, token: &Token) -> Vec<LexError> {
+        let mut errors = Vec::new();
+        self.scan(&token.text, token.location.span.offset(), &mut errors);
+        errors
+    }
+}
+
+/// A raw string literal without escapes, fenced with any number of `#`s, such as
+/// `r"C:\"` or `r#"say "hi""#`.
+///
+/// The string ends at the first quote followed by as many `#`s as it started
+/// with. A missing end is reported as [`LexError::UnterminatedString`].
+#[derive(Debug, Clone)]
+pub struct RawStringAtom {
+    prefix: String,
+}
+
+impl RawStringAtom {
+    /// Creates a raw string atom for strings starting with `prefix`, e.g. `"r"`.
+    pub fn new(prefix: &str) -> Self {
+        Self {
+            prefix: prefix.to_string(),
+        }
+    }
+
+    fn scan(
+        &self,
+        rest: &str,
+        offset: usize,
+        errors: &mut Vec<LexError>,
+    ) -> Option<(usize, String)> {
+        let after_prefix = rest.strip_prefix(self.prefix.as_str())?;
+        let hashes = after_prefix.len() - after_prefix.trim_start_matches('#').len();
+        let body = after_prefix[hashes..].strip_prefix('"')?;
+        let opener = self.prefix.len() + hashes + 1;
+
+        let closer = format!("\"{}", "#".repeat(hashes));
+        match body.find(&closer) {
+            Some(end) => Some((opener + end + closer.len(), body[..end].to_string())),
+            None => {
+                errors.push(LexError::UnterminatedString {
+                    span: SourceSpan::new(offset.into(), opener),
+                });
+                Some((rest.len(), body.to_string()))
+            }
+        }
+    }
+}
+
+impl Atom for RawStringAtom {
+    fn kind(&self) -> AtomKind {
+        AtomKind::String
+    }
+
+    fn parse<'a>(&self, input: Cursor<'a>) -> Option<(Token, Cursor<'a>)> {
+        let (len, value) = self.scan(input.rest, input.offset, &mut Vec::new())?;
+        Some((
+            Token::new(AtomKind::String, &input.rest[..len], input.offset)
+                .with_value(LiteralValue::String(value)),
+            input.advance(len),
+        ))
+    }
+
+    fn highlight(&self, token: &Token, highlighter: &mut dyn Highlighter) {
+        highlighter.highlight(token, HighlightStyle::String);
+    }
+
+    fn is_opaque(&self) -> bool {
+        true
+    }
+
+    fn pattern(&self) -> Option<String> {
+        Some(format!("^{}#*\"", regex::escape(&self.prefix)))
+    }
+
+    fn diagnose(&self, token: &Token) -> Vec<LexError> {
+        let mut errors = Vec::new();
+        self.scan(&token.text, token.location.span.offset(), &mut errors);
+        errors
+    }
+}
+
+/// An integer or float literal.
+///
+/// Integers may be decimal or use a `0x`, `0o`, or `0b` prefix. Floats are
+/// decimal, with a fraction (`1.5`), an exponent (`1e-3`), or both. Digits may be
+/// separated by `_` (`1_000`). Letters directly after the digits are a suffix,
+/// which is reported as [`LexError::InvalidSuffix`] unless it was allowed with
+/// [`NumberAtom::with_suffixes`]. Digits too large for the base (`0b102`) are
+/// reported as [`LexError::InvalidDigit`], and a prefix or exponent without digits
+/// as [`LexError::MissingDigits`]. An integer that doesn't fit in a `u128`, or a
+/// float that doesn't fit in an `f64`, is reported as [`LexError::NumberOverflow`].
+///
+/// ```rust
+/// use mcparse::atom::Atom;
+/// use mcparse::atoms::NumberAtom;
+/// use mcparse::token::Cursor;
+///
+/// let atom = NumberAtom::new().with_suffixes(&["u8", "f64"]);
+/// let (token, _) = atom.parse(Cursor::new("0x1F_u8 + 1")).unwrap();
+/// assert_eq!(token.text, "0x1F_u8");
+/// assert!(atom.diagnose(&token).is_empty());
+/// assert_eq!(token.integer_value(), Some(31));
+/// assert_eq!((token.radix(), token.suffix()), (Some(16), Some("u8")));
+/// ```
+#[derive(Debug, Clone)]
+pub struct NumberAtom {
+    floats: bool,
+    suffixes: Vec<String>,
+}
+
+impl Default for NumberAtom {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NumberAtom {
+    pub fn new() -> Self {
+        Self {
+            floats: true,
+            suffixes: Vec::new(),
+        }
+    }
+
+    /// Only lexes integers, so `1.5` is `1`, `.`, `5`.
+    pub fn integers_only(mut self) -> Self {
+        self.floats = false;
+        self
+    }
+
+    /// Allows the given type suffixes, such as `u8` in `255u8`.
+    pub fn with_suffixes(mut self, suffixes: &[&str]) -> Self {
+        self.suffixes = suffixes.iter().map(|s| s.to_string()).collect();
+        self
+    }
+
+    /// Returns the length and, if it could be decoded, the value of the number at
+    /// the start of `rest`.
+    fn scan(
+        &self,
+        rest: &str,
+        offset: usize,
+        errors: &mut Vec<LexError>,
+    ) -> Option<(usize, Option<LiteralValue>)> {
+        let bytes = rest.as_bytes();
+        if !bytes.first()?.is_ascii_digit() {
+            return None;
+        }
+        let span = |start: usize, len: usize| SourceSpan::new((offset + start).into(), len);
+
+        let radix = match bytes.get(..2) {
+            Some(b"0x" | b"0X") => 16,
+            Some(b"0o" | b"0O") => 8,
+            Some(b"0b" | b"0B") => 2,
+            _ => 10,
+        };
+        let prefix = if radix == 10 { 0 } else { 2 };
+        let mut i = prefix;
+        let mut is_float = false;
+
+        let digits = scan_digits(rest, &mut i, radix, offset, errors);
+        if digits == 0 {
+            errors.push(LexError::MissingDigits { span: span(0, 2) });
+        }
+
+        if radix == 10 && self.floats {
+            if bytes.get(i) == Some(&b'.') && bytes.get(i + 1).is_some_and(u8::is_ascii_digit) {
+                i += 1;
+                scan_digits(rest, &mut i, 10, offset, errors);
+                is_float = true;
+            }
+            if let Some(b'e' | b'E') = bytes.get(i) {
+                let mut j = i + 1;
+                let signed = matches!(bytes.get(j), Some(b'+' | b'-'));
+                if signed {
+                    j += 1;
+                }
+                // Without a sign or digit, the `e` starts a suffix instead.
+                if signed
+                    || bytes
+                        .get(j)
+                        .is_some_and(|b| b.is_ascii_digit() || *b == b'_')
+                {
+                    let exponent = i;
+                    i = j;
+                    is_float = true;
+                    if scan_digits(rest, &mut i, 10, offset, errors) == 0 {
+                        errors.push(LexError::MissingDigits {
+                            span: span(exponent, j - exponent),
+                        });
+                    }
+                }
+            }
+        }
+
+        let end = i;
+        let suffix_len: usize = rest[end..]
+            .chars()
+            .take_while(|&c| unicode_ident::is_xid_continue(c))
+            .map(char::len_utf8)
+            .sum();
+        let suffix = (suffix_len > 0).then(|| rest[end..end + suffix_len].to_string());
+        if let Some(suffix) = &suffix
+            && !self.suffixes.contains(suffix)
+        {
+            errors.push(LexError::InvalidSuffix {
+                suffix: suffix.clone(),
+                span: span(end, suffix_len),
+            });
+        }
+
+        let digits: String = rest[prefix..end].chars().filter(|&c| c != '_').collect();
+        let value = if is_float {
+            digits.parse::<f64>().ok().map(|value| {
+                if value.is_infinite() {
+                    errors.push(LexError::NumberOverflow { span: span(0, end) });
+                }
+                LiteralValue::Float { value, suffix }
+            })
+        } else {
+            match u128::from_str_radix(&digits, radix) {
+                Ok(value) => Some(LiteralValue::Integer {
+                    value,
+                    radix,
+                    suffix,
+                }),
+                Err(e) => {
+                    if *e.kind() == IntErrorKind::PosOverflow {
+                        errors.push(LexError::NumberOverflow { span: span(0, end) });
+                    }
+                    None
+                }
+            }
+        };
+        Some((end + suffix_len, value))
+    }
+}
+
+/// Consumes the digits and `_` separators at `*i` in `text`, returning the number
+/// of digits. Decimal digits too large for `radix` are consumed and reported.
+fn scan_digits(
+    text: &str,
+    i: &mut usize,
+    radix: u32,
+    offset: usize,
+    errors: &mut Vec<LexError>,
+) -> usize {
+    let mut digits = 0;
+    for c in text[*i..].chars() {
+        match c {
+            '_' => {}
+            c if c.is_digit(radix) => digits += 1,
+            c if c.is_ascii_digit() => {
+                errors.push(LexError::InvalidDigit {
+                    digit: c,
+                    radix,
+                    span: SourceSpan::new((offset + *i).into(), 1),
+                });
+                digits += 1;
+            }
+            _ => break,
+        }
+        *i += 1;
+    }
+    digits
+}
+
+impl Atom for NumberAtom {
+    fn kind(&self) -> AtomKind {
+        AtomKind::Number
+    }
+
+    fn parse<'a>(&self, input: Cursor<'a>) -> Option<(Token, Cursor<'a>)> {
+        let (len, value) = self.scan(input.rest, input.offset, &mut Vec::new())?;
+        let mut token = Token::new(AtomKind::Number, &input.rest[..len], input.offset);
+        token.value = value;
+        Some((token, input.advance(len)))
+    }
+
+    fn highlight(&self, token: &Token, highlighter: &mut dyn Highlighter) {
+        highlighter.highlight(token, HighlightStyle::Number);
+    }
+
+    fn pattern(&self) -> Option<String> {
+        Some("^[0-9]".to_string())
+    }
+
+    fn diagnose(&self, token: &Token) -> Vec<LexError> {
+        let mut errors = Vec::new();
+        self.scan(&token.text, token.location.span.offset(), &mut errors);
+        errors
+    }
+}
+
+/// A comment running from `prefix` to the end of the line, such as `// note`.
+/// The line break is not part of the comment.
+#[derive(Debug, Clone)]
+pub struct LineCommentAtom {
+    prefix: String,
+}
+
+impl LineCommentAtom {
+    pub fn new(prefix: &str) -> Self {
+        Self {
+            prefix: prefix.to_string(),
+        }
+    }
+}
+
+impl Atom for LineCommentAtom {
+    fn kind(&self) -> AtomKind {
+        AtomKind::Comment
+    }
+
+    fn parse<'a>(&self, input: Cursor<'a>) -> Option<(Token, Cursor<'a>)> {
+        if !input.rest.starts_with(&self.prefix) {
+            return None;
+        }
+        let len = input.rest.find('\n').unwrap_or(input.rest.len());
+        Some((
+            Token::new(AtomKind::Comment, &input.rest[..len], input.offset),
+            input.advance(len),
+        ))
+    }
+
+    fn highlight(&self, token: &Token, highlighter: &mut dyn Highlighter) {
+        highlighter.highlight(token, HighlightStyle::Comment);
+    }
+
+    fn is_opaque(&self) -> bool {
+        true
+    }
+
+    fn pattern(&self) -> Option<String> {
+        Some(format!("^{}", regex::escape(&self.prefix)))
+    }
+}
+
+/// A comment between `open` and `close`, such as `/* note */`.
+///
+/// A comment missing its `close` runs to the end of the input and is reported
+/// as [`LexError::UnterminatedComment`].
+#[derive(Debug, Clone)]
+pub struct BlockCommentAtom {
+    open: String,
+    close: String,
+    nested: bool,
+}
+
+impl BlockCommentAtom {
+    pub fn new(open: &str, close: &str) -> Self {
+        Self {
+            open: open.to_string(),
+            close: close.to_string(),
+            nested: false,
+        }
+    }
+
+    /// Lets comments nest, so `/* a /* b */ c */` is one comment.
+    pub fn nested(mut self) -> Self {
+        self.nested = true;
+        self
+    }
+
+    fn scan(&self, rest: &str, offset: usize, errors: &mut Vec<LexError>) -> Option<usize> {
+        if !rest.starts_with(&self.open) {
+            return None;
+        }
+        // The openers of the comments that are still open.
+        let mut open = vec![0];
+        let mut i = self.open.len();
+        while let Some(&start) = open.last() {
+            let rest = &rest[i..];
+            if rest.is_empty() {
+                errors.push(LexError::UnterminatedComment {
+                    span: SourceSpan::new((offset + start).into(), self.open.len()),
+                });
+                break;
+            }
+            if rest.starts_with(&self.close) {
+                open.pop();
+                i += self.close.len();
+            } else if self.nested && rest.starts_with(&self.open) {
+                open.push(i);
+                i += self.open.len();
+            } else {
+                i += rest.chars().next().unwrap().len_utf8();
+            }
+        }
+        Some(i)
+    }
+}
+
+impl Atom for BlockCommentAtom {
+    fn kind(&self) -> AtomKind {
+        AtomKind::Comment
+    }
+
+    fn parse<'a>(&self, input: Cursor<'a>) -> Option<(Token, Cursor<'a>)> {
+        let len = self.scan(input.rest, input.offset, &mut Vec::new())?;
+        Some((
+            Token::new(AtomKind::Comment, &input.rest[..len], input.offset),
+            input.advance(len),
+        ))
+    }
+
+    fn highlight(&self, token: &Token, highlighter: &mut dyn Highlighter) {
+        highlighter.highlight(token, HighlightStyle::Comment);
+    }
+
+    fn is_opaque(&self) -> bool {
+        true
+    }
+
+    fn pattern(&self) -> Option<String> {
+        Some(format!("^{}", regex::escape(&self.open)))
+    }
+
+    fn diagnose(&self, token: &Token) -> Vec<LexError> {
+        let mut errors = Vec::new();
+        self.scan(&token.text, token.location.span.offset(), &mut errors);
+        errors
+    }
+}
+
+/// An identifier following Unicode Standard Annex #31: an `XID_Start` character
+/// or `_`, followed by any number of `XID_Continue` characters.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IdentifierAtom;
+
+impl Atom for IdentifierAtom {
+    fn kind(&self) -> AtomKind {
+        AtomKind::Identifier
+    }
+
+    fn parse<'a>(&self, input: Cursor<'a>) -> Option<(Token, Cursor<'a>)> {
+        let mut chars = input.rest.chars();
+        let first = chars.next()?;
+        if first != '_' && !unicode_ident::is_xid_start(first) {
+            return None;
+        }
+        let len = first.len_utf8()
+            + chars
+                .take_while(|&c| unicode_ident::is_xid_continue(c))
+                .map(char::len_utf8)
+                .sum::<usize>();
+        Some((
+            Token::new(AtomKind::Identifier, &input.rest[..len], input.offset),
+            input.advance(len),
+        ))
+    }
+
+    fn highlight(&self, token: &Token, highlighter: &mut dyn Highlighter) {
+        highlighter.highlight(token, HighlightStyle::Variable);
+    }
+
+    fn pattern(&self) -> Option<String> {
+        Some(r"^[_\p{XID_Start}]".to_string())
+    }
 }
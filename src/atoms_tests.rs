@@ -1,6 +1,27 @@
 use crate::atom::{Atom, AtomKind};
-use crate::atoms::{KeywordAtom, RegexAtom};
-use crate::token::Cursor;
+use crate::atoms::{
+    BlockCommentAtom, IdentifierAtom, KeywordAtom, LineCommentAtom, NumberAtom, RawStringAtom,
+    RegexAtom, StringAtom,
+};
+use crate::lexer::{LexError, lex_with_diagnostics};
+use crate::mock::MockLanguage;
+use crate::token::{Cursor, LiteralValue, Token, TokenTree};
+use miette::SourceSpan;
+
+/// Parses `input` with `atom`, returning the token's text and its diagnostics.
+fn scan(atom: &dyn Atom, input: &str) -> Option<(String, Vec<LexError>)> {
+    let (token, _) = atom.parse(Cursor::new(input))?;
+    let errors = atom.diagnose(&token);
+    Some((token.text, errors))
+}
+
+fn token(atom: &dyn Atom, input: &str) -> Token {
+    atom.parse(Cursor::new(input)).unwrap().0
+}
+
+fn span(start: usize, len: usize) -> SourceSpan {
+    SourceSpan::new(start.into(), len)
+}
 
 #[test]
 fn test_regex_atom_whitespace() {
@@ -50,6 +71,14 @@ fn test_regex_atom_anchoring() {
     assert!(atom.parse(cursor).is_none());
 }
 
+#[test]
+fn test_regex_atom_anchors_every_alternative() {
+    let atom = RegexAtom::new(AtomKind::Number, r"0x[0-9a-f]+|\d+");
+    let cursor = Cursor::new("abc 123");
+
+    assert!(atom.parse(cursor).is_none());
+}
+
 #[test]
 fn test_regex_atom_empty_match() {
     // A regex that can match empty string should return None to avoid infinite loops
@@ -87,15 +116,389 @@ fn test_keyword_atom_longest_match() {
 }
 
 #[test]
-fn test_keyword_atom_prefix_behavior() {
-    // This documents the current behavior: KeywordAtom matches prefixes.
-    // If we have "int" and input is "integer", it WILL match "int" if "integer" is not in the list.
-    // This is why order of Atoms in the Language definition matters (Keywords vs Identifiers).
-    let atom = KeywordAtom::new(&["int"]);
-    let input = "integer";
-    let cursor = Cursor::new(input);
+fn test_keyword_atom_boundary() {
+    // "int" doesn't match the start of the identifier "integer"...
+    let atom = KeywordAtom::new(&["int", "in"]);
+    assert!(atom.parse(Cursor::new("integer")).is_none());
+    assert!(atom.parse(Cursor::new("index")).is_none());
+    assert!(atom.parse(Cursor::new("in_")).is_none());
+    assert!(atom.parse(Cursor::new("iné")).is_none());
+    let (token, next) = atom.parse(Cursor::new("in(x)")).unwrap();
+    assert_eq!((token.text.as_str(), next.rest), ("in", "(x)"));
+
+    // ...unless the boundary check is turned off or narrowed.
+    let atom = KeywordAtom::new(&["int"]).no_boundary();
+    let (token, next) = atom.parse(Cursor::new("integer")).unwrap();
+    assert_eq!((token.text.as_str(), next.rest), ("int", "eger"));
+    let atom = KeywordAtom::new(&["in"]).ascii_boundary();
+    assert_eq!(atom.parse(Cursor::new("iné")).unwrap().0.text, "in");
+
+    // Keywords that don't end in an identifier character need no boundary.
+    let atom = KeywordAtom::new(&["=>", "+"]);
+    assert_eq!(atom.parse(Cursor::new("=>x")).unwrap().0.text, "=>");
+    assert_eq!(atom.parse(Cursor::new("+1")).unwrap().0.text, "+");
+}
+
+#[test]
+fn test_keyword_atom_case_folding() {
+    let atom = KeywordAtom::new(&["select"]);
+    assert!(atom.parse(Cursor::new("SELECT")).is_none());
+
+    // The token keeps the text as written.
+    let atom = KeywordAtom::new(&["select"]).ignore_ascii_case();
+    assert_eq!(
+        atom.parse(Cursor::new("SeLeCt 1")).unwrap().0.text,
+        "SeLeCt"
+    );
+    assert!(atom.parse(Cursor::new("SELECTED")).is_none());
+
+    let atom = KeywordAtom::new(&["größe"]).ignore_ascii_case();
+    assert!(atom.parse(Cursor::new("GRÖßE")).is_none());
+    let atom = KeywordAtom::new(&["größe"]).ignore_case();
+    assert_eq!(atom.parse(Cursor::new("GRÖßE x")).unwrap().0.text, "GRÖßE");
+    assert_eq!(atom.pattern(), None);
+}
+
+#[test]
+fn test_string_atom_escapes() {
+    let atom = StringAtom::new(&['"', '\'']);
+    let (text, errors) = scan(&atom, r#""a\n\"b\x41\u{1F600}" rest"#).unwrap();
+    assert_eq!(text, r#""a\n\"b\x41\u{1F600}""#);
+    assert!(errors.is_empty());
+
+    // A string only ends at its own quote.
+    let (text, _) = scan(&atom, r#"'say "hi"' rest"#).unwrap();
+    assert_eq!(text, r#"'say "hi"'"#);
+    assert!(scan(&atom, "abc").is_none());
+}
+
+#[test]
+fn test_string_atom_invalid_escapes() {
+    let atom = StringAtom::new(&['"']);
+    let (_, errors) = scan(&atom, r#""\q \x4 \u{D800} \u{110000}""#).unwrap();
+    assert_eq!(
+        errors,
+        [
+            LexError::InvalidEscape {
+                escape: r"\q".into(),
+                span: span(1, 2)
+            },
+            LexError::InvalidEscape {
+                escape: r"\x4".into(),
+                span: span(4, 3)
+            },
+            LexError::InvalidEscape {
+                escape: r"\u{D800}".into(),
+                span: span(8, 8)
+            },
+            LexError::InvalidEscape {
+                escape: r"\u{110000}".into(),
+                span: span(17, 10)
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_string_atom_rejects_non_ascii_byte_escapes() {
+    let atom = StringAtom::new(&['"']);
+    let (_, errors) = scan(&atom, r#""\x7F\x80\xFF""#).unwrap();
+    assert_eq!(
+        errors,
+        [
+            LexError::InvalidEscape {
+                escape: r"\x80".into(),
+                span: span(5, 4)
+            },
+            LexError::InvalidEscape {
+                escape: r"\xFF".into(),
+                span: span(9, 4)
+            },
+        ]
+    );
+    assert_eq!(
+        token(&atom, r#""\x7F\xFF""#).string_value(),
+        Some("\u{7F}\\xFF")
+    );
+}
+
+#[test]
+fn test_string_atom_line_continuations() {
+    let atom = StringAtom::new(&['"']);
+    for input in ["\"a\\\nb\"", "\"a\\\r\nb\""] {
+        let (text, errors) = scan(&atom, input).unwrap();
+        assert_eq!(text, input);
+        assert!(errors.is_empty());
+        assert_eq!(token(&atom, input).string_value(), Some("ab"));
+    }
+
+    // A lone carriage return isn't a line break.
+    let (_, errors) = scan(&atom, "\"a\\\rb\"").unwrap();
+    assert_eq!(
+        errors,
+        [LexError::InvalidEscape {
+            escape: "\\\r".into(),
+            span: span(2, 2)
+        }]
+    );
+}
+
+#[test]
+fn test_string_atom_json_escapes() {
+    let atom = StringAtom::new(&['"']).json_escapes();
+    let input = r#""\"\\\/\b\f\n\r\t\u0041\u00e9\uD83D\uDE00""#;
+    let (text, errors) = scan(&atom, input).unwrap();
+    assert_eq!(text, input);
+    assert!(errors.is_empty());
+    assert_eq!(
+        token(&atom, input).string_value(),
+        Some("\"\\/\u{8}\u{c}\n\r\tA\u{e9}\u{1F600}")
+    );
+
+    // Rust-only escapes, short or lone surrogates.
+    let (_, errors) = scan(&atom, r#""\x41 \u{41} \u004 \uD83D \uDE00""#).unwrap();
+    assert_eq!(
+        errors,
+        [
+            LexError::InvalidEscape {
+                escape: r"\x".into(),
+                span: span(1, 2)
+            },
+            LexError::InvalidEscape {
+                escape: r"\u".into(),
+                span: span(6, 2)
+            },
+            LexError::InvalidEscape {
+                escape: r"\u004".into(),
+                span: span(13, 5)
+            },
+            LexError::InvalidEscape {
+                escape: r"\uD83D".into(),
+                span: span(19, 6)
+            },
+            LexError::InvalidEscape {
+                escape: r"\uDE00".into(),
+                span: span(26, 6)
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_string_atom_unterminated() {
+    let atom = StringAtom::new(&['"']);
+    let (text, errors) = scan(&atom, "\"abc\ndef").unwrap();
+    assert_eq!(text, "\"abc\ndef");
+    assert_eq!(errors, [LexError::UnterminatedString { span: span(0, 1) }]);
+
+    let (text, errors) = scan(&atom.single_line(), "\"abc\ndef").unwrap();
+    assert_eq!(text, "\"abc");
+    assert_eq!(errors, [LexError::UnterminatedString { span: span(0, 1) }]);
+}
+
+#[test]
+fn test_raw_string_atom() {
+    let atom = RawStringAtom::new("r");
+    let (text, errors) = scan(&atom, r###"r#"say "hi" \n"# rest"###).unwrap();
+    assert_eq!(text, r###"r#"say "hi" \n"#"###);
+    assert!(errors.is_empty());
+
+    let (text, errors) = scan(&atom, r###"r##"a"# b"###).unwrap();
+    assert_eq!(text, r###"r##"a"# b"###);
+    assert_eq!(errors, [LexError::UnterminatedString { span: span(0, 4) }]);
+
+    // Without a quote it isn't a raw string, so `r` can still be an identifier.
+    assert!(scan(&atom, "r#x").is_none());
+}
+
+#[test]
+fn test_number_atom_forms() {
+    let atom = NumberAtom::new().with_suffixes(&["u8", "f32"]);
+    for input in [
+        "42", "1_000", "0x1F_u8", "0o17", "0b1010", "1.5", "1e10", "1.5E-3", "2.5f32", "1_e5",
+    ] {
+        let (text, errors) = scan(&atom, input).unwrap();
+        assert_eq!(text, input);
+        assert!(errors.is_empty(), "{input}: {errors:?}");
+    }
+
+    // A dot not followed by a digit is left for method calls and ranges.
+    assert_eq!(scan(&atom, "1.max(2)").unwrap().0, "1");
+    assert_eq!(scan(&atom, "1..2").unwrap().0, "1");
+    assert_eq!(
+        scan(&NumberAtom::new().integers_only(), "1.5").unwrap().0,
+        "1"
+    );
+    assert!(scan(&atom, "x1").is_none());
+}
+
+#[test]
+fn test_number_atom_errors() {
+    let atom = NumberAtom::new();
+    assert_eq!(
+        scan(&atom, "0b1021").unwrap(),
+        (
+            "0b1021".to_string(),
+            vec![LexError::InvalidDigit {
+                digit: '2',
+                radix: 2,
+                span: span(4, 1)
+            }]
+        )
+    );
+    assert_eq!(
+        scan(&atom, "0x;").unwrap().1,
+        [LexError::MissingDigits { span: span(0, 2) }]
+    );
+    assert_eq!(
+        scan(&atom, "1e+;").unwrap(),
+        (
+            "1e+".to_string(),
+            vec![LexError::MissingDigits { span: span(1, 2) }]
+        )
+    );
+    assert_eq!(
+        scan(&atom, "12px").unwrap(),
+        (
+            "12px".to_string(),
+            vec![LexError::InvalidSuffix {
+                suffix: "px".into(),
+                span: span(2, 2)
+            }]
+        )
+    );
+}
+
+#[test]
+fn test_string_values() {
+    let atom = StringAtom::new(&['"']);
+    let value = |input| token(&atom, input).string_value().map(str::to_string);
+    assert_eq!(
+        value(r#""a\nb\x41\u{1F600}""#).as_deref(),
+        Some("a\nbA\u{1F600}")
+    );
+    assert_eq!(value("\"a\\\n b\"").as_deref(), Some("a b"));
+    // Invalid escapes are kept as written.
+    assert_eq!(value(r#""\q""#).as_deref(), Some("\\q"));
+    assert_eq!(value(r#""open"#).as_deref(), Some("open"));
+
+    let raw = RawStringAtom::new("r");
+    assert_eq!(
+        token(&raw, r##"r#"say "hi""#"##).string_value(),
+        Some(r#"say "hi""#)
+    );
+}
+
+#[test]
+fn test_number_values() {
+    let atom = NumberAtom::new().with_suffixes(&["u8", "f64"]);
+    let hex = token(&atom, "0x1F_u8");
+    assert_eq!(
+        hex.value,
+        Some(LiteralValue::Integer {
+            value: 31,
+            radix: 16,
+            suffix: Some("u8".into())
+        })
+    );
+    assert_eq!(
+        (hex.integer_value(), hex.radix(), hex.suffix()),
+        (Some(31), Some(16), Some("u8"))
+    );
+
+    assert_eq!(token(&atom, "0b1010").integer_value(), Some(10));
+    assert_eq!(token(&atom, "1_000").integer_value(), Some(1000));
+    let float = token(&atom, "2.5e-1f64");
+    assert_eq!(float.float_value(), Some(0.25));
+    assert_eq!((float.radix(), float.suffix()), (Some(10), Some("f64")));
+    assert_eq!(token(&atom, "1e3").float_value(), Some(1000.0));
+    assert_eq!(token(&atom, "1e3").integer_value(), None);
+
+    // Without valid digits there is no value.
+    assert_eq!(token(&atom, "0x").value, None);
+    assert_eq!(token(&atom, "0b12").value, None);
+}
+
+#[test]
+fn test_number_overflow() {
+    let atom = NumberAtom::new().with_suffixes(&["u8"]);
+    let big = "340282366920938463463374607431768211456u8";
+    assert_eq!(token(&atom, big).value, None);
+    assert_eq!(
+        scan(&atom, big).unwrap().1,
+        [LexError::NumberOverflow {
+            span: span(0, big.len() - 2)
+        }]
+    );
+    let max = "340282366920938463463374607431768211455";
+    assert_eq!(token(&atom, max).integer_value(), Some(u128::MAX));
+    assert_eq!(
+        scan(&atom, "1e400").unwrap().1,
+        [LexError::NumberOverflow { span: span(0, 5) }]
+    );
+}
+
+#[test]
+fn test_comment_atoms() {
+    let line = LineCommentAtom::new("//");
+    assert_eq!(scan(&line, "// a (b\nc").unwrap().0, "// a (b");
+    assert!(scan(&line, "/ a").is_none());
+
+    let block = BlockCommentAtom::new("/*", "*/");
+    assert_eq!(scan(&block, "/* a /* b */ c */").unwrap().0, "/* a /* b */");
+
+    let nested = BlockCommentAtom::new("/*", "*/").nested();
+    assert_eq!(
+        scan(&nested, "/* a /* b */ c */ d").unwrap().0,
+        "/* a /* b */ c */"
+    );
+
+    // The error points at the innermost comment left open.
+    let (text, errors) = scan(&nested, "/* a /* b */ /* c").unwrap();
+    assert_eq!(text, "/* a /* b */ /* c");
+    assert_eq!(
+        errors,
+        [LexError::UnterminatedComment { span: span(13, 2) }]
+    );
+}
+
+#[test]
+fn test_identifier_atom_xid() {
+    for input in ["foo", "_bar1", "größe", "变量", "x\u{0301}"] {
+        assert_eq!(scan(&IdentifierAtom, input).unwrap().0, input);
+    }
+    assert_eq!(scan(&IdentifierAtom, "a-b").unwrap().0, "a");
+    assert!(scan(&IdentifierAtom, "1a").is_none());
+    assert!(scan(&IdentifierAtom, "\u{0301}x").is_none());
+}
+
+#[test]
+fn test_atom_diagnostics_are_reported_by_lexer() {
+    let lang = MockLanguage::new()
+        .with_atom(Box::new(NumberAtom::new()))
+        .with_atom(Box::new(StringAtom::new(&['"'])))
+        .with_delimiter(crate::language::Delimiter::new("brace", "{", "}"));
+
+    let input = r#"{ "a\q)" 0b12 }"#;
+    let (trees, errors) = lex_with_diagnostics(input, &lang);
 
-    let (token, next) = atom.parse(cursor).expect("Should match prefix");
-    assert_eq!(token.text, "int");
-    assert_eq!(next.rest, "eger");
+    // The string is opaque, so the `)` inside it doesn't close anything.
+    let TokenTree::Delimited(_, children, _, true) = &trees[0] else {
+        panic!("Expected closed group, got {:?}", trees);
+    };
+    assert!(matches!(&children[1], TokenTree::Token(t) if t.text == r#""a\q)""#));
+    assert_eq!(
+        errors,
+        [
+            LexError::InvalidEscape {
+                escape: r"\q".into(),
+                span: span(4, 2)
+            },
+            LexError::InvalidDigit {
+                digit: '2',
+                radix: 2,
+                span: span(12, 1)
+            },
+        ]
+    );
 }
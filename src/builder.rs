@@ -0,0 +1,578 @@
+//! Layering a language on top of another.
+//!
+//! Dialects usually differ from their base language in a handful of atoms,
+//! delimiters and macros. [`LanguageBuilder`] starts from the base and records
+//! only those differences; the base is shared, not copied, so several dialects
+//! can extend the same `Arc` of it.
+//!
+//! Later changes take precedence over earlier ones, and the layer over the
+//! base:
+//!
+//! - Added atoms are tried before the inherited ones, in the order they were
+//!   added, so they win under [`LexStrategy::FirstMatch`] and ties under
+//!   [`LexStrategy::LongestMatch`].
+//! - A delimiter replaces any earlier delimiter of the same kind.
+//! - A macro replaces any earlier macro with the same name and fixity, so a
+//!   prefix `-` and an infix `-` can coexist.
+//! - Removals apply to everything added before them, including the base.
+//!
+//! Lexer modes, the lexing strategy and the other settings are inherited.
+
+use crate::atom::{Atom, AtomKind};
+use crate::highlighter::Highlighter;
+use crate::language::{Delimiter, Language, LexStrategy, LexerMode, ModeTransition};
+use crate::lexer::LexError;
+use crate::r#macro::{ExpansionResult, Macro, MacroContext};
+use crate::scoping::{BindingPass, ReferencePass};
+use crate::shape::{Associativity, Precedence, Shape};
+use crate::token::{Cursor, Token, TokenTree};
+use std::fmt::{self, Debug};
+use std::sync::Arc;
+
+/// Builds a [`LayeredLanguage`] by extending a base language.
+///
+/// ```
+/// use mcparse::{Language, LanguageBuilder, atom::AtomKind, atoms::LiteralAtom, define_language};
+///
+/// define_language! {
+///     struct Expr;
+///     atoms = [
+///         atom Identifier = regex r"[a-z]+",
+///         atom Operator = "+",
+///     ];
+///     delimiters = [];
+/// }
+///
+/// let dialect = LanguageBuilder::new(Expr::new())
+///     .with_atom(LiteralAtom::new(AtomKind::Operator, "->"))
+///     .build();
+/// assert_eq!(dialect.atoms().len(), 3);
+/// ```
+#[derive(Debug)]
+pub struct LanguageBuilder {
+    language: LayeredLanguage,
+    added_atoms: usize,
+}
+
+impl LanguageBuilder {
+    /// Starts from everything `base` defines.
+    ///
+    /// Pass an `Arc` to share one base between several dialects.
+    pub fn new(base: impl Language + 'static) -> Self {
+        let base: Arc<dyn Language> = Arc::new(base);
+        let atoms = (0..base.atoms().len())
+            .map(|index| {
+                Box::new(BaseAtom {
+                    language: base.clone(),
+                    index,
+                }) as Box<dyn Atom>
+            })
+            .collect();
+        let macros = base_macros(&base);
+
+        Self {
+            language: LayeredLanguage {
+                atoms,
+                delimiters: base.delimiters().to_vec(),
+                macros,
+                binding_pass: None,
+                reference_pass: None,
+                root: None,
+                base,
+            },
+            added_atoms: 0,
+        }
+    }
+
+    /// Adds an atom, tried before the inherited atoms.
+    pub fn with_atom(mut self, atom: impl Atom + 'static) -> Self {
+        self.language.atoms.insert(self.added_atoms, Box::new(atom));
+        self.added_atoms += 1;
+        self
+    }
+
+    /// Replaces every atom of the same kind as `atom` with it.
+    ///
+    /// Keywords are identifier atoms, so overriding the identifier atom removes
+    /// them too; add them back after the override.
+    pub fn override_atom(self, atom: impl Atom + 'static) -> Self {
+        let kind = atom.kind();
+        self.without_atoms(&kind).with_atom(atom)
+    }
+
+    /// Removes every atom of `kind`.
+    pub fn without_atoms(mut self, kind: &AtomKind) -> Self {
+        let added = self.language.atoms[..self.added_atoms]
+            .iter()
+            .filter(|atom| atom.kind() != *kind)
+            .count();
+        self.language.atoms.retain(|atom| atom.kind() != *kind);
+        self.added_atoms = added;
+        self
+    }
+
+    /// Adds a delimiter, replacing any delimiter of the same kind.
+    pub fn with_delimiter(mut self, delimiter: Delimiter) -> Self {
+        let delimiters = &mut self.language.delimiters;
+        match delimiters.iter_mut().find(|d| d.kind == delimiter.kind) {
+            Some(existing) => *existing = delimiter,
+            None => delimiters.push(delimiter),
+        }
+        self
+    }
+
+    /// Removes the delimiter of `kind`.
+    pub fn without_delimiter(mut self, kind: &str) -> Self {
+        self.language.delimiters.retain(|d| d.kind != kind);
+        self
+    }
+
+    /// Adds a macro, replacing any macro with the same name and fixity.
+    pub fn with_macro(mut self, mac: Box<dyn Macro>) -> Self {
+        let macros = &mut self.language.macros;
+        match macros
+            .iter_mut()
+            .find(|m| m.name() == mac.name() && m.is_operator() == mac.is_operator())
+        {
+            Some(existing) => *existing = mac,
+            None => macros.push(mac),
+        }
+        self
+    }
+
+    /// Merges the macro table of `other` into this one, as if each of its
+    /// macros were added with [`with_macro`](Self::with_macro).
+    pub fn with_macros_from(self, other: impl Language + 'static) -> Self {
+        let other: Arc<dyn Language> = Arc::new(other);
+        base_macros(&other)
+            .into_iter()
+            .fold(self, |builder, mac| builder.with_macro(mac))
+    }
+
+    /// Removes every macro named `name`, prefix and infix.
+    pub fn without_macro(mut self, name: &str) -> Self {
+        self.language.macros.retain(|m| m.name() != name);
+        self
+    }
+
+    /// Replaces the inherited binding pass.
+    pub fn with_binding_pass(mut self, pass: impl BindingPass + 'static) -> Self {
+        self.language.binding_pass = Some(Box::new(pass));
+        self
+    }
+
+    /// Replaces the inherited reference pass.
+    pub fn with_reference_pass(mut self, pass: impl ReferencePass + 'static) -> Self {
+        self.language.reference_pass = Some(Box::new(pass));
+        self
+    }
+
+    /// Replaces the inherited root shape.
+    pub fn with_root(mut self, root: impl Shape + 'static) -> Self {
+        self.language.root = Some(Box::new(root));
+        self
+    }
+
+    pub fn build(self) -> LayeredLanguage {
+        self.language
+    }
+}
+
+fn base_macros(base: &Arc<dyn Language>) -> Vec<Box<dyn Macro>> {
+    (0..base.macros().len())
+        .map(|index| {
+            Box::new(BaseMacro {
+                language: base.clone(),
+                index,
+            }) as Box<dyn Macro>
+        })
+        .collect()
+}
+
+/// A language built by [`LanguageBuilder`].
+pub struct LayeredLanguage {
+    base: Arc<dyn Language>,
+    atoms: Vec<Box<dyn Atom>>,
+    delimiters: Vec<Delimiter>,
+    macros: Vec<Box<dyn Macro>>,
+    binding_pass: Option<Box<dyn BindingPass>>,
+    reference_pass: Option<Box<dyn ReferencePass>>,
+    root: Option<Box<dyn Shape>>,
+}
+
+impl Debug for LayeredLanguage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LayeredLanguage")
+            .field("atoms", &self.atoms)
+            .field("delimiters", &self.delimiters)
+            .field("macros", &self.macros)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Language for LayeredLanguage {
+    fn atoms(&self) -> &[Box<dyn Atom>] {
+        &self.atoms
+    }
+
+    fn delimiters(&self) -> &[Delimiter] {
+        &self.delimiters
+    }
+
+    fn macros(&self) -> &[Box<dyn Macro>] {
+        &self.macros
+    }
+
+    fn binding_pass(&self) -> &dyn BindingPass {
+        match &self.binding_pass {
+            Some(pass) => pass.as_ref(),
+            None => self.base.binding_pass(),
+        }
+    }
+
+    fn reference_pass(&self) -> &dyn ReferencePass {
+        match &self.reference_pass {
+            Some(pass) => pass.as_ref(),
+            None => self.base.reference_pass(),
+        }
+    }
+
+    fn modes(&self) -> &[LexerMode] {
+        self.base.modes()
+    }
+
+    fn lex_strategy(&self) -> LexStrategy {
+        self.base.lex_strategy()
+    }
+
+    fn offside_rule(&self) -> bool {
+        self.base.offside_rule()
+    }
+
+    fn attach_trivia(&self) -> bool {
+        self.base.attach_trivia()
+    }
+
+    fn is_trivia(&self, kind: &AtomKind) -> bool {
+        self.base.is_trivia(kind)
+    }
+
+    fn root(&self) -> Option<&dyn Shape> {
+        match &self.root {
+            Some(root) => Some(root.as_ref()),
+            None => self.base.root(),
+        }
+    }
+}
+
+/// An atom inherited from the base language.
+struct BaseAtom {
+    language: Arc<dyn Language>,
+    index: usize,
+}
+
+impl BaseAtom {
+    fn get(&self) -> &dyn Atom {
+        self.language.atoms()[self.index].as_ref()
+    }
+}
+
+impl Debug for BaseAtom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.get().fmt(f)
+    }
+}
+
+impl Atom for BaseAtom {
+    fn kind(&self) -> AtomKind {
+        self.get().kind()
+    }
+
+    fn parse<'a>(&self, input: Cursor<'a>) -> Option<(Token, Cursor<'a>)> {
+        self.get().parse(input)
+    }
+
+    fn highlight(&self, token: &Token, highlighter: &mut dyn Highlighter) {
+        self.get().highlight(token, highlighter)
+    }
+
+    fn is_opaque(&self) -> bool {
+        self.get().is_opaque()
+    }
+
+    fn priority(&self) -> i32 {
+        self.get().priority()
+    }
+
+    fn pattern(&self) -> Option<String> {
+        self.get().pattern()
+    }
+
+    fn pattern_is_exact(&self) -> bool {
+        self.get().pattern_is_exact()
+    }
+
+    fn diagnose(&self, token: &Token) -> Vec<LexError> {
+        self.get().diagnose(token)
+    }
+
+    fn literals(&self) -> Vec<&str> {
+        self.get().literals()
+    }
+
+    fn mode_transition(&self) -> Option<ModeTransition> {
+        self.get().mode_transition()
+    }
+}
+
+/// A macro inherited from the base language, or merged from another.
+struct BaseMacro {
+    language: Arc<dyn Language>,
+    index: usize,
+}
+
+impl BaseMacro {
+    fn get(&self) -> &dyn Macro {
+        self.language.macros()[self.index].as_ref()
+    }
+}
+
+impl Debug for BaseMacro {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.get().fmt(f)
+    }
+}
+
+impl Macro for BaseMacro {
+    fn name(&self) -> &str {
+        self.get().name()
+    }
+
+    fn signature(&self) -> &dyn Shape {
+        self.get().signature()
+    }
+
+    fn expand(
+        &self,
+        args: TokenTree,
+        lhs: Option<TokenTree>,
+        context: &MacroContext,
+    ) -> ExpansionResult {
+        self.get().expand(args, lhs, context)
+    }
+
+    fn is_operator(&self) -> bool {
+        self.get().is_operator()
+    }
+
+    fn precedence(&self) -> Precedence {
+        self.get().precedence()
+    }
+
+    fn associativity(&self) -> Associativity {
+        self.get().associativity()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atoms::{KeywordAtom, LiteralAtom, RegexAtom};
+    use crate::define_language;
+    use crate::lexer::lex;
+    use crate::parser::Parser;
+    use crate::shape::expr;
+    use crate::token::TokenStream;
+
+    /// An infix operator that expands to `(lhs op rhs)`, tagged so tests can
+    /// tell which layer it came from.
+    #[derive(Debug)]
+    struct Infix {
+        name: &'static str,
+        precedence: u32,
+        tag: &'static str,
+        shape: Box<dyn Shape>,
+    }
+
+    fn infix(name: &'static str, precedence: u32, tag: &'static str) -> Infix {
+        Infix {
+            name,
+            precedence,
+            tag,
+            shape: Box::new(expr(Precedence(precedence))),
+        }
+    }
+
+    impl Macro for Infix {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn signature(&self) -> &dyn Shape {
+            self.shape.as_ref()
+        }
+
+        fn expand(
+            &self,
+            args: TokenTree,
+            lhs: Option<TokenTree>,
+            _context: &MacroContext,
+        ) -> ExpansionResult {
+            let tag = Token::new(AtomKind::Other("tag".into()), self.tag, 0);
+            ExpansionResult::Ok(TokenTree::Group(vec![
+                lhs.unwrap(),
+                TokenTree::Token(tag),
+                args,
+            ]))
+        }
+
+        fn is_operator(&self) -> bool {
+            true
+        }
+
+        fn precedence(&self) -> Precedence {
+            Precedence(self.precedence)
+        }
+    }
+
+    define_language! {
+        struct Base;
+        atoms = [
+            atom Whitespace = regex r"\s+",
+            atom Identifier = regex r"[a-z]+",
+            atom Number = regex r"[0-9]+",
+            atom Operator = "+",
+            atom Operator = "*",
+        ];
+        delimiters = [
+            delimiter "paren" = "(", ")",
+            delimiter "bracket" = "[", "]",
+        ];
+        macros = [infix("+", 10, "base+"), infix("*", 20, "base*")];
+    }
+
+    fn render(tree: &TokenTree) -> String {
+        match tree {
+            TokenTree::Token(t) => t.text.clone(),
+            TokenTree::Group(items) => {
+                let items: Vec<_> = items.iter().map(render).collect();
+                format!("({})", items.join(" "))
+            }
+            other => other.text(),
+        }
+    }
+
+    fn parse(language: &LayeredLanguage, input: &str) -> String {
+        let trees = lex(input, language);
+        let mut parser = Parser::new(TokenStream::new(&trees), language);
+        render(&parser.parse().unwrap())
+    }
+
+    fn kinds(language: &LayeredLanguage, input: &str) -> Vec<(String, String)> {
+        lex(input, language)
+            .iter()
+            .filter_map(|tree| match tree {
+                TokenTree::Token(t) if !t.kind.is_trivia() => {
+                    Some((t.kind.to_string(), t.text.clone()))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_added_atoms_come_before_inherited_ones() {
+        let language = LanguageBuilder::new(Base::new())
+            .with_atom(KeywordAtom::new(&["fn"]))
+            .with_atom(RegexAtom::new(
+                AtomKind::Other("hex".into()),
+                r"0x[0-9a-f]+",
+            ))
+            .build();
+
+        assert_eq!(language.atoms().len(), 7);
+        assert_eq!(
+            kinds(&language, "fn 0xff 12"),
+            [
+                ("identifier".into(), "fn".into()),
+                ("hex".into(), "0xff".into()),
+                ("number".into(), "12".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_override_and_remove_atoms_by_kind() {
+        let language = LanguageBuilder::new(Base::new())
+            .override_atom(RegexAtom::new(AtomKind::Number, r"[0-9_]+"))
+            .without_atoms(&AtomKind::Operator)
+            .with_atom(LiteralAtom::new(AtomKind::Operator, "-"))
+            .build();
+
+        assert_eq!(language.atoms().len(), 4);
+        assert_eq!(
+            kinds(&language, "1_000 - a"),
+            [
+                ("number".into(), "1_000".into()),
+                ("operator".into(), "-".into()),
+                ("identifier".into(), "a".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_delimiters_replace_by_kind() {
+        let language = LanguageBuilder::new(Base::new())
+            .with_delimiter(Delimiter::new("paren", "<", ">"))
+            .with_delimiter(Delimiter::new("brace", "{", "}"))
+            .without_delimiter("bracket")
+            .build();
+
+        let delimiters: Vec<_> = language
+            .delimiters()
+            .iter()
+            .map(|d| (d.kind.as_str(), d.open.as_str()))
+            .collect();
+        assert_eq!(delimiters, [("paren", "<"), ("brace", "{")]);
+    }
+
+    #[test]
+    fn test_macro_tables_merge() {
+        define_language! {
+            struct Extras;
+            atoms = [];
+            delimiters = [];
+            macros = [infix("*", 20, "extra*"), infix("^", 30, "extra^")];
+        }
+
+        let language = LanguageBuilder::new(Base::new())
+            .with_atom(LiteralAtom::new(AtomKind::Operator, "^"))
+            .with_macros_from(Extras::new())
+            .with_macro(Box::new(infix("+", 5, "dialect+")))
+            .build();
+
+        assert_eq!(language.macros().len(), 3);
+        assert_eq!(
+            parse(&language, "a + b * c ^ d"),
+            "(a dialect+ (b extra* (c extra^ d)))"
+        );
+
+        let language = LanguageBuilder::new(Base::new()).without_macro("*").build();
+        assert_eq!(language.macros().len(), 1);
+    }
+
+    #[test]
+    fn test_dialects_share_a_base() {
+        let base = Arc::new(Base::new());
+        let first = LanguageBuilder::new(base.clone())
+            .with_atom(LiteralAtom::new(AtomKind::Operator, "&&"))
+            .build();
+        let second = LanguageBuilder::new(base.clone()).build();
+        let stacked = LanguageBuilder::new(first)
+            .with_macro(Box::new(infix("&&", 1, "and")))
+            .build();
+
+        assert_eq!(parse(&second, "a + b"), "(a base+ b)");
+        assert_eq!(parse(&stacked, "a && b + c"), "(a and (b base+ c))");
+    }
+}
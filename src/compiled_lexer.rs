@@ -0,0 +1,389 @@
+//! A lexer that precompiles a language's declarative atoms.
+//!
+//! The plain [`crate::lexer::lex`] tries every atom at every position, each with its
+//! own anchored regex search. [`CompiledLexer`] instead fuses the patterns of
+//! neighbouring atoms (see [`Atom::pattern`]) into a single multi-pattern automaton,
+//! so one anchored search finds the atom that matches. Atoms whose pattern is exact
+//! (see [`Atom::pattern_is_exact`]) take their token straight from that match; any
+//! other candidate is asked to `parse`, which keeps the results identical to the
+//! plain lexer.
+//!
+//! Opaque atoms and the others are fused separately, since the opaque ones are
+//! tried first.
+//!
+//! Custom atoms without a pattern split the fused runs and are tried on their own,
+//! in declaration order.
+
+use crate::atom::Atom;
+use crate::language::Language;
+use crate::lexer::{LexError, Lexer};
+use crate::source_map::{FileId, SourceMap};
+use crate::token::{Cursor, TokenTree};
+use regex_automata::hybrid::dfa::DFA;
+use regex_automata::meta::Regex;
+use regex_automata::util::start;
+use regex_automata::{Anchored, Input, MatchKind, PatternSet};
+use std::ops::ControlFlow;
+
+/// A lexer for a language whose atoms have been compiled ahead of time.
+///
+/// Building one is relatively expensive, so create it once and reuse it.
+///
+/// ```rust
+/// use mcparse::compiled_lexer::CompiledLexer;
+/// use mcparse::define_language;
+///
+/// define_language! {
+///     struct Config;
+///     atoms = [
+///         atom Whitespace = regex r"\s+",
+///         atom Identifier = regex r"[a-zA-Z_]\w*",
+///         atom Operator = "=",
+///     ];
+///     delimiters = [];
+/// }
+///
+/// let language = Config::new();
+/// let lexer = CompiledLexer::new(&language);
+/// assert_eq!(lexer.lex("a = b").len(), 5);
+/// ```
+pub struct CompiledLexer<'l, L: Language + ?Sized> {
+    language: &'l L,
+    /// The root mode's table, followed by one for each of `language.modes()`.
+    dispatch: Vec<AtomDispatch>,
+}
+
+impl<'l, L: Language + ?Sized> CompiledLexer<'l, L> {
+    pub fn new(language: &'l L) -> Self {
+        let mut dispatch = vec![AtomDispatch::new(language.atoms())];
+        dispatch.extend(
+            language
+                .modes()
+                .iter()
+                .map(|mode| AtomDispatch::new(&mode.atoms)),
+        );
+        Self { language, dispatch }
+    }
+
+    /// Converts a raw string into a list of `TokenTree`s, like [`crate::lexer::lex`].
+    pub fn lex(&self, input: &str) -> Vec<TokenTree> {
+        self.lex_with_diagnostics(input).0
+    }
+
+    /// Like [`CompiledLexer::lex`], but also returns the diagnostics found while lexing.
+    pub fn lex_with_diagnostics(&self, input: &str) -> (Vec<TokenTree>, Vec<LexError>) {
+        Lexer::with_dispatch(self.language, &self.dispatch).lex_with_diagnostics(Cursor::new(input))
+    }
+
+    /// Lexes one file of a [`SourceMap`], like [`crate::lexer::lex_file`].
+    pub fn lex_file(&self, files: &SourceMap, file: FileId) -> (Vec<TokenTree>, Vec<LexError>) {
+        Lexer::with_dispatch(self.language, &self.dispatch)
+            .in_file(file)
+            .lex_with_diagnostics(files.cursor(file))
+    }
+
+    /// Lexes `input` starting in the named lexer mode, like [`crate::lexer::lex_in_mode`].
+    pub fn lex_in_mode(&self, input: &str, mode: &str) -> Vec<TokenTree> {
+        Lexer::with_dispatch(self.language, &self.dispatch).lex_in_mode(input, mode)
+    }
+}
+
+/// The candidate lookup for the atoms of one lexer mode.
+#[derive(Debug)]
+pub(crate) struct AtomDispatch {
+    opaque: Vec<Segment>,
+    other: Vec<Segment>,
+}
+
+/// An atom that may match, as reported by [`AtomDispatch::for_each_candidate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Candidate {
+    pub(crate) index: usize,
+    /// The length of the atom's pattern match, if the search found it.
+    pub(crate) len: Option<usize>,
+}
+
+impl Candidate {
+    pub(crate) fn new(index: usize) -> Self {
+        Self { index, len: None }
+    }
+}
+
+#[derive(Debug)]
+enum Segment {
+    /// A run of atoms with patterns. `atoms[i]` is the index of the atom for
+    /// pattern `i` of both automata.
+    Fused {
+        /// Reports the first pattern, in declaration order, that matches.
+        first: Regex,
+        /// Reports every pattern that matches.
+        all: Regex,
+        atoms: Vec<usize>,
+        /// Whether any pattern can match input starting with each byte, so most
+        /// positions can skip the search entirely.
+        first_bytes: Box<[bool; 256]>,
+    },
+    /// An atom tried on its own.
+    Single(usize),
+}
+
+impl AtomDispatch {
+    fn new(atoms: &[Box<dyn Atom>]) -> Self {
+        Self {
+            opaque: Self::segments(atoms, |atom| atom.is_opaque()),
+            other: Self::segments(atoms, |atom| !atom.is_opaque()),
+        }
+    }
+
+    fn segments(atoms: &[Box<dyn Atom>], filter: impl Fn(&dyn Atom) -> bool) -> Vec<Segment> {
+        let mut segments = Vec::new();
+        let mut run: Vec<(usize, String)> = Vec::new();
+
+        for (index, atom) in atoms.iter().enumerate() {
+            if !filter(atom.as_ref()) {
+                continue;
+            }
+            match atom.pattern() {
+                Some(pattern) => run.push((index, pattern)),
+                None => {
+                    Self::flush(&mut run, &mut segments);
+                    segments.push(Segment::Single(index));
+                }
+            }
+        }
+        Self::flush(&mut run, &mut segments);
+
+        segments
+    }
+
+    fn flush(run: &mut Vec<(usize, String)>, segments: &mut Vec<Segment>) {
+        let (atoms, patterns): (Vec<usize>, Vec<String>) = run.drain(..).unzip();
+        let build = |kind: MatchKind| {
+            Regex::builder()
+                .configure(Regex::config().match_kind(kind))
+                .build_many(&patterns)
+                .ok()
+        };
+
+        if atoms.len() > 1
+            && let (Some(first), Some(all)) =
+                (build(MatchKind::LeftmostFirst), build(MatchKind::All))
+        {
+            let first_bytes = first_bytes(&patterns);
+            segments.push(Segment::Fused {
+                first,
+                all,
+                atoms,
+                first_bytes,
+            });
+        } else {
+            // A single atom gains nothing from fusing, and patterns that fail to
+            // compile together (e.g. too large) degrade to the per-atom path.
+            segments.extend(atoms.into_iter().map(Segment::Single));
+        }
+    }
+
+    /// Calls `f` with each atom that may match at the start of `rest`, until `f`
+    /// breaks: the opaque atoms first, then the others, each in declaration order.
+    ///
+    /// With `all_candidates` unset, only the first matching atom of each fused run
+    /// is reported, with the length of its match, followed by the rest of the run
+    /// if `f` rejects it.
+    pub(crate) fn for_each_candidate(
+        &self,
+        rest: &str,
+        all_candidates: bool,
+        f: &mut impl FnMut(Candidate) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        let input = Input::new(rest).anchored(Anchored::Yes);
+        let Some(&byte) = rest.as_bytes().first() else {
+            return ControlFlow::Continue(());
+        };
+
+        for segment in self.opaque.iter().chain(&self.other) {
+            match segment {
+                Segment::Fused { first_bytes, .. } if !first_bytes[byte as usize] => {}
+                Segment::Fused { all, atoms, .. } if all_candidates => {
+                    let mut matches = PatternSet::new(atoms.len());
+                    all.which_overlapping_matches(&input, &mut matches);
+                    for pattern in matches.iter() {
+                        f(Candidate::new(atoms[pattern.as_usize()]))?;
+                    }
+                }
+                Segment::Fused { first, atoms, .. } => {
+                    if let Some(found) = first.search_half(&input) {
+                        let pattern = found.pattern().as_usize();
+                        f(Candidate {
+                            index: atoms[pattern],
+                            len: Some(found.offset()),
+                        })?;
+                        // `parse` may still reject the match (e.g. an empty one), in
+                        // which case the later atoms of the run get their turn.
+                        for &index in &atoms[pattern + 1..] {
+                            f(Candidate::new(index))?;
+                        }
+                    }
+                }
+                Segment::Single(index) => f(Candidate::new(*index))?,
+            }
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// Computes which first bytes can begin a non-empty match of any of `patterns`,
+/// by stepping a lazy DFA one byte from its anchored start state.
+///
+/// If the DFA can't be built, every byte is allowed.
+fn first_bytes(patterns: &[String]) -> Box<[bool; 256]> {
+    let mut table = Box::new([true; 256]);
+    let Ok(dfa) = DFA::new_many(patterns) else {
+        return table;
+    };
+
+    let mut cache = dfa.create_cache();
+    let config = start::Config::new().anchored(Anchored::Yes);
+    let Ok(start) = dfa.start_state(&mut cache, &config) else {
+        return table;
+    };
+    for (byte, allowed) in table.iter_mut().enumerate() {
+        match dfa.next_state(&mut cache, start, byte as u8) {
+            Ok(next) => *allowed = !next.is_dead(),
+            Err(_) => return Box::new([true; 256]),
+        }
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atom::AtomKind;
+    use crate::atoms::{
+        BlockCommentAtom, IdentifierAtom, KeywordAtom, LineCommentAtom, LiteralAtom, NumberAtom,
+        RawStringAtom, RegexAtom, StringAtom,
+    };
+    use crate::language::{Delimiter, LexStrategy, LexerMode};
+    use crate::lexer::lex;
+    use crate::mock::MockLanguage;
+
+    fn assert_same_as_lex(language: &MockLanguage, input: &str) {
+        let expected: Vec<String> = lex(input, language)
+            .iter()
+            .map(|t| format!("{:?}", t))
+            .collect();
+        let actual: Vec<String> = CompiledLexer::new(language)
+            .lex(input)
+            .iter()
+            .map(|t| format!("{:?}", t))
+            .collect();
+        assert_eq!(actual, expected);
+    }
+
+    fn declarative_language() -> MockLanguage {
+        MockLanguage::new()
+            .with_atom(Box::new(RegexAtom::new(AtomKind::Number, r"\d+")))
+            .with_atom(Box::new(LiteralAtom::new(AtomKind::Operator, "==")))
+            .with_atom(Box::new(LiteralAtom::new(AtomKind::Operator, "=")))
+            .with_atom(Box::new(KeywordAtom::new(&["if", "in", "int"])))
+            .with_atom(Box::new(
+                RegexAtom::new(AtomKind::String, r#""[^"]*""#).opaque(),
+            ))
+    }
+
+    #[test]
+    fn test_compiled_matches_plain_lexer() {
+        let lang = declarative_language();
+        assert_same_as_lex(&lang, r#"if x == 10 (int = "(" in y) ?? letter"#);
+    }
+
+    #[test]
+    fn test_compiled_matches_plain_lexer_longest_match() {
+        let lang = declarative_language().with_lex_strategy(LexStrategy::LongestMatch);
+        assert_same_as_lex(&lang, r#"if x == 10 (int = "(" in y) ?? interior"#);
+    }
+
+    #[test]
+    fn test_compiled_matches_plain_lexer_with_standard_atoms() {
+        let lang = MockLanguage::new()
+            .with_atom(Box::new(IdentifierAtom))
+            .with_atom(Box::new(NumberAtom::new()))
+            .with_atom(Box::new(BlockCommentAtom::new("/*", "*/").nested()))
+            .with_atom(Box::new(LineCommentAtom::new("//")))
+            .with_atom(Box::new(StringAtom::new(&['"', '\''])))
+            .with_atom(Box::new(RawStringAtom::new("r")));
+        assert_same_as_lex(
+            &lang,
+            "r#\"(\"# r größe (0x1F 1.5e3 '\\'') /* (/* */ */ // )\n\"a\\q",
+        );
+    }
+
+    #[test]
+    fn test_compiled_matches_plain_lexer_in_modes() {
+        let lang = MockLanguage::new()
+            .with_delimiter(Delimiter::new("string", "\"", "\"").with_mode("string"))
+            .with_mode(
+                LexerMode::new("string")
+                    .with_atom(RegexAtom::new(AtomKind::String, r#"[^"$]+"#))
+                    .with_atom(LiteralAtom::new(AtomKind::Operator, "$")),
+            );
+        assert_same_as_lex(&lang, r#"a "b $ c" d"#);
+    }
+
+    #[test]
+    fn test_compiled_builds_tokens_from_matches() {
+        // Later atoms get their turn after an empty match, and the opaque comment
+        // wins over the operator declared before it.
+        let lang = MockLanguage::new()
+            .with_atom(Box::new(
+                RegexAtom::new(AtomKind::Comment, r"//[^\n]*").opaque(),
+            ))
+            .with_atom(Box::new(LiteralAtom::new(AtomKind::Operator, "/")))
+            .with_atom(Box::new(LiteralAtom::new(AtomKind::Operator, "+")))
+            .with_atom(Box::new(RegexAtom::new(AtomKind::Number, r"\d*")));
+        assert_same_as_lex(&lang, "1 + 22 / 3 // (\n+");
+
+        let trees = CompiledLexer::new(&lang).lex("12/ //");
+        let texts: Vec<_> = trees
+            .iter()
+            .map(|t| match t {
+                TokenTree::Token(t) => (t.kind.clone(), t.text.as_str()),
+                other => panic!("unexpected tree {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            texts,
+            [
+                (AtomKind::Number, "12"),
+                (AtomKind::Operator, "/"),
+                (AtomKind::Whitespace, " "),
+                (AtomKind::Comment, "//"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_custom_atoms_split_fused_runs() {
+        let lang = declarative_language();
+        let dispatch = AtomDispatch::new(lang.atoms());
+
+        // Whitespace, the declarative atoms and the keyword atom, then the mock
+        // identifier. The opaque string atom is fused on its own.
+        assert!(matches!(dispatch.other[0], Segment::Single(0)));
+        assert!(
+            matches!(&dispatch.other[1], Segment::Fused { atoms, .. } if atoms == &[2, 3, 4, 5, 6])
+        );
+        assert!(matches!(dispatch.other[2], Segment::Single(7)));
+        assert!(matches!(dispatch.opaque[..], [Segment::Single(1)]));
+    }
+
+    #[test]
+    fn test_first_bytes() {
+        let table = first_bytes(&["^(?:[a-c]+)".to_string(), "^=".to_string()]);
+        assert!(table[b'a' as usize]);
+        assert!(table[b'c' as usize]);
+        assert!(table[b'=' as usize]);
+        assert!(!table[b'd' as usize]);
+        assert!(!table[b' ' as usize]);
+    }
+}
@@ -3,7 +3,7 @@ use crate::atom::AtomKind;
 use crate::language::Language;
 use crate::scoping::ScopeStack;
 use crate::shape::{CompletionItem, CompletionKind};
-use crate::token::{Token, TokenTree};
+use crate::token::{SyntaxNode, Token, TokenTree};
 
 pub fn find_completions(
     tokens: &[TokenTree],
@@ -55,18 +55,23 @@ fn find_token_at_offset(tokens: &[TokenTree], offset: usize) -> Option<&Token> {
                     return Some(t);
                 }
             }
-            TokenTree::Delimited(_, children, loc, _) => {
-                if loc.span.offset() <= offset && offset <= loc.span.offset() + loc.span.len() {
-                    if let Some(t) = find_token_at_offset(children, offset) {
-                        return Some(t);
-                    }
+            TokenTree::Delimited(_, children, loc, _)
+                if loc.span.offset() <= offset && offset <= loc.span.offset() + loc.span.len() =>
+            {
+                if let Some(t) = find_token_at_offset(children, offset) {
+                    return Some(t);
                 }
             }
-            TokenTree::Group(children) => {
+            TokenTree::Group(children) | TokenTree::Node(SyntaxNode { children, .. }) => {
                 if let Some(t) = find_token_at_offset(children, offset) {
                     return Some(t);
                 }
             }
+            TokenTree::Field(_, value) => {
+                if let Some(t) = find_token_at_offset(std::slice::from_ref(value), offset) {
+                    return Some(t);
+                }
+            }
             _ => {}
         }
     }
@@ -0,0 +1,1076 @@
+//! Languages whose syntax is loaded from a grammar file at runtime.
+//!
+//! [`DynamicLanguage::from_json`] reads a JSON description of atoms,
+//! delimiters, scoping and macros. Mistakes in the file are reported as
+//! [`GrammarError`]s whose spans point into the grammar source, so they can be
+//! rendered with `miette` like any other diagnostic.
+
+use crate::atom::{Atom, AtomKind};
+use crate::atoms::{KeywordAtom, LiteralAtom, NumberAtom, RegexAtom, StringAtom};
+use crate::language::{Delimiter, Language, LexStrategy};
+use crate::lexer::{LexError, lex_with_diagnostics};
+use crate::r#macro::{ExpansionResult, Macro, MacroContext};
+use crate::scoping::{
+    BindingPass, NoOpBindingPass, NoOpReferencePass, ReferencePass, SimpleBindingPass,
+    SimpleReferencePass,
+};
+use crate::shape::{Associativity, Precedence, Shape, choice, empty, enter, expr, rep, seq, term};
+use crate::token::TokenTree;
+use miette::{Diagnostic, SourceSpan};
+use regex::Regex;
+use thiserror::Error;
+
+/// A problem in a grammar file.
+///
+/// Every span is an offset into the grammar source passed to
+/// [`DynamicLanguage::from_json`].
+#[derive(Debug, Clone, PartialEq, Eq, Error, Diagnostic)]
+pub enum GrammarError {
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Lex(#[from] LexError),
+    #[error("{message}")]
+    Syntax {
+        message: String,
+        #[label("here")]
+        span: SourceSpan,
+    },
+    #[error("missing field `{field}`")]
+    MissingField {
+        field: &'static str,
+        #[label("this object needs `{field}`")]
+        span: SourceSpan,
+    },
+    #[error("unknown field `{field}`")]
+    UnknownField {
+        field: String,
+        #[label("not recognised here")]
+        span: SourceSpan,
+    },
+    #[error("expected {expected}")]
+    InvalidType {
+        expected: &'static str,
+        #[label("expected {expected}")]
+        span: SourceSpan,
+    },
+    #[error("invalid regex")]
+    InvalidRegex {
+        #[help]
+        message: String,
+        #[label("invalid regex")]
+        span: SourceSpan,
+    },
+    #[error("unknown delimiter `{kind}`")]
+    UnknownDelimiter {
+        kind: String,
+        #[label("not defined in `delimiters`")]
+        span: SourceSpan,
+    },
+    #[error("{message}")]
+    InvalidValue {
+        message: String,
+        #[label("here")]
+        span: SourceSpan,
+    },
+}
+
+/// A [`Language`] loaded from a grammar file.
+///
+/// The grammar is a JSON object with these optional fields:
+///
+/// - `atoms`: a list of `{"regex": .., "kind": ..}`, `{"literal": ..}` or
+///   `{"keywords": [..]}` objects. Regex atoms may set `opaque`, keyword atoms
+///   may set `ignore_case` (`"ascii"` or `"unicode"`) and `boundary`, and all
+///   of them may set `priority`, which overrides the atom's default.
+/// - `lex_strategy`: `"first"` (the default) or `"longest"`, see
+///   [`LexStrategy`]. Atom priorities only matter under `"longest"`.
+/// - `delimiters`: a list of `{"kind": .., "open": .., "close": ..}`.
+/// - `binding_keyword`: the keyword that introduces a variable, scoped with
+///   [`SimpleBindingPass`].
+/// - `operators`: a list of `{"name": .., "precedence": ..}`, with optional
+///   `associativity` (`"left"`, `"right"` or `"none"`) and `signature`.
+/// - `macros`: a list of prefix macros, `{"name": .., "signature": ..}`.
+/// - `root`: the shape of a whole program (see [`Language::root`]).
+///
+/// A signature is a shape: a string matches that literal text, a list is a
+/// sequence, and objects select the other combinators: `{"kind": ..}`,
+/// `{"expr": precedence}`, `{"enter": delimiter, "shape": ..}`,
+/// `{"opt": ..}`, `{"rep": ..}` and `{"separated": .., "by": ..}`.
+///
+/// Operators expand to a group of the left-hand side, the operator token and
+/// the matched arguments; prefix macros expand to a group of the macro name and
+/// the arguments.
+#[derive(Debug)]
+pub struct DynamicLanguage {
+    atoms: Vec<Box<dyn Atom>>,
+    delimiters: Vec<Delimiter>,
+    macros: Vec<Box<dyn Macro>>,
+    binding_pass: Box<dyn BindingPass>,
+    reference_pass: Box<dyn ReferencePass>,
+    root: Option<Box<dyn Shape>>,
+    lex_strategy: LexStrategy,
+}
+
+impl DynamicLanguage {
+    /// Loads a language from the JSON grammar in `source`.
+    ///
+    /// Returns every problem found in the grammar if it cannot be loaded.
+    pub fn from_json(source: &str) -> Result<Self, Vec<GrammarError>> {
+        let mut errors = Vec::new();
+        let language = read_json(source, &mut errors).and_then(|root| load(&root, &mut errors));
+        match language {
+            Some(language) if errors.is_empty() => Ok(language),
+            _ => Err(errors),
+        }
+    }
+}
+
+impl Language for DynamicLanguage {
+    fn atoms(&self) -> &[Box<dyn Atom>] {
+        &self.atoms
+    }
+
+    fn delimiters(&self) -> &[Delimiter] {
+        &self.delimiters
+    }
+
+    fn macros(&self) -> &[Box<dyn Macro>] {
+        &self.macros
+    }
+
+    fn binding_pass(&self) -> &dyn BindingPass {
+        self.binding_pass.as_ref()
+    }
+
+    fn reference_pass(&self) -> &dyn ReferencePass {
+        self.reference_pass.as_ref()
+    }
+
+    fn lex_strategy(&self) -> LexStrategy {
+        self.lex_strategy
+    }
+
+    fn root(&self) -> Option<&dyn Shape> {
+        self.root.as_deref()
+    }
+}
+
+#[derive(Debug)]
+struct DynamicMacro {
+    name: String,
+    signature: Box<dyn Shape>,
+    operator: Option<(Precedence, Associativity)>,
+}
+
+impl Macro for DynamicMacro {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn signature(&self) -> &dyn Shape {
+        self.signature.as_ref()
+    }
+
+    fn expand(
+        &self,
+        args: TokenTree,
+        lhs: Option<TokenTree>,
+        context: &MacroContext,
+    ) -> ExpansionResult {
+        let mut items: Vec<TokenTree> = lhs.into_iter().collect();
+        items.extend(context.name.clone().map(TokenTree::Token));
+        items.push(args);
+        ExpansionResult::Ok(TokenTree::Group(items))
+    }
+
+    fn is_operator(&self) -> bool {
+        self.operator.is_some()
+    }
+
+    fn precedence(&self) -> Precedence {
+        self.operator
+            .map_or(Precedence(0), |(precedence, _)| precedence)
+    }
+
+    fn associativity(&self) -> Associativity {
+        self.operator
+            .map_or(Associativity::Left, |(_, associativity)| associativity)
+    }
+}
+
+fn load(root: &Json, errors: &mut Vec<GrammarError>) -> Option<DynamicLanguage> {
+    let grammar = root.as_object(errors)?;
+    grammar.deny_unknown(
+        &[
+            "atoms",
+            "lex_strategy",
+            "delimiters",
+            "binding_keyword",
+            "operators",
+            "macros",
+            "root",
+        ],
+        errors,
+    );
+
+    let lex_strategy = match grammar.get("lex_strategy") {
+        Some(strategy) => match strategy.as_str(errors)? {
+            "first" => LexStrategy::FirstMatch,
+            "longest" => LexStrategy::LongestMatch,
+            _ => {
+                errors.push(strategy.invalid("expected `first` or `longest`"));
+                return None;
+            }
+        },
+        None => LexStrategy::default(),
+    };
+    let atoms = grammar
+        .list("atoms", errors)
+        .iter()
+        .filter_map(|atom| load_atom(atom, errors))
+        .collect();
+    let delimiters: Vec<Delimiter> = grammar
+        .list("delimiters", errors)
+        .iter()
+        .filter_map(|delimiter| load_delimiter(delimiter, errors))
+        .collect();
+
+    let mut macros: Vec<Box<dyn Macro>> = Vec::new();
+    for operator in grammar.list("operators", errors) {
+        macros.extend(load_operator(operator, &delimiters, errors));
+    }
+    for mac in grammar.list("macros", errors) {
+        macros.extend(load_macro(mac, &delimiters, errors));
+    }
+
+    let root = match grammar.get("root") {
+        Some(root) => Some(load_shape(root, &delimiters, errors)?),
+        None => None,
+    };
+
+    let (binding_pass, reference_pass): (Box<dyn BindingPass>, Box<dyn ReferencePass>) =
+        match grammar.get("binding_keyword") {
+            Some(keyword) => (
+                Box::new(SimpleBindingPass::new(keyword.as_str(errors)?)),
+                Box::new(SimpleReferencePass),
+            ),
+            None => (Box::new(NoOpBindingPass), Box::new(NoOpReferencePass)),
+        };
+
+    Some(DynamicLanguage {
+        atoms,
+        delimiters,
+        macros,
+        binding_pass,
+        reference_pass,
+        root,
+        lex_strategy,
+    })
+}
+
+fn load_atom(json: &Json, errors: &mut Vec<GrammarError>) -> Option<Box<dyn Atom>> {
+    let atom = json.as_object(errors)?;
+    let kind = match atom.get("kind") {
+        Some(kind) => Some(atom_kind(kind.as_str(errors)?)),
+        None => None,
+    };
+    let priority: Option<i32> = match atom.get("priority") {
+        Some(priority) => Some(priority.as_integer(errors)?),
+        None => None,
+    };
+
+    match (atom.get("regex"), atom.get("literal"), atom.get("keywords")) {
+        (Some(regex), None, None) => {
+            atom.deny_unknown(&["regex", "kind", "priority", "opaque"], errors);
+            let pattern = regex.as_str(errors)?;
+            if let Err(error) = Regex::new(&format!("^(?:{})", pattern)) {
+                errors.push(GrammarError::InvalidRegex {
+                    message: error.to_string(),
+                    span: regex.span,
+                });
+                return None;
+            }
+            let Some(kind) = kind else {
+                errors.push(GrammarError::MissingField {
+                    field: "kind",
+                    span: atom.span,
+                });
+                return None;
+            };
+            let mut regex = RegexAtom::new(kind, pattern);
+            if let Some(priority) = priority {
+                regex = regex.with_priority(priority);
+            }
+            if let Some(opaque) = atom.get("opaque")
+                && opaque.as_bool(errors)?
+            {
+                regex = regex.opaque();
+            }
+            Some(Box::new(regex))
+        }
+        (None, Some(literal), None) => {
+            atom.deny_unknown(&["literal", "kind", "priority"], errors);
+            let literal = literal.as_str(errors)?;
+            let kind = kind.unwrap_or(AtomKind::Operator);
+            let mut literal = LiteralAtom::new(kind, literal);
+            if let Some(priority) = priority {
+                literal = literal.with_priority(priority);
+            }
+            Some(Box::new(literal))
+        }
+        (None, None, Some(keywords)) => {
+            atom.deny_unknown(&["keywords", "priority", "ignore_case", "boundary"], errors);
+            let keywords = keywords
+                .as_array(errors)?
+                .iter()
+                .map(|keyword| keyword.as_str(errors))
+                .collect::<Option<Vec<_>>>()?;
+            let mut keywords = KeywordAtom::new(&keywords);
+            if let Some(priority) = priority {
+                keywords = keywords.with_priority(priority);
+            }
+            if let Some(case) = atom.get("ignore_case") {
+                keywords = match case.as_str(errors)? {
+                    "ascii" => keywords.ignore_ascii_case(),
+                    "unicode" => keywords.ignore_case(),
+                    _ => {
+                        errors.push(case.invalid("expected `ascii` or `unicode`"));
+                        return None;
+                    }
+                };
+            }
+            if let Some(boundary) = atom.get("boundary")
+                && !boundary.as_bool(errors)?
+            {
+                keywords = keywords.no_boundary();
+            }
+            Some(Box::new(keywords))
+        }
+        _ => {
+            errors.push(GrammarError::InvalidValue {
+                message: "an atom needs exactly one of `regex`, `literal` or `keywords`".into(),
+                span: atom.span,
+            });
+            None
+        }
+    }
+}
+
+fn atom_kind(name: &str) -> AtomKind {
+    match name {
+        "whitespace" => AtomKind::Whitespace,
+        "comment" => AtomKind::Comment,
+        "identifier" => AtomKind::Identifier,
+        "string" => AtomKind::String,
+        "number" => AtomKind::Number,
+        "operator" => AtomKind::Operator,
+        other => AtomKind::Other(other.to_string()),
+    }
+}
+
+fn load_delimiter(json: &Json, errors: &mut Vec<GrammarError>) -> Option<Delimiter> {
+    let delimiter = json.as_object(errors)?;
+    delimiter.deny_unknown(&["kind", "open", "close"], errors);
+    let kind = delimiter
+        .require("kind", errors)
+        .and_then(|kind| kind.as_str(errors));
+    let open = delimiter
+        .require("open", errors)
+        .and_then(|open| open.as_str(errors));
+    let close = delimiter
+        .require("close", errors)
+        .and_then(|close| close.as_str(errors));
+    Some(Delimiter::new(
+        kind?.to_string(),
+        open?.to_string(),
+        close?.to_string(),
+    ))
+}
+
+fn load_operator(
+    json: &Json,
+    delimiters: &[Delimiter],
+    errors: &mut Vec<GrammarError>,
+) -> Option<Box<dyn Macro>> {
+    let operator = json.as_object(errors)?;
+    operator.deny_unknown(
+        &["name", "precedence", "associativity", "signature"],
+        errors,
+    );
+    let name = operator
+        .require("name", errors)
+        .and_then(|name| name.as_str(errors));
+    let precedence = operator
+        .require("precedence", errors)
+        .and_then(|precedence| precedence.as_integer(errors))
+        .map(Precedence);
+    let associativity = match operator.get("associativity") {
+        Some(associativity) => match associativity.as_str(errors)? {
+            "left" => Associativity::Left,
+            "right" => Associativity::Right,
+            "none" => Associativity::None,
+            _ => {
+                errors.push(associativity.invalid("expected `left`, `right` or `none`"));
+                return None;
+            }
+        },
+        None => Associativity::Left,
+    };
+    let signature = match operator.get("signature") {
+        Some(signature) => load_shape(signature, delimiters, errors)?,
+        None => Box::new(expr(precedence?)),
+    };
+    Some(Box::new(DynamicMacro {
+        name: name?.to_string(),
+        signature,
+        operator: Some((precedence?, associativity)),
+    }))
+}
+
+fn load_macro(
+    json: &Json,
+    delimiters: &[Delimiter],
+    errors: &mut Vec<GrammarError>,
+) -> Option<Box<dyn Macro>> {
+    let mac = json.as_object(errors)?;
+    mac.deny_unknown(&["name", "signature"], errors);
+    let name = mac
+        .require("name", errors)
+        .and_then(|name| name.as_str(errors));
+    let signature = mac
+        .require("signature", errors)
+        .and_then(|signature| load_shape(signature, delimiters, errors));
+    Some(Box::new(DynamicMacro {
+        name: name?.to_string(),
+        signature: signature?,
+        operator: None,
+    }))
+}
+
+fn load_shape(
+    json: &Json,
+    delimiters: &[Delimiter],
+    errors: &mut Vec<GrammarError>,
+) -> Option<Box<dyn Shape>> {
+    match &json.value {
+        Value::String(text) => Some(Box::new(term(text.clone()))),
+        Value::Array(items) => {
+            let shapes: Vec<_> = items
+                .iter()
+                .map(|item| load_shape(item, delimiters, errors))
+                .collect();
+            let mut shapes = shapes
+                .into_iter()
+                .collect::<Option<Vec<_>>>()?
+                .into_iter()
+                .rev();
+            let last = shapes.next().unwrap_or_else(|| Box::new(empty()));
+            Some(shapes.fold(last, |rest, shape| Box::new(seq(shape, rest))))
+        }
+        Value::Object(_) => load_shape_object(json, delimiters, errors),
+        _ => {
+            errors.push(json.expected("a shape"));
+            None
+        }
+    }
+}
+
+fn load_shape_object(
+    json: &Json,
+    delimiters: &[Delimiter],
+    errors: &mut Vec<GrammarError>,
+) -> Option<Box<dyn Shape>> {
+    let shape = json.as_object(errors)?;
+    if let Some(kind) = shape.get("kind") {
+        shape.deny_unknown(&["kind"], errors);
+        Some(Box::new(term(atom_kind(kind.as_str(errors)?))))
+    } else if let Some(precedence) = shape.get("expr") {
+        shape.deny_unknown(&["expr"], errors);
+        Some(Box::new(expr(Precedence(precedence.as_integer(errors)?))))
+    } else if let Some(kind) = shape.get("enter") {
+        shape.deny_unknown(&["enter", "shape"], errors);
+        let inner = match shape.get("shape") {
+            Some(inner) => load_shape(inner, delimiters, errors),
+            None => Some(Box::new(empty()) as Box<dyn Shape>),
+        };
+        let name = kind.as_str(errors)?;
+        let Some(delimiter) = delimiters.iter().find(|d| d.kind == name) else {
+            errors.push(GrammarError::UnknownDelimiter {
+                kind: name.to_string(),
+                span: kind.span,
+            });
+            return None;
+        };
+        Some(Box::new(enter(delimiter.clone(), inner?)))
+    } else if let Some(inner) = shape.get("opt") {
+        shape.deny_unknown(&["opt"], errors);
+        Some(Box::new(choice(
+            load_shape(inner, delimiters, errors)?,
+            empty(),
+        )))
+    } else if let Some(inner) = shape.get("rep") {
+        shape.deny_unknown(&["rep"], errors);
+        Some(Box::new(rep(load_shape(inner, delimiters, errors)?)))
+    } else if let Some(item) = shape.get("separated") {
+        shape.deny_unknown(&["separated", "by"], errors);
+        let first = load_shape(item, delimiters, errors);
+        let separator = shape
+            .require("by", errors)
+            .and_then(|by| load_shape(by, delimiters, errors));
+        // Built a second time rather than cloned; its errors were reported above.
+        let rest = load_shape(item, delimiters, &mut Vec::new());
+        Some(Box::new(seq(first?, rep(seq(separator?, rest?)))))
+    } else {
+        errors.push(GrammarError::InvalidValue {
+            message: "expected one of `kind`, `expr`, `enter`, `opt`, `rep` or `separated`".into(),
+            span: shape.span,
+        });
+        None
+    }
+}
+
+/// A JSON value with the span it was read from.
+#[derive(Debug)]
+struct Json {
+    value: Value,
+    span: SourceSpan,
+}
+
+#[derive(Debug)]
+enum Value {
+    Null,
+    Bool(bool),
+    Integer(u128),
+    Float,
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<Field>),
+}
+
+#[derive(Debug)]
+struct Field {
+    name: String,
+    span: SourceSpan,
+    value: Json,
+}
+
+/// The fields of a JSON object, looked up by name.
+struct Object<'a> {
+    fields: &'a [Field],
+    span: SourceSpan,
+}
+
+impl<'a> Object<'a> {
+    fn get(&self, name: &str) -> Option<&'a Json> {
+        self.fields
+            .iter()
+            .find(|field| field.name == name)
+            .map(|field| &field.value)
+    }
+
+    fn require(&self, name: &'static str, errors: &mut Vec<GrammarError>) -> Option<&'a Json> {
+        let value = self.get(name);
+        if value.is_none() {
+            errors.push(GrammarError::MissingField {
+                field: name,
+                span: self.span,
+            });
+        }
+        value
+    }
+
+    /// The list in field `name`, or an empty list if it is absent.
+    fn list(&self, name: &str, errors: &mut Vec<GrammarError>) -> &'a [Json] {
+        self.get(name)
+            .and_then(|list| list.as_array(errors))
+            .unwrap_or_default()
+    }
+
+    fn deny_unknown(&self, known: &[&str], errors: &mut Vec<GrammarError>) {
+        for field in self.fields {
+            if !known.contains(&field.name.as_str()) {
+                errors.push(GrammarError::UnknownField {
+                    field: field.name.clone(),
+                    span: field.span,
+                });
+            }
+        }
+    }
+}
+
+impl Json {
+    fn expected(&self, expected: &'static str) -> GrammarError {
+        GrammarError::InvalidType {
+            expected,
+            span: self.span,
+        }
+    }
+
+    fn invalid(&self, message: &str) -> GrammarError {
+        GrammarError::InvalidValue {
+            message: message.to_string(),
+            span: self.span,
+        }
+    }
+
+    fn as_object(&self, errors: &mut Vec<GrammarError>) -> Option<Object<'_>> {
+        match &self.value {
+            Value::Object(fields) => Some(Object {
+                fields,
+                span: self.span,
+            }),
+            _ => {
+                errors.push(self.expected("an object"));
+                None
+            }
+        }
+    }
+
+    fn as_array(&self, errors: &mut Vec<GrammarError>) -> Option<&[Json]> {
+        match &self.value {
+            Value::Array(items) => Some(items),
+            _ => {
+                errors.push(self.expected("a list"));
+                None
+            }
+        }
+    }
+
+    fn as_str(&self, errors: &mut Vec<GrammarError>) -> Option<&str> {
+        match &self.value {
+            Value::String(text) => Some(text),
+            _ => {
+                errors.push(self.expected("a string"));
+                None
+            }
+        }
+    }
+
+    fn as_bool(&self, errors: &mut Vec<GrammarError>) -> Option<bool> {
+        match self.value {
+            Value::Bool(value) => Some(value),
+            _ => {
+                errors.push(self.expected("`true` or `false`"));
+                None
+            }
+        }
+    }
+
+    fn as_integer<T: TryFrom<u128>>(&self, errors: &mut Vec<GrammarError>) -> Option<T> {
+        match self.value {
+            Value::Integer(value) => {
+                let value = T::try_from(value).ok();
+                if value.is_none() {
+                    errors.push(self.invalid("integer is out of range"));
+                }
+                value
+            }
+            _ => {
+                errors.push(self.expected("a non-negative integer"));
+                None
+            }
+        }
+    }
+}
+
+/// The lexical syntax of grammar files.
+#[derive(Debug)]
+struct JsonSyntax {
+    atoms: Vec<Box<dyn Atom>>,
+    delimiters: Vec<Delimiter>,
+}
+
+impl JsonSyntax {
+    fn new() -> Self {
+        Self {
+            atoms: vec![
+                Box::new(RegexAtom::new(AtomKind::Whitespace, r"\s+")),
+                Box::new(StringAtom::new(&['"']).single_line().json_escapes()),
+                Box::new(NumberAtom::new()),
+                Box::new(KeywordAtom::new(&["true", "false", "null"])),
+                Box::new(LiteralAtom::new(AtomKind::Operator, ":")),
+                Box::new(LiteralAtom::new(AtomKind::Operator, ",")),
+            ],
+            delimiters: vec![
+                Delimiter::new("object", "{", "}"),
+                Delimiter::new("list", "[", "]"),
+            ],
+        }
+    }
+}
+
+impl Language for JsonSyntax {
+    fn atoms(&self) -> &[Box<dyn Atom>] {
+        &self.atoms
+    }
+
+    fn delimiters(&self) -> &[Delimiter] {
+        &self.delimiters
+    }
+
+    fn macros(&self) -> &[Box<dyn Macro>] {
+        &[]
+    }
+
+    fn binding_pass(&self) -> &dyn BindingPass {
+        &NoOpBindingPass
+    }
+
+    fn reference_pass(&self) -> &dyn ReferencePass {
+        &NoOpReferencePass
+    }
+}
+
+fn read_json(source: &str, errors: &mut Vec<GrammarError>) -> Option<Json> {
+    let (trees, lex_errors) = lex_with_diagnostics(source, &JsonSyntax::new());
+    errors.extend(lex_errors.into_iter().map(GrammarError::from));
+
+    let items = significant(&trees);
+    let Some(first) = items.first() else {
+        errors.push(GrammarError::Syntax {
+            message: "expected a grammar object".into(),
+            span: (source.len(), 0).into(),
+        });
+        return None;
+    };
+    if let Some(extra) = items.get(1) {
+        errors.push(GrammarError::Syntax {
+            message: "unexpected input after the grammar object".into(),
+            span: span_of(extra),
+        });
+    }
+    read_value(first, errors)
+}
+
+fn significant(trees: &[TokenTree]) -> Vec<&TokenTree> {
+    trees
+        .iter()
+        .filter(|tree| !matches!(tree, TokenTree::Token(t) if t.kind.is_trivia()))
+        .collect()
+}
+
+fn span_of(tree: &TokenTree) -> SourceSpan {
+    match tree {
+        TokenTree::Token(t) => t.location.span,
+        TokenTree::Delimited(_, _, location, _) => location.span,
+        _ => (0, 0).into(),
+    }
+}
+
+fn is_punct(tree: &TokenTree, text: &str) -> bool {
+    matches!(tree, TokenTree::Token(t) if t.kind == AtomKind::Operator && t.text == text)
+}
+
+fn read_value(tree: &TokenTree, errors: &mut Vec<GrammarError>) -> Option<Json> {
+    let span = span_of(tree);
+    let value = match tree {
+        TokenTree::Token(t) => match (&t.kind, t.text.as_str()) {
+            (AtomKind::String, _) => {
+                Value::String(t.string_value().unwrap_or_default().to_string())
+            }
+            (AtomKind::Number, _) => match t.integer_value() {
+                Some(value) => Value::Integer(value),
+                None => Value::Float,
+            },
+            (AtomKind::Identifier, "true") => Value::Bool(true),
+            (AtomKind::Identifier, "false") => Value::Bool(false),
+            (AtomKind::Identifier, "null") => Value::Null,
+            _ => {
+                errors.push(GrammarError::Syntax {
+                    message: "expected a value".into(),
+                    span,
+                });
+                return None;
+            }
+        },
+        TokenTree::Delimited(d, children, _, _) if d.kind == "object" => {
+            Value::Object(read_fields(children, errors))
+        }
+        TokenTree::Delimited(_, children, _, _) => Value::Array(read_items(children, errors)),
+        _ => return None,
+    };
+    Some(Json { value, span })
+}
+
+fn read_items(children: &[TokenTree], errors: &mut Vec<GrammarError>) -> Vec<Json> {
+    let mut items = Vec::new();
+    let mut rest = significant(children).into_iter();
+    while let Some(tree) = rest.next() {
+        items.extend(read_value(tree, errors));
+        if !expect_comma(&mut rest, "]", errors) {
+            break;
+        }
+    }
+    items
+}
+
+fn read_fields(children: &[TokenTree], errors: &mut Vec<GrammarError>) -> Vec<Field> {
+    let mut fields = Vec::new();
+    let mut rest = significant(children).into_iter();
+    while let Some(key) = rest.next() {
+        let name = match key {
+            TokenTree::Token(t) if t.kind == AtomKind::String => {
+                t.string_value().unwrap_or_default().to_string()
+            }
+            _ => {
+                errors.push(GrammarError::Syntax {
+                    message: "expected a field name".into(),
+                    span: span_of(key),
+                });
+                rest.by_ref().find(|tree| is_punct(tree, ","));
+                continue;
+            }
+        };
+        match rest.next() {
+            Some(colon) if is_punct(colon, ":") => {}
+            other => {
+                errors.push(GrammarError::Syntax {
+                    message: "expected `:`".into(),
+                    span: other.map_or(span_of(key), span_of),
+                });
+                rest.by_ref().find(|tree| is_punct(tree, ","));
+                continue;
+            }
+        }
+        let Some(value) = rest.next() else {
+            errors.push(GrammarError::Syntax {
+                message: "expected a value".into(),
+                span: span_of(key),
+            });
+            break;
+        };
+        if let Some(value) = read_value(value, errors) {
+            fields.push(Field {
+                name,
+                span: span_of(key),
+                value,
+            });
+        }
+        if !expect_comma(&mut rest, "}", errors) {
+            break;
+        }
+    }
+    fields
+}
+
+/// Consumes the `,` after an item, returning false at the end of the group.
+fn expect_comma(
+    rest: &mut std::vec::IntoIter<&TokenTree>,
+    close: &str,
+    errors: &mut Vec<GrammarError>,
+) -> bool {
+    match rest.next() {
+        None => false,
+        Some(comma) if is_punct(comma, ",") && rest.as_slice().is_empty() => {
+            errors.push(GrammarError::Syntax {
+                message: format!("unexpected `,` before `{}`", close),
+                span: span_of(comma),
+            });
+            false
+        }
+        Some(comma) if is_punct(comma, ",") => true,
+        Some(other) => {
+            errors.push(GrammarError::Syntax {
+                message: format!("expected `,` or `{}`", close),
+                span: span_of(other),
+            });
+            rest.find(|tree| is_punct(tree, ","));
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex;
+    use crate::parser::Parser;
+    use crate::token::TokenStream;
+
+    const GRAMMAR: &str = r#"{
+        "atoms": [
+            {"kind": "whitespace", "regex": "\\s+"},
+            {"keywords": ["let"]},
+            {"kind": "identifier", "regex": "[a-z]+"},
+            {"kind": "number", "regex": "[0-9]+"},
+            {"literal": "+"},
+            {"literal": "*"},
+            {"literal": "^"},
+            {"literal": "="},
+            {"literal": ","}
+        ],
+        "delimiters": [
+            {"kind": "paren", "open": "(", "close": ")"}
+        ],
+        "binding_keyword": "let",
+        "operators": [
+            {"name": "+", "precedence": 10},
+            {"name": "*", "precedence": 20},
+            {"name": "^", "precedence": 30, "associativity": "right"}
+        ],
+        "macros": [
+            {"name": "let", "signature": [{"kind": "identifier"}, "=", {"expr": 0}]},
+            {"name": "max", "signature": {"enter": "paren", "shape": {"separated": {"expr": 0}, "by": ","}}}
+        ]
+    }"#;
+
+    /// Renders groups as parenthesised lists so tests can compare structure.
+    fn render(tree: &TokenTree) -> String {
+        match tree {
+            TokenTree::Token(t) => t.text.clone(),
+            TokenTree::Group(items) => {
+                let items: Vec<_> = items.iter().map(render).collect();
+                format!("({})", items.join(" "))
+            }
+            other => other.text(),
+        }
+    }
+
+    fn parse(language: &DynamicLanguage, input: &str) -> String {
+        let trees = lex(input, language);
+        let mut parser = Parser::new(TokenStream::new(&trees), language);
+        render(&parser.parse().unwrap())
+    }
+
+    #[test]
+    fn test_operators_from_grammar() {
+        let language = DynamicLanguage::from_json(GRAMMAR).unwrap();
+
+        assert_eq!(parse(&language, "a + b * c"), "(a + (b * c))");
+        assert_eq!(parse(&language, "a + b + c"), "((a + b) + c)");
+        assert_eq!(parse(&language, "a ^ b ^ c"), "(a ^ (b ^ c))");
+    }
+
+    #[test]
+    fn test_prefix_macros_from_grammar() {
+        let language = DynamicLanguage::from_json(GRAMMAR).unwrap();
+
+        assert_eq!(parse(&language, "let x = 1 + 2"), "(let (x (= (1 + 2))))");
+        assert_eq!(parse(&language, "max(1, 2)"), "(max (1 ((, 2))))");
+    }
+
+    #[test]
+    fn test_grammar_errors_point_into_the_file() {
+        let source = r#"{
+            "atoms": [{"kind": "number", "regex": "[0-9"}, {"literal": 1}],
+            "delimiters": [{"kind": "paren", "open": "("}],
+            "operators": [{"name": "+", "precedence": 1, "associativity": "up"}],
+            "macros": [{"name": "f", "signature": {"enter": "brace"}}],
+            "extra": true
+        }"#;
+        let errors = DynamicLanguage::from_json(source).unwrap_err();
+        let at = |needle: &str| SourceSpan::from((source.find(needle).unwrap(), needle.len()));
+
+        assert_eq!(
+            errors,
+            [
+                GrammarError::UnknownField {
+                    field: "extra".into(),
+                    span: at(r#""extra""#),
+                },
+                GrammarError::InvalidRegex {
+                    message: Regex::new(&format!("^(?:{})", "[0-9"))
+                        .unwrap_err()
+                        .to_string(),
+                    span: at(r#""[0-9""#),
+                },
+                GrammarError::InvalidType {
+                    expected: "a string",
+                    span: at("1"),
+                },
+                GrammarError::MissingField {
+                    field: "close",
+                    span: at(r#"{"kind": "paren", "open": "("}"#),
+                },
+                GrammarError::InvalidValue {
+                    message: "expected `left`, `right` or `none`".into(),
+                    span: at(r#""up""#),
+                },
+                GrammarError::UnknownDelimiter {
+                    kind: "brace".into(),
+                    span: at(r#""brace""#),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_grammar_syntax_errors() {
+        let source = r#"{"atoms": [{"literal" "+"}]} x"#;
+        let errors = DynamicLanguage::from_json(source).unwrap_err();
+
+        assert!(errors.contains(&GrammarError::Syntax {
+            message: "expected `:`".into(),
+            span: (source.find(r#""+""#).unwrap(), 3).into(),
+        }));
+        assert!(
+            errors
+                .iter()
+                .any(|error| matches!(error, GrammarError::Lex(_)))
+        );
+    }
+
+    #[test]
+    fn test_grammar_strings_use_json_escapes() {
+        let source = r#"{"atoms": [
+            {"kind": "operator", "literal": "\u002B"},
+            {"kind": "operator", "literal": "\/"}
+        ]}"#;
+        let lang = DynamicLanguage::from_json(source).unwrap();
+        let trees = lex("+/", &lang);
+        assert!(
+            trees
+                .iter()
+                .all(|tree| matches!(tree, TokenTree::Token(t) if t.kind == AtomKind::Operator))
+        );
+    }
+
+    #[test]
+    fn test_grammar_trailing_commas() {
+        let source = r#"{"atoms": [{"kind": "operator", "literal": "+",},]}"#;
+        let errors = DynamicLanguage::from_json(source).unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                GrammarError::Syntax {
+                    message: "unexpected `,` before `}`".into(),
+                    span: (source.find(",}").unwrap(), 1).into(),
+                },
+                GrammarError::Syntax {
+                    message: "unexpected `,` before `]`".into(),
+                    span: (source.find(",]").unwrap(), 1).into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_grammar_priorities_under_longest_match() {
+        let atom_of_in = |priorities: &str| {
+            let source = format!(
+                r#"{{"lex_strategy": "longest", "atoms": [
+                    {{"kind": "identifier", "regex": "[a-z]+"{priorities}}},
+                    {{"keywords": ["in"]{priorities}}}
+                ]}}"#
+            );
+            let lang = DynamicLanguage::from_json(&source).unwrap();
+            match &lex("in", &lang)[..] {
+                [TokenTree::Token(t)] => t.atom_index,
+                trees => panic!("unexpected trees {:?}", trees),
+            }
+        };
+
+        // Keywords keep their default priority of 1 unless it is given.
+        assert_eq!(atom_of_in(""), Some(1));
+        assert_eq!(atom_of_in(r#", "priority": 0"#), Some(0));
+
+        let source = r#"{"lex_strategy": "shortest"}"#;
+        assert_eq!(
+            DynamicLanguage::from_json(source).unwrap_err(),
+            [GrammarError::InvalidValue {
+                message: "expected `first` or `longest`".into(),
+                span: (source.find(r#""shortest""#).unwrap(), 10).into(),
+            }]
+        );
+    }
+}
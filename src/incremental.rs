@@ -1,21 +1,67 @@
 use crate::atom::AtomKind;
-use crate::language::{Delimiter, Language};
-use crate::lexer::lex;
-use crate::token::TokenTree;
+use crate::language::{Delimiter, Language, SharedStr};
+use crate::lexer::{lex, lex_in_mode};
+use crate::token::{Token, TokenTree};
 
 /// A "Green" token that knows its text and kind, but not its absolute position.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct GreenToken {
     pub kind: AtomKind,
     pub text: String,
+    /// Trivia attached before the token (see [`Language::attach_trivia`]).
+    pub leading_trivia: Vec<GreenToken>,
+    /// Trivia attached after the token.
+    pub trailing_trivia: Vec<GreenToken>,
 }
 
 impl GreenToken {
+    pub fn new(kind: AtomKind, text: impl Into<String>) -> Self {
+        Self {
+            kind,
+            text: text.into(),
+            leading_trivia: Vec::new(),
+            trailing_trivia: Vec::new(),
+        }
+    }
+
+    /// The width of the token including its trivia.
     pub fn width(&self) -> usize {
-        self.text.len()
+        self.leading_width() + self.text.len() + trivia_width(&self.trailing_trivia)
+    }
+
+    /// The width of the leading trivia, i.e. the offset of the token's own text
+    /// within the node.
+    pub fn leading_width(&self) -> usize {
+        trivia_width(&self.leading_trivia)
+    }
+
+    /// The text of the token including its trivia.
+    pub fn full_text(&self) -> String {
+        let mut text = String::new();
+        for trivia in &self.leading_trivia {
+            text.push_str(&trivia.text);
+        }
+        text.push_str(&self.text);
+        for trivia in &self.trailing_trivia {
+            text.push_str(&trivia.text);
+        }
+        text
+    }
+
+    fn from_token(token: &Token) -> GreenToken {
+        GreenToken {
+            kind: token.kind.clone(),
+            text: token.text.clone(),
+            leading_trivia: token.leading_trivia.iter().map(Self::from_token).collect(),
+            trailing_trivia: token.trailing_trivia.iter().map(Self::from_token).collect(),
+        }
     }
 }
 
+fn trivia_width(trivia: &[GreenToken]) -> usize {
+    trivia.iter().map(|t| t.text.len()).sum()
+}
+
 /// A "Green" tree node that forms the structure of the code.
 /// It is immutable and position-independent.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -27,6 +73,16 @@ pub enum GreenTree {
         is_closed: bool,
     },
     Group(Vec<GreenTree>),
+    /// A kinded syntax node (see [`crate::token::SyntaxNode`]).
+    Node {
+        kind: SharedStr,
+        children: Vec<GreenTree>,
+    },
+    /// A named child of the enclosing node.
+    Field {
+        name: SharedStr,
+        value: Box<GreenTree>,
+    },
     Empty,
 }
 
@@ -44,7 +100,10 @@ impl GreenTree {
                     + content_width
                     + if *is_closed { delimiter.close.len() } else { 0 }
             }
-            GreenTree::Group(children) => children.iter().map(|c| c.width()).sum(),
+            GreenTree::Group(children) | GreenTree::Node { children, .. } => {
+                children.iter().map(|c| c.width()).sum()
+            }
+            GreenTree::Field { value, .. } => value.width(),
             GreenTree::Empty => 0,
         }
     }
@@ -52,23 +111,26 @@ impl GreenTree {
     /// Reconstructs the full text of this node.
     pub fn text(&self) -> String {
         match self {
-            GreenTree::Token(t) => t.text.clone(),
+            GreenTree::Token(t) => t.full_text(),
             GreenTree::Delimited {
                 delimiter,
                 children,
                 is_closed,
             } => {
                 let mut s = String::new();
-                s.push_str(delimiter.open);
+                s.push_str(&delimiter.open);
                 for child in children {
                     s.push_str(&child.text());
                 }
                 if *is_closed {
-                    s.push_str(delimiter.close);
+                    s.push_str(&delimiter.close);
                 }
                 s
             }
-            GreenTree::Group(children) => children.iter().map(|c| c.text()).collect(),
+            GreenTree::Group(children) | GreenTree::Node { children, .. } => {
+                children.iter().map(|c| c.text()).collect()
+            }
+            GreenTree::Field { value, .. } => value.text(),
             GreenTree::Empty => String::new(),
         }
     }
@@ -76,10 +138,7 @@ impl GreenTree {
     /// Converts a legacy `TokenTree` (with absolute offsets) to a `GreenTree`.
     pub fn from_token_tree(tree: &TokenTree) -> GreenTree {
         match tree {
-            TokenTree::Token(t) => GreenTree::Token(GreenToken {
-                kind: t.kind.clone(),
-                text: t.text.clone(),
-            }),
+            TokenTree::Token(t) => GreenTree::Token(GreenToken::from_token(t)),
             TokenTree::Delimited(d, children, _, is_closed) => {
                 let children = children.iter().map(GreenTree::from_token_tree).collect();
                 GreenTree::Delimited {
@@ -91,8 +150,16 @@ impl GreenTree {
             TokenTree::Group(children) => {
                 GreenTree::Group(children.iter().map(Self::from_token_tree).collect())
             }
+            TokenTree::Node(node) => GreenTree::Node {
+                kind: node.kind.clone(),
+                children: node.children.iter().map(Self::from_token_tree).collect(),
+            },
+            TokenTree::Field(name, value) => GreenTree::Field {
+                name: name.clone(),
+                value: Box::new(Self::from_token_tree(value)),
+            },
             TokenTree::Empty => GreenTree::Empty,
-            TokenTree::Error(_) => GreenTree::Empty, // TODO: Handle errors better
+            TokenTree::Error(..) => GreenTree::Empty, // TODO: Handle errors better
         }
     }
 }
@@ -162,7 +229,9 @@ fn relex_recursive(
             let content_start = offset + open_len;
             let content_end = node_end - close_len;
 
-            // If edit is strictly inside the delimiters
+            // If edit is strictly inside the delimiters.
+            // Indented blocks have no text of their own to re-lex against, so an
+            // edit that can't be handled by a child falls back to the parent.
             if edit.start >= content_start && edit.end <= content_end {
                 // Try to find a child that contains it
                 let mut current_child_offset = content_start;
@@ -191,6 +260,10 @@ fn relex_recursive(
                     current_child_offset += child_width;
                 }
 
+                if delimiter.open.is_empty() {
+                    return RelexResult::Failed;
+                }
+
                 // If we are here, either:
                 // 1. Edit spans multiple children (but still inside block)
                 // 2. Edit is in the "void" between children (if that's possible? No, we have whitespace atoms usually)
@@ -215,8 +288,11 @@ fn relex_recursive(
                 }
                 .apply(&inner_text);
 
-                // 3. Lex the new inner text.
-                let new_tokens = lex(&new_inner_text, language);
+                // 3. Lex the new inner text, in the delimiter's mode if it has one.
+                let new_tokens = match &delimiter.mode {
+                    Some(mode) => lex_in_mode(&new_inner_text, language, mode),
+                    None => lex(&new_inner_text, language),
+                };
 
                 // 4. Convert to GreenTrees
                 let new_green_children: Vec<GreenTree> =
@@ -349,12 +425,17 @@ impl<'a> RedNode<'a> {
                 children: green_children,
                 ..
             }
-            | GreenTree::Group(green_children) => {
+            | GreenTree::Group(green_children)
+            | GreenTree::Node {
+                children: green_children,
+                ..
+            } => {
                 for child in green_children {
                     children.push(RedNode::new(child, current_offset));
                     current_offset += child.width();
                 }
             }
+            GreenTree::Field { value, .. } => children.push(RedNode::new(value, current_offset)),
             _ => {}
         }
         children
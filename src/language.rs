@@ -1,14 +1,243 @@
 pub use crate::atom::{Atom, AtomKind};
 use crate::r#macro::Macro;
 use crate::scoping::{BindingPass, ReferencePass};
-use crate::shape::CompletionItem;
-use std::fmt::Debug;
+use crate::shape::{CompletionItem, Shape};
+use std::borrow::Borrow;
+use std::fmt::{self, Debug, Display};
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// The name of the language's own mode (its `atoms()` and `delimiters()`).
+///
+/// A delimiter whose mode is `ROOT_MODE` lexes its contents with the language's
+/// top-level atoms, e.g. the `${ ... }` region inside an interpolated string.
+pub const ROOT_MODE: &str = "root";
+
+/// Text in a language definition, such as a delimiter or mode name.
+///
+/// Grammars written in code use `'static` string literals, while grammars built
+/// at runtime (e.g. from a plugin's configuration) own their text. Either way,
+/// cloning is cheap, which matters because every delimited group in a token tree
+/// holds a copy of its [`Delimiter`].
+///
+/// ```rust
+/// use mcparse::language::{Delimiter, SharedStr};
+///
+/// let config = String::from("<%");
+/// let tag = Delimiter::new("tag", config, "%>");
+/// assert_eq!(tag.open, "<%");
+/// assert_eq!(tag.kind, SharedStr::from_static("tag"));
+/// ```
+#[derive(Clone)]
+pub struct SharedStr(Repr);
+
+#[derive(Clone)]
+enum Repr {
+    Static(&'static str),
+    Shared(Arc<str>),
+}
+
+impl SharedStr {
+    pub const fn from_static(s: &'static str) -> Self {
+        Self(Repr::Static(s))
+    }
+
+    pub fn as_str(&self) -> &str {
+        match &self.0 {
+            Repr::Static(s) => s,
+            Repr::Shared(s) => s,
+        }
+    }
+}
+
+impl Deref for SharedStr {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for SharedStr {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Borrow<str> for SharedStr {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<&'static str> for SharedStr {
+    fn from(s: &'static str) -> Self {
+        Self::from_static(s)
+    }
+}
+
+impl From<String> for SharedStr {
+    fn from(s: String) -> Self {
+        Self(Repr::Shared(s.into()))
+    }
+}
+
+impl From<Arc<str>> for SharedStr {
+    fn from(s: Arc<str>) -> Self {
+        Self(Repr::Shared(s))
+    }
+}
+
+impl PartialEq for SharedStr {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for SharedStr {}
+
+impl PartialEq<str> for SharedStr {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for SharedStr {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl Hash for SharedStr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl Debug for SharedStr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl Display for SharedStr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(self.as_str(), f)
+    }
+}
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Delimiter {
-    pub kind: &'static str,
-    pub open: &'static str,
-    pub close: &'static str,
+    pub kind: SharedStr,
+    pub open: SharedStr,
+    pub close: SharedStr,
+    /// The lexer mode used for the contents of the group, if it differs from the
+    /// surrounding mode.
+    pub mode: Option<SharedStr>,
+    /// Whether an unclosed group of this kind ends at the first line indented no
+    /// further than the line that opened it (see [`Delimiter::with_indentation_recovery`]).
+    pub recover_by_indentation: bool,
+}
+
+impl Delimiter {
+    pub fn new(
+        kind: impl Into<SharedStr>,
+        open: impl Into<SharedStr>,
+        close: impl Into<SharedStr>,
+    ) -> Self {
+        Self {
+            kind: kind.into(),
+            open: open.into(),
+            close: close.into(),
+            mode: None,
+            recover_by_indentation: false,
+        }
+    }
+
+    /// Lexes the contents of this delimiter in the named mode.
+    /// The mode is popped again when the closer is found.
+    pub fn with_mode(mut self, mode: impl Into<SharedStr>) -> Self {
+        self.mode = Some(mode.into());
+        self
+    }
+
+    /// Uses indentation to recover when this delimiter's closer is missing.
+    ///
+    /// Inside the group, a line indented less than the line with the opener, or
+    /// indented the same but not starting with the closer, ends the group early.
+    /// The group is reported as unclosed, and later closers go to the enclosing
+    /// groups instead of the one that lost its closer. Requires the language to
+    /// lex whitespace as [`AtomKind::Whitespace`] tokens.
+    pub fn with_indentation_recovery(mut self) -> Self {
+        self.recover_by_indentation = true;
+        self
+    }
+}
+
+/// The synthetic delimiter wrapping indented blocks in languages that use the
+/// offside rule (see [`Language::offside_rule`]).
+///
+/// It has no open or close text, so the group's text is exactly its contents.
+pub const INDENT: Delimiter = Delimiter {
+    kind: SharedStr::from_static("indent"),
+    open: SharedStr::from_static(""),
+    close: SharedStr::from_static(""),
+    mode: None,
+    recover_by_indentation: false,
+};
+
+/// How the lexer chooses between atoms that match at the same position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LexStrategy {
+    /// Take the first atom, in declaration order, that matches.
+    #[default]
+    FirstMatch,
+    /// Try every atom and take the longest match (maximal munch). Ties are broken
+    /// by [`Atom::priority`], then by declaration order.
+    LongestMatch,
+}
+
+/// A change to the lexer's mode stack requested by an atom.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModeTransition {
+    /// Lex the following input with the named mode until it is popped.
+    Push(SharedStr),
+    /// Return to the previous mode.
+    Pop,
+}
+
+/// A named set of atoms and delimiters that is active in part of the input.
+///
+/// Modes let a language switch tokenization rules based on context: string
+/// interpolation (`"a ${b} c"`), template languages (text vs. `{{ }}` code), and so on.
+/// A mode is entered either by a delimiter (see [`Delimiter::with_mode`]) or by an
+/// atom that returns a [`ModeTransition`].
+#[derive(Debug)]
+pub struct LexerMode {
+    pub name: SharedStr,
+    pub atoms: Vec<Box<dyn Atom>>,
+    pub delimiters: Vec<Delimiter>,
+}
+
+impl LexerMode {
+    pub fn new(name: impl Into<SharedStr>) -> Self {
+        Self {
+            name: name.into(),
+            atoms: Vec::new(),
+            delimiters: Vec::new(),
+        }
+    }
+
+    pub fn with_atom(mut self, atom: impl Atom + 'static) -> Self {
+        self.atoms.push(Box::new(atom));
+        self
+    }
+
+    pub fn with_delimiter(mut self, delimiter: Delimiter) -> Self {
+        self.delimiters.push(delimiter);
+        self
+    }
 }
 
 pub trait Language: Debug + Send + Sync {
@@ -18,8 +247,131 @@ pub trait Language: Debug + Send + Sync {
     fn binding_pass(&self) -> &dyn BindingPass;
     fn reference_pass(&self) -> &dyn ReferencePass;
 
+    /// Additional lexer modes, besides the root mode formed by `atoms()` and `delimiters()`.
+    fn modes(&self) -> &[LexerMode] {
+        &[]
+    }
+
+    /// How the lexer chooses between atoms that match at the same position.
+    fn lex_strategy(&self) -> LexStrategy {
+        LexStrategy::FirstMatch
+    }
+
+    /// Whether indentation is significant.
+    ///
+    /// When enabled, each increase in indentation at the top level opens an
+    /// [`INDENT`] group that lasts until the matching dedent, so indented blocks
+    /// can be matched with `enter` like any other delimited group. Indentation
+    /// inside explicit delimiters is ignored.
+    fn offside_rule(&self) -> bool {
+        false
+    }
+
+    /// Whether the lexer attaches trivia to the neighbouring tokens.
+    ///
+    /// When enabled, trivia (see [`Language::is_trivia`]) on the same line after a
+    /// token becomes its `trailing_trivia`, and the remaining trivia becomes the
+    /// `leading_trivia` of the next token in the same group. Trivia with no token
+    /// to follow, such as comments before a group's closer, trails the token before
+    /// it, and stays in the tree only when its group has no such token.
+    /// [`TokenTree::text`](crate::token::TokenTree::text) still reproduces the input.
+    fn attach_trivia(&self) -> bool {
+        false
+    }
+
+    /// Whether tokens of `kind` are trivia, which shapes and the parser skip.
+    ///
+    /// Defaults to whitespace and comments (see [`AtomKind::is_trivia`]).
+    fn is_trivia(&self, kind: &AtomKind) -> bool {
+        kind.is_trivia()
+    }
+
+    /// The shape of a whole program, such as a list of statements.
+    ///
+    /// [`Parser::parse_program`](crate::parser::Parser::parse_program) matches it
+    /// against the entire input. Without one, a program is a single expression.
+    fn root(&self) -> Option<&dyn Shape> {
+        None
+    }
+
+    /// Looks up an atom by the index stored in `Token::atom_index`.
+    ///
+    /// Indices number the root atoms first, followed by the atoms of each mode in
+    /// the order returned by `modes()`.
+    fn atom(&self, index: usize) -> Option<&dyn Atom> {
+        let atoms = self.atoms();
+        if index < atoms.len() {
+            return Some(atoms[index].as_ref());
+        }
+
+        let mut base = atoms.len();
+        for mode in self.modes() {
+            if index < base + mode.atoms.len() {
+                return Some(mode.atoms[index - base].as_ref());
+            }
+            base += mode.atoms.len();
+        }
+        None
+    }
+
     fn complete(&self, input: &str, offset: usize) -> Vec<CompletionItem> {
         let tokens = crate::lexer::lex(input, self);
         crate::completion::find_completions(&tokens, self, offset)
     }
 }
+
+/// Lets one language be shared, e.g. as the base of several
+/// [`crate::builder::LanguageBuilder`] dialects.
+impl<L: Language + ?Sized> Language for Arc<L> {
+    fn atoms(&self) -> &[Box<dyn Atom>] {
+        (**self).atoms()
+    }
+
+    fn delimiters(&self) -> &[Delimiter] {
+        (**self).delimiters()
+    }
+
+    fn macros(&self) -> &[Box<dyn Macro>] {
+        (**self).macros()
+    }
+
+    fn binding_pass(&self) -> &dyn BindingPass {
+        (**self).binding_pass()
+    }
+
+    fn reference_pass(&self) -> &dyn ReferencePass {
+        (**self).reference_pass()
+    }
+
+    fn modes(&self) -> &[LexerMode] {
+        (**self).modes()
+    }
+
+    fn lex_strategy(&self) -> LexStrategy {
+        (**self).lex_strategy()
+    }
+
+    fn offside_rule(&self) -> bool {
+        (**self).offside_rule()
+    }
+
+    fn attach_trivia(&self) -> bool {
+        (**self).attach_trivia()
+    }
+
+    fn is_trivia(&self, kind: &AtomKind) -> bool {
+        (**self).is_trivia(kind)
+    }
+
+    fn root(&self) -> Option<&dyn Shape> {
+        (**self).root()
+    }
+
+    fn atom(&self, index: usize) -> Option<&dyn Atom> {
+        (**self).atom(index)
+    }
+
+    fn complete(&self, input: &str, offset: usize) -> Vec<CompletionItem> {
+        (**self).complete(input, offset)
+    }
+}
@@ -1,144 +1,825 @@
-use crate::atom::AtomKind;
-use crate::language::{Delimiter, Language};
+use crate::atom::{Atom, AtomKind};
+use crate::compiled_lexer::{AtomDispatch, Candidate};
+use crate::language::{
+    Delimiter, INDENT, Language, LexStrategy, ModeTransition, ROOT_MODE, SharedStr,
+};
+use crate::source_map::{FileId, SourceMap};
 use crate::token::{Cursor, SourceLocation, Token, TokenTree};
-use miette::SourceSpan;
+use crate::trivia::attach_trivia;
+use miette::{Diagnostic, SourceSpan};
+use std::cell::Cell;
+use std::ops::ControlFlow;
+use thiserror::Error;
+
+/// A problem found while lexing.
+///
+/// Lexing never fails: the token trees are always produced, and these
+/// diagnostics describe what was wrong with the input.
+#[derive(Debug, Clone, PartialEq, Eq, Error, Diagnostic)]
+pub enum LexError {
+    #[error("indentation contains tabs")]
+    TabIndentation {
+        #[label("tab used for indentation")]
+        span: SourceSpan,
+    },
+    #[error("indentation mixes tabs and spaces")]
+    MixedIndentation {
+        #[label("mixed tabs and spaces")]
+        span: SourceSpan,
+    },
+    #[error("unindent does not match any outer indentation level")]
+    InconsistentDedent {
+        #[label("here")]
+        span: SourceSpan,
+    },
+    #[error("unclosed delimiter `{delimiter}`")]
+    UnclosedDelimiter {
+        delimiter: SharedStr,
+        #[label("unclosed delimiter")]
+        span: SourceSpan,
+    },
+    #[error("mismatched closing delimiter `{delimiter}`")]
+    MismatchedDelimiter {
+        delimiter: SharedStr,
+        #[label("mismatched closing delimiter")]
+        span: SourceSpan,
+        #[label("unclosed delimiter")]
+        opener: SourceSpan,
+    },
+    #[error("unexpected closing delimiter `{delimiter}`")]
+    UnexpectedClosingDelimiter {
+        delimiter: SharedStr,
+        #[label("no matching opening delimiter")]
+        span: SourceSpan,
+    },
+    #[error("unknown characters")]
+    UnknownCharacters {
+        #[label("not recognized by any atom")]
+        span: SourceSpan,
+    },
+    #[error("unterminated string literal")]
+    UnterminatedString {
+        #[label("string starts here")]
+        span: SourceSpan,
+    },
+    #[error("unterminated block comment")]
+    UnterminatedComment {
+        #[label("comment starts here")]
+        span: SourceSpan,
+    },
+    #[error("invalid escape sequence `{escape}`")]
+    InvalidEscape {
+        escape: String,
+        #[label("invalid escape")]
+        span: SourceSpan,
+    },
+    #[error("invalid digit `{digit}` in a base {radix} literal")]
+    InvalidDigit {
+        digit: char,
+        radix: u32,
+        #[label("invalid digit")]
+        span: SourceSpan,
+    },
+    #[error("expected at least one digit")]
+    MissingDigits {
+        #[label("expected digits after this")]
+        span: SourceSpan,
+    },
+    #[error("invalid suffix `{suffix}` on a number literal")]
+    InvalidSuffix {
+        suffix: String,
+        #[label("invalid suffix")]
+        span: SourceSpan,
+    },
+    #[error("number literal is too large")]
+    NumberOverflow {
+        #[label("out of range")]
+        span: SourceSpan,
+    },
+}
+
+impl LexError {
+    pub fn span(&self) -> SourceSpan {
+        match self {
+            LexError::TabIndentation { span }
+            | LexError::MixedIndentation { span }
+            | LexError::InconsistentDedent { span }
+            | LexError::UnclosedDelimiter { span, .. }
+            | LexError::MismatchedDelimiter { span, .. }
+            | LexError::UnexpectedClosingDelimiter { span, .. }
+            | LexError::UnknownCharacters { span }
+            | LexError::UnterminatedString { span }
+            | LexError::UnterminatedComment { span }
+            | LexError::InvalidEscape { span, .. }
+            | LexError::InvalidDigit { span, .. }
+            | LexError::MissingDigits { span }
+            | LexError::InvalidSuffix { span, .. }
+            | LexError::NumberOverflow { span } => *span,
+        }
+    }
+}
 
 /// The entry point for the atomic lexer.
 /// Converts a raw string into a list of `TokenTree`s, handling delimiters recursively.
 pub fn lex(input: &str, language: &(impl Language + ?Sized)) -> Vec<TokenTree> {
-    let cursor = Cursor::new(input);
-    let (trees, _) = lex_group(cursor, language, None);
-    trees
+    lex_with_diagnostics(input, language).0
 }
 
-/// Recursively lexes a group of tokens until the input is exhausted or a closing delimiter is found.
-fn lex_group<'a>(
-    mut cursor: Cursor<'a>,
+/// Like [`lex`], but also returns the diagnostics found while lexing.
+pub fn lex_with_diagnostics(
+    input: &str,
     language: &(impl Language + ?Sized),
-    terminator: Option<&Delimiter>,
-) -> (Vec<TokenTree>, Cursor<'a>) {
-    let mut trees = Vec::new();
-    let mut pending_unknown: Option<(usize, String)> = None;
-
-    'outer: while !cursor.rest.is_empty() {
-        // Helper to flush pending unknown tokens
-        let mut flush_unknown = |trees: &mut Vec<TokenTree>| {
-            if let Some((start, text)) = pending_unknown.take() {
-                let len = text.len();
-                let span = SourceSpan::new(start.into(), len);
-                let location = SourceLocation { span };
-                trees.push(TokenTree::Token(Token {
-                    kind: AtomKind::Other("Unknown".to_string()),
-                    text,
-                    location,
-                    atom_index: None,
-                    binding: None,
-                }));
+) -> (Vec<TokenTree>, Vec<LexError>) {
+    Lexer::new(language).lex_with_diagnostics(Cursor::new(input))
+}
+
+/// Lexes one file of a [`SourceMap`].
+///
+/// Spans are offsets into the whole map and locations carry `file`, so trees and
+/// diagnostics from different files never collide.
+pub fn lex_file(
+    files: &SourceMap,
+    file: FileId,
+    language: &(impl Language + ?Sized),
+) -> (Vec<TokenTree>, Vec<LexError>) {
+    Lexer::new(language)
+        .in_file(file)
+        .lex_with_diagnostics(files.cursor(file))
+}
+
+/// Lexes `input` starting in the named lexer mode.
+/// Used to re-lex the contents of a delimiter that switches modes.
+///
+/// An unknown mode name lexes with the root mode, after failing a debug assertion.
+pub fn lex_in_mode(input: &str, language: &(impl Language + ?Sized), mode: &str) -> Vec<TokenTree> {
+    Lexer::new(language).lex_in_mode(input, mode)
+}
+
+/// The atoms and delimiters of one lexer mode, as seen by the lexer.
+#[derive(Clone, Copy)]
+struct ActiveMode<'l> {
+    atoms: &'l [Box<dyn Atom>],
+    delimiters: &'l [Delimiter],
+    /// The `atom_index` of this mode's first atom (see `Language::atom`).
+    index_base: usize,
+    /// The compiled dispatch table for `atoms`, if the lexer has one.
+    dispatch: Option<&'l AtomDispatch>,
+}
+
+/// The group being lexed by `Lexer::lex_group`, and how it was opened.
+#[derive(Clone, Copy)]
+struct Terminator<'d> {
+    delimiter: &'d Delimiter,
+    /// The span of the opening delimiter.
+    opener: SourceSpan,
+    /// The indentation width of the line containing the opener.
+    indentation: usize,
+}
+
+pub(crate) struct Lexer<'l, L: Language + ?Sized> {
+    language: &'l L,
+    /// Compiled dispatch tables for the root mode followed by each of `language.modes()`.
+    dispatch: Option<&'l [AtomDispatch]>,
+    /// The indentation width of the line being lexed.
+    line_indentation: Cell<usize>,
+    /// Where the input begins, so its first line starts without a newline.
+    /// `None` when lexing resumes partway through a document.
+    input_start: Cell<Option<usize>>,
+    /// Whether lexing resumes partway through a document.
+    resumed: bool,
+    /// The file that locations point into.
+    file: FileId,
+}
+
+impl<'l, L: Language + ?Sized> Lexer<'l, L> {
+    pub(crate) fn new(language: &'l L) -> Self {
+        Self {
+            language,
+            dispatch: None,
+            line_indentation: Cell::new(0),
+            input_start: Cell::new(Some(0)),
+            resumed: false,
+            file: FileId::default(),
+        }
+    }
+
+    pub(crate) fn with_dispatch(language: &'l L, dispatch: &'l [AtomDispatch]) -> Self {
+        Self {
+            language,
+            dispatch: Some(dispatch),
+            line_indentation: Cell::new(0),
+            input_start: Cell::new(Some(0)),
+            resumed: false,
+            file: FileId::default(),
+        }
+    }
+
+    /// Starts lexing partway through a document, on a line indented by `width`.
+    pub(crate) fn on_line_indented(mut self, width: usize) -> Self {
+        self.line_indentation.set(width);
+        self.resumed = true;
+        self
+    }
+
+    pub(crate) fn in_file(mut self, file: FileId) -> Self {
+        self.file = file;
+        self
+    }
+
+    pub(crate) fn lex_with_diagnostics(&self, cursor: Cursor) -> (Vec<TokenTree>, Vec<LexError>) {
+        self.input_start
+            .set((!self.resumed).then_some(cursor.offset));
+        let mut errors = Vec::new();
+        let (mut trees, _) = self.lex_group(cursor, None, self.mode(ROOT_MODE), &mut errors);
+        if self.language.offside_rule() {
+            trees = apply_offside_rule(trees, self.language, self.file, &mut errors);
+        }
+        if self.language.attach_trivia() {
+            trees = attach_trivia(trees, self.language);
+        }
+        (trees, errors)
+    }
+
+    pub(crate) fn lex_in_mode(&self, input: &str, mode: &str) -> Vec<TokenTree> {
+        let cursor = Cursor::new(input);
+        let (trees, _) = self.lex_group(cursor, None, self.mode(mode), &mut Vec::new());
+        if self.language.attach_trivia() {
+            return attach_trivia(trees, self.language);
+        }
+        trees
+    }
+
+    /// Resolves a mode by name. Unknown names fall back to the root mode;
+    /// [`lint`](crate::lint::lint) reports them.
+    fn mode(&self, name: &str) -> ActiveMode<'l> {
+        let language = self.language;
+        let dispatch = |i: usize| self.dispatch.and_then(|d| d.get(i));
+
+        let mut index_base = language.atoms().len();
+        for (i, mode) in language.modes().iter().enumerate() {
+            if mode.name == name {
+                return ActiveMode {
+                    atoms: &mode.atoms,
+                    delimiters: &mode.delimiters,
+                    index_base,
+                    dispatch: dispatch(i + 1),
+                };
             }
-        };
+            index_base += mode.atoms.len();
+        }
 
-        // 1. Check for terminator (close delimiter)
-        if let Some(term) = terminator
-            && cursor.rest.starts_with(term.close)
-        {
-            flush_unknown(&mut trees);
-            return (trees, cursor);
+        debug_assert!(name == ROOT_MODE, "unknown lexer mode `{name}`");
+        ActiveMode {
+            atoms: language.atoms(),
+            delimiters: language.delimiters(),
+            index_base: 0,
+            dispatch: dispatch(0),
         }
+    }
+
+    /// Recursively lexes a group of tokens until the input is exhausted or a closing delimiter is found.
+    ///
+    /// Each group starts in `mode`. Atoms may push or pop modes within the group;
+    /// any modes still pushed when the group ends are discarded along with it.
+    ///
+    /// If the group's delimiter recovers by indentation, the group also ends before
+    /// a line break that outdents past its opener, leaving the line to the parent.
+    fn lex_group<'a>(
+        &self,
+        mut cursor: Cursor<'a>,
+        terminator: Option<Terminator>,
+        mode: ActiveMode<'l>,
+        errors: &mut Vec<LexError>,
+    ) -> (Vec<TokenTree>, Cursor<'a>) {
+        let mut trees = Vec::new();
+        let mut pending_unknown: Option<(usize, String)> = None;
+        let mut modes = vec![mode];
+        // The cursor before the previous token, and the new line's indentation,
+        // if that token was a line break.
+        let mut line_break: Option<(Cursor<'a>, usize)> = None;
 
-        // 2. Check for openers (delimiters)
-        for delim in language.delimiters() {
-            if cursor.rest.starts_with(delim.open) {
+        'outer: while !cursor.rest.is_empty() {
+            let mode = *modes.last().unwrap();
+
+            if let Some(term) = terminator
+                && term.delimiter.recover_by_indentation
+                && let Some((line_cursor, width)) = line_break.take()
+                && (width < term.indentation
+                    || (width == term.indentation
+                        && !cursor.rest.starts_with(&*term.delimiter.close)))
+            {
+                // The closer is most likely missing. Give the line break back to the parent.
+                trees.pop();
+                return (trees, line_cursor);
+            }
+            line_break = None;
+
+            // Helper to flush pending unknown tokens
+            let mut flush_unknown = |trees: &mut Vec<TokenTree>| {
+                if let Some((start, text)) = pending_unknown.take() {
+                    self.push_unknown(trees, errors, start, text);
+                }
+            };
+
+            // 0. Match atoms. Opaque atoms (strings, comments) win over any delimiter,
+            // so their contents can't open or close a group.
+            let matched = self.match_atom(cursor, mode);
+            let opaque = matched
+                .as_ref()
+                .is_some_and(|(index, ..)| mode.atoms[*index].is_opaque());
+
+            // 1. Check for terminator (close delimiter)
+            if let Some(term) = terminator
+                && !opaque
+                && cursor.rest.starts_with(&*term.delimiter.close)
+            {
                 flush_unknown(&mut trees);
+                return (trees, cursor);
+            }
+
+            // 2. Check for openers (delimiters)
+            for delim in mode.delimiters.iter().filter(|_| !opaque) {
+                if cursor.rest.starts_with(&*delim.open) {
+                    flush_unknown(&mut trees);
+
+                    let start_offset = cursor.offset;
+                    let opener = SourceSpan::new(start_offset.into(), delim.open.len());
+                    let inner_cursor = cursor.advance(delim.open.len());
+                    let inner_mode = match &delim.mode {
+                        Some(name) => self.mode(name),
+                        None => mode,
+                    };
+                    let inner_terminator = Terminator {
+                        delimiter: delim,
+                        opener,
+                        indentation: self.line_indentation.get(),
+                    };
+                    let (inner_trees, next_cursor) =
+                        self.lex_group(inner_cursor, Some(inner_terminator), inner_mode, errors);
 
-                let start_offset = cursor.offset;
-                let inner_cursor = cursor.advance(delim.open.len());
-                let (inner_trees, next_cursor) = lex_group(inner_cursor, language, Some(delim));
-
-                // Check if we found the closer
-                if next_cursor.rest.starts_with(delim.close) {
-                    let end_cursor = next_cursor.advance(delim.close.len());
-                    let span =
-                        SourceSpan::new(start_offset.into(), end_cursor.offset - start_offset);
-                    let location = SourceLocation { span };
-
-                    trees.push(TokenTree::Delimited(
-                        delim.clone(),
-                        inner_trees,
-                        location,
-                        true,
-                    ));
-                    cursor = end_cursor;
-                    continue 'outer;
-                } else {
-                    // Unclosed delimiter - treat as a delimited group that extends to where the inner lexer stopped.
-                    // This allows completion and partial parsing to work inside unclosed groups.
-                    let span =
-                        SourceSpan::new(start_offset.into(), next_cursor.offset - start_offset);
-                    let location = SourceLocation { span };
-                    trees.push(TokenTree::Delimited(
-                        delim.clone(),
-                        inner_trees,
-                        location,
-                        false,
-                    ));
-
-                    cursor = next_cursor;
-                    // We continue, but likely next_cursor is at EOF or a mismatched closer, so the loop will handle it.
-                    continue 'outer;
+                    // Check if we found the closer
+                    if next_cursor.rest.starts_with(&*delim.close) {
+                        let end_cursor = next_cursor.advance(delim.close.len());
+                        let span =
+                            SourceSpan::new(start_offset.into(), end_cursor.offset - start_offset);
+                        let location = SourceLocation {
+                            span,
+                            file: self.file,
+                        };
+
+                        trees.push(TokenTree::Delimited(
+                            delim.clone(),
+                            inner_trees,
+                            location,
+                            true,
+                        ));
+                        cursor = end_cursor;
+                        continue 'outer;
+                    } else {
+                        // Unclosed delimiter - treat as a delimited group that extends to where the inner lexer stopped.
+                        // This allows completion and partial parsing to work inside unclosed groups.
+                        let span =
+                            SourceSpan::new(start_offset.into(), next_cursor.offset - start_offset);
+                        let location = SourceLocation {
+                            span,
+                            file: self.file,
+                        };
+                        trees.push(TokenTree::Delimited(
+                            delim.clone(),
+                            inner_trees,
+                            location,
+                            false,
+                        ));
+                        errors.push(LexError::UnclosedDelimiter {
+                            delimiter: delim.open.clone(),
+                            span: opener,
+                        });
+
+                        cursor = next_cursor;
+                        // We continue, but likely next_cursor is at EOF or a mismatched closer, so the loop will handle it.
+                        continue 'outer;
+                    }
                 }
             }
-        }
 
-        // 3. Check for atoms
-        for (index, atom) in language.atoms().iter().enumerate() {
-            if let Some((mut token, next_cursor)) = atom.parse(cursor) {
+            // 3. Otherwise, take the atom
+            if let Some((index, mut token, next_cursor)) = matched {
                 flush_unknown(&mut trees);
 
                 // Set the atom index for highlighting
-                token.atom_index = Some(index);
+                token.atom_index = Some(mode.index_base + index);
+                token.location.file = self.file;
+                errors.extend(mode.atoms[index].diagnose(&token));
+                line_break = self.line_start(&token).map(|width| (cursor, width));
 
-                trees.push(TokenTree::Token(token.clone()));
+                trees.push(TokenTree::Token(token));
+                self.apply_transition(&mut modes, mode.atoms[index].as_ref());
 
                 cursor = next_cursor;
                 continue 'outer;
             }
+
+            // 4. A closer that doesn't belong to this group stays an unknown token of its own.
+            if let Some(delim) = mode
+                .delimiters
+                .iter()
+                .find(|d| !d.close.is_empty() && cursor.rest.starts_with(&*d.close))
+            {
+                flush_unknown(&mut trees);
+
+                let span = SourceSpan::new(cursor.offset.into(), delim.close.len());
+                errors.push(match terminator {
+                    Some(term) => LexError::MismatchedDelimiter {
+                        delimiter: delim.close.clone(),
+                        span,
+                        opener: term.opener,
+                    },
+                    None => LexError::UnexpectedClosingDelimiter {
+                        delimiter: delim.close.clone(),
+                        span,
+                    },
+                });
+                trees.push(TokenTree::Token(Token {
+                    kind: AtomKind::Other("Unknown".to_string()),
+                    text: delim.close.to_string(),
+                    location: SourceLocation {
+                        span,
+                        file: self.file,
+                    },
+                    atom_index: None,
+                    binding: None,
+                    leading_trivia: Vec::new(),
+                    trailing_trivia: Vec::new(),
+                    value: None,
+                }));
+
+                cursor = cursor.advance(delim.close.len());
+                continue 'outer;
+            }
+
+            // No match found - accumulate unknown character
+            let char_len = cursor.rest.chars().next().unwrap().len_utf8();
+            let char_text = &cursor.rest[..char_len];
+
+            if let Some((_, ref mut text)) = pending_unknown {
+                text.push_str(char_text);
+            } else {
+                pending_unknown = Some((cursor.offset, char_text.to_string()));
+            }
+            cursor = cursor.advance(char_len);
+        }
+
+        // Flush any remaining unknown text at EOF
+        if let Some((start, text)) = pending_unknown {
+            self.push_unknown(&mut trees, errors, start, text);
         }
 
-        // No match found - accumulate unknown character
-        let char_len = cursor.rest.chars().next().unwrap().len_utf8();
-        let char_text = &cursor.rest[..char_len];
+        (trees, cursor)
+    }
 
-        if let Some((_, ref mut text)) = pending_unknown {
-            text.push_str(char_text);
-        } else {
-            pending_unknown = Some((cursor.offset, char_text.to_string()));
+    /// Finds the atom of `mode` that matches at `cursor`, according to the
+    /// language's [`LexStrategy`]. Opaque atoms are tried first, and the others
+    /// only if none of them matches.
+    fn match_atom<'a>(
+        &self,
+        cursor: Cursor<'a>,
+        mode: ActiveMode<'l>,
+    ) -> Option<(usize, Token, Cursor<'a>)> {
+        let strategy = self.language.lex_strategy();
+        let mut best: Option<(usize, Token, Cursor<'a>)> = None;
+
+        let mut consider = |candidate: Candidate| {
+            let atom = &mode.atoms[candidate.index];
+            if !atom.is_opaque()
+                && best
+                    .as_ref()
+                    .is_some_and(|(index, ..)| mode.atoms[*index].is_opaque())
+            {
+                return ControlFlow::Break(());
+            }
+
+            let parsed = match candidate.len {
+                Some(len) if atom.pattern_is_exact() => (len > 0).then(|| {
+                    let token = Token::new(atom.kind(), &cursor.rest[..len], cursor.offset);
+                    (token, cursor.advance(len))
+                }),
+                _ => atom.parse(cursor),
+            };
+            if let Some((token, next)) = parsed {
+                let better = match &best {
+                    None => true,
+                    Some((best_index, _, best_next)) => {
+                        (next.offset, atom.priority())
+                            > (best_next.offset, mode.atoms[*best_index].priority())
+                    }
+                };
+                if better {
+                    best = Some((candidate.index, token, next));
+                }
+                if strategy == LexStrategy::FirstMatch {
+                    return ControlFlow::Break(());
+                }
+            }
+            ControlFlow::Continue(())
+        };
+
+        match mode.dispatch {
+            Some(dispatch) => {
+                let all_candidates = strategy == LexStrategy::LongestMatch;
+                let _ = dispatch.for_each_candidate(cursor.rest, all_candidates, &mut consider);
+            }
+            None => {
+                let atoms = mode.atoms.iter().enumerate();
+                let opaque = atoms.clone().filter(|(_, atom)| atom.is_opaque());
+                let other = atoms.filter(|(_, atom)| !atom.is_opaque());
+                for (index, _) in opaque.chain(other) {
+                    if consider(Candidate::new(index)).is_break() {
+                        break;
+                    }
+                }
+            }
         }
-        cursor = cursor.advance(char_len);
+
+        best
     }
 
-    // Flush any remaining unknown text at EOF
-    if let Some((start, text)) = pending_unknown {
-        let len = text.len();
-        let span = SourceSpan::new(start.into(), len);
-        let location = SourceLocation { span };
+    /// Pushes a run of characters no atom recognized as an `Unknown` token, and reports it.
+    fn push_unknown(
+        &self,
+        trees: &mut Vec<TokenTree>,
+        errors: &mut Vec<LexError>,
+        start: usize,
+        text: String,
+    ) {
+        let span = SourceSpan::new(start.into(), text.len());
+        errors.push(LexError::UnknownCharacters { span });
         trees.push(TokenTree::Token(Token {
             kind: AtomKind::Other("Unknown".to_string()),
             text,
-            location,
+            location: SourceLocation {
+                span,
+                file: self.file,
+            },
             atom_index: None,
             binding: None,
+            leading_trivia: Vec::new(),
+            trailing_trivia: Vec::new(),
+            value: None,
         }));
     }
 
-    (trees, cursor)
+    /// If `token` is whitespace that starts a line, records and returns the
+    /// line's indentation width.
+    fn line_start(&self, token: &Token) -> Option<usize> {
+        let width = line_start_indentation(token, self.input_start.get())?;
+        self.line_indentation.set(width);
+        Some(width)
+    }
+
+    /// Applies the mode transition requested by `atom`, if any.
+    /// The group's starting mode is never popped.
+    fn apply_transition(&self, modes: &mut Vec<ActiveMode<'l>>, atom: &dyn Atom) {
+        match atom.mode_transition() {
+            Some(ModeTransition::Push(name)) => modes.push(self.mode(&name)),
+            Some(ModeTransition::Pop) if modes.len() > 1 => {
+                modes.pop();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The width of a tab stop when measuring indentation.
+const TAB_WIDTH: usize = 8;
+
+/// Wraps indented runs of top-level trees in synthetic [`INDENT`] groups.
+///
+/// A line starts after any whitespace containing a newline; its indentation is
+/// the whitespace after the last newline. The first line, after any blank
+/// lines, sets the base level. Lines holding only trivia, such as comments,
+/// count as blank.
+/// Trivia between lines stays outside the blocks it separates, so a block's
+/// span runs from its first token to its last.
+fn apply_offside_rule(
+    trees: Vec<TokenTree>,
+    language: &(impl Language + ?Sized),
+    file: FileId,
+    errors: &mut Vec<LexError>,
+) -> Vec<TokenTree> {
+    // The open blocks, outermost first, with their indentation width.
+    let mut blocks: Vec<(usize, Vec<TokenTree>)> = vec![(0, Vec::new())];
+    let mut pending_trivia: Vec<TokenTree> = Vec::new();
+    let mut seen_first_line = false;
+
+    for tree in trees {
+        if let TokenTree::Token(token) = &tree
+            && language.is_trivia(&token.kind)
+        {
+            pending_trivia.push(tree);
+            continue;
+        }
+
+        let line_start = match line_indentation(&pending_trivia) {
+            Some(indentation) => Some(indentation),
+            // The first line may start at the beginning of the input.
+            None if !seen_first_line => Some(leading_indentation(&pending_trivia, &tree)),
+            None => None,
+        };
+
+        if let Some((indentation, span)) = line_start {
+            let width = measure_indentation(&indentation, span, errors);
+
+            if !seen_first_line {
+                seen_first_line = true;
+                blocks[0].0 = width;
+            } else {
+                let mut dedented = false;
+                while blocks.len() > 1 && width < blocks.last().unwrap().0 {
+                    close_block(&mut blocks, file);
+                    dedented = true;
+                }
+
+                let level = blocks.last().unwrap().0;
+                if width < level || (dedented && width > level) {
+                    errors.push(LexError::InconsistentDedent { span });
+                } else if width > level {
+                    // The line break before the block belongs to the enclosing block.
+                    let parent = &mut blocks.last_mut().unwrap().1;
+                    parent.append(&mut pending_trivia);
+                    blocks.push((width, Vec::new()));
+                }
+            }
+        }
+
+        let current = &mut blocks.last_mut().unwrap().1;
+        current.append(&mut pending_trivia);
+        current.push(tree);
+    }
+
+    while blocks.len() > 1 {
+        close_block(&mut blocks, file);
+    }
+    let mut trees = blocks.pop().unwrap().1;
+    trees.append(&mut pending_trivia);
+    trees
+}
+
+/// The indentation of the line started by the last newline in `trivia`, and its
+/// span: the whitespace after that newline.
+fn line_indentation(trivia: &[TokenTree]) -> Option<(String, SourceSpan)> {
+    trivia.iter().enumerate().rev().find_map(|(i, tree)| {
+        let TokenTree::Token(token) = tree else {
+            return None;
+        };
+        if token.kind != AtomKind::Whitespace {
+            return None;
+        }
+        let newline = token.text.rfind('\n')?;
+        let start = token.location.span.offset() + newline + 1;
+        let mut indentation = token.text[newline + 1..].to_string();
+        indentation.extend(whitespace_run(&trivia[i + 1..]));
+        let span = SourceSpan::new(start.into(), indentation.len());
+        Some((indentation, span))
+    })
+}
+
+/// The whitespace at the start of the input, before the first line's `first` tree.
+fn leading_indentation(trivia: &[TokenTree], first: &TokenTree) -> (String, SourceSpan) {
+    let indentation: String = whitespace_run(trivia).collect();
+    let start = match trivia.first() {
+        Some(tree) => tree_span(tree),
+        None => tree_span(first),
+    }
+    .map_or(0, |span| span.offset());
+    let span = SourceSpan::new(start.into(), indentation.len());
+    (indentation, span)
+}
+
+/// The text of the whitespace tokens at the start of `trivia`.
+fn whitespace_run(trivia: &[TokenTree]) -> impl Iterator<Item = &str> {
+    trivia.iter().map_while(|tree| match tree {
+        TokenTree::Token(token) if token.kind == AtomKind::Whitespace => Some(token.text.as_str()),
+        _ => None,
+    })
+}
+
+/// Measures an indentation string, reporting tabs.
+fn measure_indentation(indentation: &str, span: SourceSpan, errors: &mut Vec<LexError>) -> usize {
+    if indentation.contains('\t') {
+        if indentation.contains(' ') {
+            errors.push(LexError::MixedIndentation { span });
+        } else {
+            errors.push(LexError::TabIndentation { span });
+        }
+    }
+
+    indentation_width(indentation)
+}
+
+/// If `token` is whitespace that starts a line, returns the line's indentation width.
+/// Whitespace at `input_start` starts the first line.
+fn line_start_indentation(token: &Token, input_start: Option<usize>) -> Option<usize> {
+    if token.kind != AtomKind::Whitespace {
+        return None;
+    }
+    let indentation = match token.text.rfind('\n') {
+        Some(i) => &token.text[i + 1..],
+        None if Some(token.location.span.offset()) == input_start => &token.text,
+        None => return None,
+    };
+    Some(indentation_width(indentation))
+}
+
+/// Finds the indentation of the last line started within `trees`, as the lexer
+/// saw it after lexing them from `input_start`.
+pub(crate) fn last_line_indentation(
+    trees: &[TokenTree],
+    input_start: Option<usize>,
+) -> Option<usize> {
+    trees.iter().rev().find_map(|tree| match tree {
+        TokenTree::Token(token) => line_start_indentation(token, input_start),
+        TokenTree::Delimited(_, children, _, _) => last_line_indentation(children, input_start),
+        _ => None,
+    })
+}
+
+/// Measures the width of `indentation`, with tabs advancing to the next tab stop.
+fn indentation_width(indentation: &str) -> usize {
+    indentation.chars().fold(0, |width, c| match c {
+        '\t' => (width / TAB_WIDTH + 1) * TAB_WIDTH,
+        _ => width + 1,
+    })
+}
+
+/// Pops the innermost block and adds it to its parent as an [`INDENT`] group.
+fn close_block(blocks: &mut Vec<(usize, Vec<TokenTree>)>, file: FileId) {
+    let (_, children) = blocks.pop().unwrap();
+    let start = children
+        .first()
+        .and_then(tree_span)
+        .map_or(0, |s| s.offset());
+    let end = children
+        .last()
+        .and_then(tree_span)
+        .map_or(start, |s| s.offset() + s.len());
+    let location = SourceLocation {
+        span: SourceSpan::new(start.into(), end - start),
+        file,
+    };
+    blocks
+        .last_mut()
+        .unwrap()
+        .1
+        .push(TokenTree::Delimited(INDENT, children, location, true));
+}
+
+pub(crate) fn tree_span(tree: &TokenTree) -> Option<SourceSpan> {
+    tree_location(tree).map(|location| location.span)
+}
+
+/// The location of a tree, from its first to its last token. Groups take
+/// theirs from their children; empty trees have none.
+pub(crate) fn tree_location(tree: &TokenTree) -> Option<SourceLocation> {
+    match tree {
+        TokenTree::Token(t) => Some(t.location.clone()),
+        TokenTree::Delimited(_, _, loc, _) => Some(loc.clone()),
+        TokenTree::Node(node) => Some(node.location.clone()),
+        TokenTree::Field(_, value) => tree_location(value),
+        TokenTree::Group(children) => {
+            let first = children.iter().find_map(tree_location)?;
+            let last = children.iter().rev().find_map(tree_location)?;
+            let end = last.span.offset() + last.span.len();
+            Some(SourceLocation {
+                span: SourceSpan::new(first.span.offset().into(), end - first.span.offset()),
+                file: first.file,
+            })
+        }
+        TokenTree::Error(_, loc) => Some(loc.clone()),
+        TokenTree::Empty => None,
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::atom::AtomKind;
+    use crate::atoms::{LiteralAtom, RegexAtom};
+    use crate::language::LexerMode;
     use crate::mock::MockLanguage;
 
+    /// A language with `"a ${b} c"` string interpolation.
+    fn interpolating_language() -> MockLanguage {
+        MockLanguage::new()
+            .with_delimiter(Delimiter::new("string", "\"", "\"").with_mode("string"))
+            .with_mode(
+                LexerMode::new("string")
+                    .with_atom(RegexAtom::new(AtomKind::String, r#"([^"$\\]|\\.|\$[^{])+"#))
+                    .with_delimiter(
+                        Delimiter::new("interpolation", "${", "}").with_mode(ROOT_MODE),
+                    ),
+            )
+    }
+
     #[test]
     fn test_lex_simple() {
         let lang = MockLanguage::new();
@@ -210,4 +891,524 @@ mod tests {
             panic!("Expected token");
         }
     }
+
+    #[test]
+    fn test_lex_diagnostics_unclosed_delimiter() {
+        let lang = MockLanguage::new();
+        let (trees, errors) = lex_with_diagnostics("a (b (c)", &lang);
+
+        assert!(matches!(&trees[2], TokenTree::Delimited(_, _, _, false)));
+        assert_eq!(
+            errors,
+            vec![LexError::UnclosedDelimiter {
+                delimiter: "(".into(),
+                span: SourceSpan::new(2.into(), 1)
+            }]
+        );
+    }
+
+    #[test]
+    fn test_lex_diagnostics_mismatched_closer() {
+        let lang = MockLanguage::new().with_delimiter(Delimiter::new("bracket", "[", "]"));
+        let (_, errors) = lex_with_diagnostics("(a])", &lang);
+
+        assert_eq!(
+            errors,
+            vec![LexError::MismatchedDelimiter {
+                delimiter: "]".into(),
+                span: SourceSpan::new(2.into(), 1),
+                opener: SourceSpan::new(0.into(), 1),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_lex_diagnostics_stray_closer() {
+        let lang = MockLanguage::new();
+        let (trees, errors) = lex_with_diagnostics("a ?) b", &lang);
+
+        // The unknown run and the closer are reported separately.
+        let texts: Vec<String> = trees.iter().map(|t| t.to_sexp()).collect();
+        assert_eq!(
+            texts,
+            [r#""a""#, r#"" ""#, r#""?""#, r#"")""#, r#"" ""#, r#""b""#]
+        );
+        assert_eq!(
+            errors,
+            vec![
+                LexError::UnknownCharacters {
+                    span: SourceSpan::new(2.into(), 1)
+                },
+                LexError::UnexpectedClosingDelimiter {
+                    delimiter: ")".into(),
+                    span: SourceSpan::new(3.into(), 1)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lex_diagnostics_unknown_run() {
+        let lang = MockLanguage::new();
+        let (_, errors) = lex_with_diagnostics("a 123", &lang);
+
+        assert_eq!(
+            errors,
+            vec![LexError::UnknownCharacters {
+                span: SourceSpan::new(2.into(), 3)
+            }]
+        );
+    }
+
+    fn recovering_sexp(input: &str) -> (String, Vec<LexError>) {
+        let lang = MockLanguage::new()
+            .with_delimiter(Delimiter::new("brace", "{", "}").with_indentation_recovery());
+        let (trees, errors) = lex_with_diagnostics(input, &lang);
+        let sexp: Vec<String> = trees
+            .iter()
+            .map(|t| without_whitespace(t).to_sexp())
+            .collect();
+        (sexp.join(" "), errors)
+    }
+
+    fn without_whitespace(tree: &TokenTree) -> TokenTree {
+        match tree {
+            TokenTree::Delimited(d, children, loc, closed) => TokenTree::Delimited(
+                d.clone(),
+                children
+                    .iter()
+                    .filter(|t| !matches!(t, TokenTree::Token(t) if t.kind == AtomKind::Whitespace))
+                    .map(without_whitespace)
+                    .collect(),
+                loc.clone(),
+                *closed,
+            ),
+            other => other.clone(),
+        }
+    }
+
+    #[test]
+    fn test_lex_indentation_recovery_missing_closer() {
+        let input = "a {\n    b {\n        c\n    d\n}\ne {\n}";
+        let (sexp, errors) = recovering_sexp(input);
+
+        // `b`'s group ends before `d`, so the `}` on the last line closes `a`'s group
+        // and `e`'s group stays at the top level.
+        assert_eq!(
+            sexp,
+            r#""a" " " (brace "b" (brace "c") "d") "\n" "e" " " (brace )"#
+        );
+        assert_eq!(
+            errors,
+            vec![LexError::UnclosedDelimiter {
+                delimiter: "{".into(),
+                span: SourceSpan::new(10.into(), 1)
+            }]
+        );
+    }
+
+    #[test]
+    fn test_lex_indentation_recovery_outdented_closer() {
+        // A closer outdented past the inner opener belongs to the outer group.
+        let (sexp, errors) = recovering_sexp("a {\n    b {\n        c\n}");
+        assert_eq!(sexp, r#""a" " " (brace "b" (brace "c"))"#);
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn test_lex_indentation_recovery_leaves_closed_groups_alone() {
+        let (sexp, errors) = recovering_sexp("a {\n    b {\n        c\n    }\n}");
+        assert_eq!(sexp, r#""a" " " (brace "b" (brace "c"))"#);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn test_lex_without_indentation_recovery_nests_to_eof() {
+        let lang = MockLanguage::new().with_delimiter(Delimiter::new("brace", "{", "}"));
+        let (trees, errors) = lex_with_diagnostics("a {\n    b {\n        c\n    d\n}", &lang);
+
+        assert!(matches!(&trees[2], TokenTree::Delimited(_, _, _, false)));
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn test_lex_opaque_atom_wins_over_opener() {
+        // An OCaml-style comment starts with the paren opener.
+        let lang = MockLanguage::new().with_atom(Box::new(
+            RegexAtom::new(AtomKind::Other("Comment".into()), r"\(\*(?s:.)*?\*\)").opaque(),
+        ));
+        let input = "(* a ) b *) foo";
+        let trees = lex(input, &lang);
+
+        // comment, space, foo
+        assert_eq!(trees.len(), 3);
+        if let TokenTree::Token(t) = &trees[0] {
+            assert_eq!(t.text, "(* a ) b *)");
+        } else {
+            panic!("Expected comment token, got {:?}", trees[0]);
+        }
+    }
+
+    #[test]
+    fn test_lex_opaque_atom_wins_over_terminator() {
+        let lang = MockLanguage::new().with_atom(Box::new(
+            RegexAtom::new(AtomKind::String, r"\)[a-z]*\)").opaque(),
+        ));
+        let input = "(foo )x) bar) baz";
+        let trees = lex(input, &lang);
+
+        // (foo )x) bar), space, baz
+        assert_eq!(trees.len(), 3);
+        if let TokenTree::Delimited(_, inner, _, closed) = &trees[0] {
+            assert!(closed);
+            assert!(inner.iter().any(
+                |t| matches!(t, TokenTree::Token(t) if t.text == ")x)" && t.kind == AtomKind::String)
+            ));
+        } else {
+            panic!("Expected delimited");
+        }
+    }
+
+    #[test]
+    fn test_lex_non_opaque_atom_loses_to_opener() {
+        let lang = MockLanguage::new().with_atom(Box::new(RegexAtom::new(
+            AtomKind::Other("Comment".into()),
+            r"\(\*(?s:.)*?\*\)",
+        )));
+        let input = "(* a *)";
+        let trees = lex(input, &lang);
+
+        assert_eq!(trees.len(), 1);
+        assert!(matches!(&trees[0], TokenTree::Delimited(..)));
+    }
+
+    #[test]
+    fn test_lex_delimiter_mode() {
+        let lang = interpolating_language();
+        let input = r#""a ${b} c" d"#;
+        let trees = lex(input, &lang);
+
+        // string, space, d
+        assert_eq!(trees.len(), 3);
+        let TokenTree::Delimited(delim, inner, _, true) = &trees[0] else {
+            panic!("Expected closed string, got {:?}", trees[0]);
+        };
+        assert_eq!(delim.kind, "string");
+        // "a ", ${b}, " c"
+        assert_eq!(inner.len(), 3);
+        assert!(
+            matches!(&inner[0], TokenTree::Token(t) if t.text == "a " && t.kind == AtomKind::String)
+        );
+        assert!(
+            matches!(&inner[2], TokenTree::Token(t) if t.text == " c" && t.kind == AtomKind::String)
+        );
+
+        let TokenTree::Delimited(delim, code, _, true) = &inner[1] else {
+            panic!("Expected interpolation, got {:?}", inner[1]);
+        };
+        assert_eq!(delim.kind, "interpolation");
+        assert!(
+            matches!(&code[..], [TokenTree::Token(t)] if t.text == "b" && t.kind == AtomKind::Identifier)
+        );
+
+        // The root mode is restored after the string closes.
+        assert!(matches!(&trees[2], TokenTree::Token(t) if t.kind == AtomKind::Identifier));
+    }
+
+    #[test]
+    fn test_lex_nested_interpolation() {
+        let lang = interpolating_language();
+        let input = r#""${ "x${y}" }""#;
+        let trees = lex(input, &lang);
+
+        assert_eq!(trees.len(), 1);
+        let TokenTree::Delimited(_, inner, _, true) = &trees[0] else {
+            panic!("Expected closed string");
+        };
+        let TokenTree::Delimited(_, code, _, true) = &inner[0] else {
+            panic!("Expected interpolation");
+        };
+        // space, nested string, space
+        assert_eq!(code.len(), 3);
+        assert!(matches!(&code[1], TokenTree::Delimited(d, _, _, true) if d.kind == "string"));
+    }
+
+    #[test]
+    fn test_lex_mode_atom_index() {
+        let lang = interpolating_language();
+        let trees = lex(r#""a""#, &lang);
+
+        let TokenTree::Delimited(_, inner, _, _) = &trees[0] else {
+            panic!("Expected string");
+        };
+        let TokenTree::Token(t) = &inner[0] else {
+            panic!("Expected token");
+        };
+        let atom = lang.atom(t.atom_index.unwrap()).unwrap();
+        assert_eq!(atom.kind(), AtomKind::String);
+    }
+
+    #[test]
+    fn test_lex_atom_mode_transition() {
+        // A template language: text until `{{`, then code until `}}`.
+        let lang = MockLanguage::new()
+            .with_atom(Box::new(
+                LiteralAtom::new(AtomKind::Operator, "%%")
+                    .with_transition(ModeTransition::Push("text".into())),
+            ))
+            .with_mode(
+                LexerMode::new("text")
+                    .with_atom(
+                        LiteralAtom::new(AtomKind::Operator, "%%")
+                            .with_transition(ModeTransition::Pop),
+                    )
+                    .with_atom(RegexAtom::new(AtomKind::String, r"([^%]|%[^%])+")),
+            );
+        let input = "a %%b c%% d";
+        let trees = lex(input, &lang);
+
+        let texts: Vec<_> = trees
+            .iter()
+            .map(|t| match t {
+                TokenTree::Token(t) => (t.text.as_str(), t.kind.clone()),
+                _ => panic!("Expected token"),
+            })
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                ("a", AtomKind::Identifier),
+                (" ", AtomKind::Whitespace),
+                ("%%", AtomKind::Operator),
+                ("b c", AtomKind::String),
+                ("%%", AtomKind::Operator),
+                (" ", AtomKind::Whitespace),
+                ("d", AtomKind::Identifier),
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg_attr(
+        debug_assertions,
+        should_panic(expected = "unknown lexer mode `missing`")
+    )]
+    fn test_lex_unknown_mode_falls_back_to_root() {
+        let lang = MockLanguage::new();
+        let trees = lex_in_mode("foo", &lang, "missing");
+        assert!(matches!(&trees[..], [TokenTree::Token(t)] if t.kind == AtomKind::Identifier));
+    }
+
+    fn block_sexp(input: &str) -> (String, Vec<LexError>) {
+        let lang = MockLanguage::new().with_symbol(":").with_offside_rule();
+        let (trees, errors) = lex_with_diagnostics(input, &lang);
+        let sexp: Vec<String> = trees
+            .iter()
+            .filter(|t| !matches!(t, TokenTree::Token(t) if t.kind == AtomKind::Whitespace))
+            .map(|t| t.to_sexp())
+            .collect();
+        (sexp.join(" "), errors)
+    }
+
+    #[test]
+    fn test_lex_offside_block() {
+        let input = "foo:\n    bar\n    baz\nqux";
+        let lang = MockLanguage::new().with_symbol(":").with_offside_rule();
+        let (trees, errors) = lex_with_diagnostics(input, &lang);
+        assert!(errors.is_empty());
+
+        // foo, :, newline, block, newline, qux
+        assert_eq!(trees.len(), 6);
+        let TokenTree::Delimited(delim, inner, loc, true) = &trees[3] else {
+            panic!("Expected indented block, got {:?}", trees[3]);
+        };
+        assert_eq!(delim.kind, "indent");
+        // bar, newline, baz
+        assert_eq!(inner.len(), 3);
+        // The span covers the indented region, from `bar` to `baz`.
+        assert_eq!(
+            &input[loc.span.offset()..loc.span.offset() + loc.span.len()],
+            "bar\n    baz"
+        );
+    }
+
+    #[test]
+    fn test_lex_offside_nested_blocks() {
+        let (sexp, errors) = block_sexp("a:\n  b:\n    c\n  d\ne");
+        assert!(errors.is_empty());
+        assert_eq!(
+            sexp,
+            r#""a" ":" (indent "b" ":" "\n    " (indent "c") "\n  " "d") "e""#
+        );
+
+        // Several blocks can close at once, and at end of input.
+        let (sexp, _) = block_sexp("a:\n  b:\n    c\nd:\n  e\n");
+        assert_eq!(
+            sexp,
+            r#""a" ":" (indent "b" ":" "\n    " (indent "c")) "d" ":" (indent "e")"#
+        );
+    }
+
+    #[test]
+    fn test_lex_offside_ignores_blank_lines_and_brackets() {
+        let (sexp, errors) = block_sexp("a:\n  b\n\n  c (\nd\n    e)\nf");
+        assert!(errors.is_empty());
+        assert_eq!(
+            sexp,
+            r#""a" ":" (indent "b" "\n\n  " "c" " " (paren "\n" "d" "\n    " "e")) "f""#
+        );
+    }
+
+    #[test]
+    fn test_lex_offside_tab_diagnostics() {
+        let (_, errors) = block_sexp("a:\n\tb");
+        assert_eq!(
+            errors,
+            vec![LexError::TabIndentation {
+                span: SourceSpan::new(3.into(), 1)
+            }]
+        );
+
+        let (_, errors) = block_sexp("a:\n \tb");
+        assert!(matches!(&errors[..], [LexError::MixedIndentation { .. }]));
+    }
+
+    #[test]
+    fn test_lex_offside_inconsistent_dedent() {
+        let (sexp, errors) = block_sexp("a:\n    b\n  c");
+        assert_eq!(
+            errors,
+            vec![LexError::InconsistentDedent {
+                span: SourceSpan::new(9.into(), 2)
+            }]
+        );
+        // The misaligned line stays in the enclosing block.
+        assert_eq!(sexp, r#""a" ":" (indent "b") "c""#);
+    }
+
+    #[test]
+    fn test_lex_offside_leading_blank_lines() {
+        let (sexp, errors) = block_sexp("\n  a\n  b");
+        assert!(errors.is_empty());
+        assert_eq!(sexp, r#""a" "b""#);
+
+        let (sexp, errors) = block_sexp("\na:\n  b\nc");
+        assert!(errors.is_empty());
+        assert_eq!(sexp, r#""a" ":" (indent "b") "c""#);
+
+        // Lines holding only whitespace are blank too.
+        let (sexp, errors) = block_sexp("    \n\t\na:\n  b\n   \n  c\nd");
+        assert!(errors.is_empty());
+        assert_eq!(sexp, r#""a" ":" (indent "b" "\n   \n  " "c") "d""#);
+    }
+
+    #[test]
+    fn test_lex_offside_skips_comment_lines() {
+        let lang = MockLanguage::new()
+            .with_symbol(":")
+            .with_atom(Box::new(RegexAtom::new(AtomKind::Comment, r"#[^\n]*")))
+            .with_offside_rule();
+        let sexp = |input: &str| {
+            let (trees, errors) = lex_with_diagnostics(input, &lang);
+            assert!(errors.is_empty(), "{errors:?}");
+            let sexp: Vec<String> = trees.iter().map(|t| t.to_sexp()).collect();
+            sexp.join(" ")
+        };
+
+        assert_eq!(
+            sexp("if:\n    a\n# note\n    b\nc"),
+            r##""if" ":" "\n    " (indent "a" "\n" "# note" "\n    " "b") "\n" "c""##
+        );
+        assert_eq!(
+            sexp("if:\n    a\n        # note\n    b\nc"),
+            r##""if" ":" "\n    " (indent "a" "\n        " "# note" "\n    " "b") "\n" "c""##
+        );
+        // A comment line before a block stays outside it, like a blank line.
+        assert_eq!(
+            sexp("# head\nif: # why\n  # first\n  a\n"),
+            r##""# head" "\n" "if" ":" " " "# why" "\n  " "# first" "\n  " (indent "a") "\n""##
+        );
+    }
+
+    #[test]
+    fn test_lex_without_offside_rule_has_no_blocks() {
+        let lang = MockLanguage::new().with_symbol(":");
+        let trees = lex("a:\n  b", &lang);
+        assert!(trees.iter().all(|t| matches!(t, TokenTree::Token(_))));
+    }
+
+    #[test]
+    fn test_lex_offside_block_matches_enter() {
+        use crate::language::INDENT;
+        use crate::shape::{NoOpMatchContext, Shape, enter, rep, seq, term};
+        use crate::token::TokenStream;
+
+        let lang = MockLanguage::new().with_symbol(":").with_offside_rule();
+        let trees = lex("foo:\n  bar\n  baz", &lang);
+        let shape = seq(
+            seq(term(AtomKind::Identifier), term(":")),
+            enter(INDENT, rep(term(AtomKind::Identifier))),
+        );
+
+        let (_, rest) = shape
+            .match_shape(TokenStream::new(&trees), &mut NoOpMatchContext)
+            .unwrap();
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn test_lex_first_match_uses_declaration_order() {
+        let lang = MockLanguage::new().with_symbol("==").with_symbol("=");
+        let trees = lex("==", &lang);
+        // `=` was inserted last, so it comes first and matches twice.
+        assert_eq!(trees.len(), 2);
+    }
+
+    #[test]
+    fn test_lex_longest_match() {
+        let lang = MockLanguage::new()
+            .with_symbol("==")
+            .with_symbol("=")
+            .with_lex_strategy(LexStrategy::LongestMatch);
+        let trees = lex("== =", &lang);
+        let texts: Vec<_> = trees.iter().map(|t| t.to_sexp()).collect();
+        assert_eq!(texts, vec![r#""==""#, r#"" ""#, r#""=""#]);
+    }
+
+    #[test]
+    fn test_lex_longest_match_identifier_over_keyword_prefix() {
+        // The identifier atom comes after the keyword atom, but is longer.
+        let lang = MockLanguage::new().with_lex_strategy(LexStrategy::LongestMatch);
+        let trees = lex("letter", &lang);
+        let TokenTree::Token(t) = &trees[0] else {
+            panic!("Expected token");
+        };
+        assert_eq!(t.text, "letter");
+        assert_eq!(t.atom_index, Some(2));
+    }
+
+    #[test]
+    fn test_lex_longest_match_priority_breaks_ties() {
+        // Keywords have priority 1, so they beat identifiers of the same length
+        // even when declared later.
+        let lang = MockLanguage::new()
+            .with_atom(Box::new(RegexAtom::new(AtomKind::Identifier, r"[a-z]+")))
+            .with_lex_strategy(LexStrategy::LongestMatch);
+        let trees = lex("let", &lang);
+        let TokenTree::Token(t) = &trees[0] else {
+            panic!("Expected token");
+        };
+        assert_eq!(t.atom_index, Some(2));
+
+        // An explicit priority overrides that.
+        let lang = MockLanguage::new()
+            .with_atom(Box::new(
+                RegexAtom::new(AtomKind::Other("Name".into()), r"[a-z]+").with_priority(2),
+            ))
+            .with_lex_strategy(LexStrategy::LongestMatch);
+        let trees = lex("let", &lang);
+        assert!(
+            matches!(&trees[0], TokenTree::Token(t) if t.kind == AtomKind::Other("Name".into()))
+        );
+    }
 }
@@ -37,13 +37,22 @@
 //! }
 //! ```
 
+// Lets `#[derive(Shape)]` refer to `::mcparse` inside this crate too.
+extern crate self as mcparse;
+
+pub mod ast;
 pub mod atom;
 pub mod atoms;
+pub mod builder;
+pub mod compiled_lexer;
 pub mod completion;
+pub mod dynamic;
 pub mod highlighter;
 pub mod incremental;
 pub mod language;
 pub mod lexer;
+pub mod line_index;
+pub mod lint;
 pub mod r#macro;
 mod macros;
 #[cfg(test)]
@@ -51,19 +60,29 @@ pub mod mock;
 pub mod parser;
 pub mod scoping;
 pub mod shape;
+pub mod source_map;
+pub mod stream;
 pub mod token;
+pub mod trivia;
 
 pub use atom::{Atom, AtomKind};
+#[cfg(feature = "derive")]
+pub use mcparse_derive::Shape;
+pub use builder::{LanguageBuilder, LayeredLanguage};
+pub use dynamic::{DynamicLanguage, GrammarError};
 pub use highlighter::{HighlightStyle, Highlighter};
 pub use incremental::{GreenTree, RedNode, TextEdit, incremental_relex};
 pub use language::Language;
+pub use line_index::{LineCol, LineIndex, PositionEncoding};
 pub use r#macro::{ExpansionResult, Macro, MacroContext};
 pub use parser::Parser;
 pub use shape::{
-    AdjacencyConstraint, MatchContext, MatchResult, Shape, adjacent, choice, empty, end, enter,
-    expr, joined, opt, recover, rep, separated, seq, term,
+    AdjacencyConstraint, MatchContext, MatchResult, Shape, Structure, adjacent, choice, empty, end,
+    enter, expr, field, joined, memo, node, opt, recover, rep, separated, seq, term,
 };
-pub use token::{Cursor, SourceLocation, Token, TokenTree};
+pub use shape::typed::TypedShape;
+pub use source_map::{FileId, SourceMap};
+pub use token::{Cursor, LiteralValue, SourceLocation, SyntaxNode, Token, TokenTree};
 
 #[cfg(test)]
 mod atoms_tests;
@@ -106,6 +125,13 @@ mod book_tests {
     #[doc = include_str!("../docs/book/src/advanced/custom-shapes.md")]
     pub struct CustomShapes;
 
+    #[doc = include_str!("../docs/book/src/advanced/dynamic-languages.md")]
+    pub struct DynamicLanguages;
+
+    #[cfg(feature = "derive")]
+    #[doc = include_str!("../docs/book/src/advanced/typed-syntax-trees.md")]
+    pub struct TypedSyntaxTrees;
+
     #[doc = include_str!("../docs/book/src/reference/atoms.md")]
     pub struct RefAtoms;
 
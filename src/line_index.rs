@@ -0,0 +1,315 @@
+//! Conversion between byte offsets and line/column positions.
+//!
+//! Everything in McParse is located by byte offset (see [`SourceLocation`]), but
+//! editors, LSP clients, and terminals think in lines and columns, and don't agree
+//! on what a column counts. [`LineIndex`] answers both questions for a document and
+//! stays up to date as [`TextEdit`]s are applied to it.
+
+use crate::incremental::TextEdit;
+use crate::token::SourceLocation;
+use std::ops::Range;
+
+/// The unit that columns are counted in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PositionEncoding {
+    /// Bytes of UTF-8, matching the offsets used throughout McParse.
+    #[default]
+    Utf8,
+    /// UTF-16 code units, as used by the Language Server Protocol and most editors.
+    Utf16,
+    /// Unicode scalar values (`char`s).
+    Char,
+}
+
+impl PositionEncoding {
+    /// The width of a character that takes `utf8_len` bytes of UTF-8.
+    fn width(self, utf8_len: usize) -> usize {
+        match self {
+            PositionEncoding::Utf8 => utf8_len,
+            PositionEncoding::Utf16 if utf8_len == 4 => 2,
+            PositionEncoding::Utf16 | PositionEncoding::Char => 1,
+        }
+    }
+}
+
+/// A zero-based line and column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LineCol {
+    pub line: usize,
+    pub col: usize,
+}
+
+impl LineCol {
+    pub fn new(line: usize, col: usize) -> Self {
+        Self { line, col }
+    }
+}
+
+/// A table of line starts for a document.
+///
+/// Only the positions of line breaks and non-ASCII characters are stored, so
+/// the index is much smaller than the text it describes. Lines are separated by
+/// `\n`; a `\r` before it counts as the last column of its line.
+///
+/// ```rust
+/// use mcparse::line_index::{LineCol, LineIndex, PositionEncoding};
+///
+/// let index = LineIndex::new("let a = 1;\nlet 😀 = 2;");
+/// let position = index.line_col(20, PositionEncoding::Utf16).unwrap();
+/// assert_eq!(position, LineCol::new(1, 7));
+/// assert_eq!(index.offset(position, PositionEncoding::Utf16), Some(20));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineIndex {
+    /// The byte offset at which each line starts. The first is always 0.
+    line_starts: Vec<usize>,
+    /// The byte offset and UTF-8 length of every non-ASCII character, in order.
+    wide_chars: Vec<(usize, usize)>,
+    /// The length of the text in bytes.
+    len: usize,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> Self {
+        let (line_starts, wide_chars) = scan(text, 0);
+        Self {
+            line_starts: [0].into_iter().chain(line_starts).collect(),
+            wide_chars,
+            len: text.len(),
+        }
+    }
+
+    /// The number of lines. Text ending in a line break has an empty last line.
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// The length of the indexed text in bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The byte range of `line`, excluding its line break.
+    pub fn line_range(&self, line: usize) -> Option<Range<usize>> {
+        let start = *self.line_starts.get(line)?;
+        let end = match self.line_starts.get(line + 1) {
+            Some(next) => next - 1,
+            None => self.len,
+        };
+        Some(start..end)
+    }
+
+    /// Converts a byte offset into a line and column.
+    ///
+    /// Returns `None` if `offset` is past the end of the text or inside a character.
+    pub fn line_col(&self, offset: usize, encoding: PositionEncoding) -> Option<LineCol> {
+        if offset > self.len {
+            return None;
+        }
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let start = self.line_starts[line];
+
+        let mut col = offset - start;
+        for &(char_start, char_len) in self.wide_chars_in(start..offset) {
+            if offset < char_start + char_len {
+                return None;
+            }
+            col = col - char_len + encoding.width(char_len);
+        }
+        Some(LineCol { line, col })
+    }
+
+    /// Converts a line and column into a byte offset.
+    ///
+    /// Returns `None` if the line doesn't exist, the column is past the end of the
+    /// line, or the column falls inside a character (e.g. between the halves of a
+    /// UTF-16 surrogate pair).
+    pub fn offset(&self, position: LineCol, encoding: PositionEncoding) -> Option<usize> {
+        let range = self.line_range(position.line)?;
+
+        // Walk the wide characters, counting the ASCII between them one unit per byte.
+        let mut offset = range.start;
+        let mut col = 0;
+        for &(char_start, char_len) in self.wide_chars_in(range.clone()) {
+            let ascii = char_start - offset;
+            if position.col < col + ascii {
+                break;
+            }
+            col += ascii;
+            offset = char_start;
+            if position.col == col {
+                return Some(offset);
+            }
+
+            let width = encoding.width(char_len);
+            if position.col < col + width {
+                return None;
+            }
+            col += width;
+            offset += char_len;
+        }
+
+        let offset = offset + (position.col - col);
+        (offset <= range.end).then_some(offset)
+    }
+
+    /// Converts a location into a range of line/column positions.
+    pub fn range(
+        &self,
+        location: &SourceLocation,
+        encoding: PositionEncoding,
+    ) -> Option<Range<LineCol>> {
+        let start = location.span.offset();
+        let end = start + location.span.len();
+        Some(self.line_col(start, encoding)?..self.line_col(end, encoding)?)
+    }
+
+    /// Updates the index for `edit` having been applied to the text, without
+    /// rescanning the unchanged parts.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the edit's range is out of bounds.
+    pub fn apply_edit(&mut self, edit: &TextEdit) {
+        assert!(
+            edit.start <= edit.end && edit.end <= self.len,
+            "edit {}..{} is out of bounds for text of length {}",
+            edit.start,
+            edit.end,
+            self.len
+        );
+        let removed = edit.end - edit.start;
+        let inserted = edit.new_text.len();
+        let shift = |offset: usize| offset - removed + inserted;
+        let (line_starts, wide_chars) = scan(&edit.new_text, edit.start);
+
+        // A line start in `start + 1..=end` follows a line break that was removed.
+        let first = self.line_starts.partition_point(|&s| s <= edit.start);
+        let last = self.line_starts.partition_point(|&s| s <= edit.end);
+        let added = line_starts.len();
+        self.line_starts.splice(first..last, line_starts);
+        for start in &mut self.line_starts[first + added..] {
+            *start = shift(*start);
+        }
+
+        let first = self.wide_chars.partition_point(|&(s, _)| s < edit.start);
+        let last = self.wide_chars.partition_point(|&(s, _)| s < edit.end);
+        let added = wide_chars.len();
+        self.wide_chars.splice(first..last, wide_chars);
+        for (start, _) in &mut self.wide_chars[first + added..] {
+            *start = shift(*start);
+        }
+
+        self.len = shift(self.len);
+    }
+
+    /// The wide characters that start within `range`.
+    fn wide_chars_in(&self, range: Range<usize>) -> &[(usize, usize)] {
+        let first = self.wide_chars.partition_point(|&(s, _)| s < range.start);
+        let last = self.wide_chars.partition_point(|&(s, _)| s < range.end);
+        &self.wide_chars[first..last]
+    }
+}
+
+/// Finds the line starts (after each `\n`) and wide characters of `text`, which
+/// starts at byte `base` of the document.
+fn scan(text: &str, base: usize) -> (Vec<usize>, Vec<(usize, usize)>) {
+    let mut line_starts = Vec::new();
+    let mut wide_chars = Vec::new();
+    for (i, c) in text.char_indices() {
+        if c == '\n' {
+            line_starts.push(base + i + 1);
+        } else if !c.is_ascii() {
+            wide_chars.push((base + i, c.len_utf8()));
+        }
+    }
+    (line_starts, wide_chars)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use PositionEncoding::{Char, Utf8, Utf16};
+
+    const TEXT: &str = "ab\ncé😀d\r\n\nx";
+
+    #[test]
+    fn test_line_col_in_each_encoding() {
+        let index = LineIndex::new(TEXT);
+        assert_eq!(index.line_count(), 4);
+
+        // `d` follows `c` (1 byte), `é` (2 bytes), and `😀` (4 bytes).
+        let d = TEXT.find('d').unwrap();
+        assert_eq!(index.line_col(d, Utf8), Some(LineCol::new(1, 7)));
+        assert_eq!(index.line_col(d, Utf16), Some(LineCol::new(1, 4)));
+        assert_eq!(index.line_col(d, Char), Some(LineCol::new(1, 3)));
+
+        assert_eq!(index.line_col(0, Utf16), Some(LineCol::new(0, 0)));
+        assert_eq!(index.line_col(TEXT.len(), Utf16), Some(LineCol::new(3, 1)));
+        assert_eq!(index.line_col(TEXT.len() + 1, Utf16), None);
+        // Inside the emoji.
+        assert_eq!(index.line_col(d - 2, Utf8), None);
+    }
+
+    #[test]
+    fn test_offset_round_trips() {
+        let index = LineIndex::new(TEXT);
+        for encoding in [Utf8, Utf16, Char] {
+            for (offset, _) in TEXT.char_indices().chain([(TEXT.len(), ' ')]) {
+                let position = index.line_col(offset, encoding).unwrap();
+                assert_eq!(
+                    index.offset(position, encoding),
+                    Some(offset),
+                    "{encoding:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_offset_rejects_invalid_positions() {
+        let index = LineIndex::new(TEXT);
+        // Between the halves of the emoji's surrogate pair.
+        assert_eq!(index.offset(LineCol::new(1, 3), Utf16), None);
+        // Past the end of the line and past the last line.
+        assert_eq!(index.offset(LineCol::new(0, 3), Utf16), None);
+        assert_eq!(index.offset(LineCol::new(4, 0), Utf16), None);
+        // The end of a line, before its line break, is valid.
+        assert_eq!(index.offset(LineCol::new(0, 2), Utf16), Some(2));
+    }
+
+    #[test]
+    fn test_range() {
+        let index = LineIndex::new(TEXT);
+        let location = SourceLocation::new(1, 5);
+        assert_eq!(
+            index.range(&location, Utf16),
+            Some(LineCol::new(0, 1)..LineCol::new(1, 2))
+        );
+    }
+
+    #[test]
+    fn test_apply_edit_matches_rebuilding() {
+        let edits = [
+            (0, 0, "hé\n"),
+            (3, 10, ""),
+            (4, 4, "😀\n\n"),
+            (TEXT.len(), TEXT.len(), "\nend"),
+            (0, TEXT.len(), "z"),
+        ];
+        for (start, end, new_text) in edits {
+            let edit = TextEdit {
+                start,
+                end,
+                new_text: new_text.to_string(),
+            };
+            let mut index = LineIndex::new(TEXT);
+            index.apply_edit(&edit);
+            assert_eq!(index, LineIndex::new(&edit.apply(TEXT)), "{edit:?}");
+        }
+    }
+}
@@ -0,0 +1,675 @@
+//! Grammar analysis.
+//!
+//! Some grammar mistakes fail silently: an atom that another atom always
+//! matches first never produces a token, an alternative after one that always
+//! succeeds is never tried, and a shape that reaches itself without consuming
+//! a token recurses until the stack overflows, and a delimiter or atom that
+//! enters a misspelled mode lexes with the root mode's atoms instead. [`lint`]
+//! looks for these in a language's atoms, delimiters, root shape and macro
+//! signatures.
+//!
+//! Shapes are inspected through [`Shape::structure`]. Custom shapes that build
+//! their body on the fly, like recursive grammar rules, should describe it
+//! with [`Structure::Rule`] so cycles through them can be found.
+
+use crate::atom::Atom;
+use crate::language::{Delimiter, Language, LexStrategy, ModeTransition, ROOT_MODE, SharedStr};
+use crate::shape::{Shape, Structure};
+use crate::token::Cursor;
+use miette::Diagnostic;
+use thiserror::Error;
+
+/// A likely mistake in a grammar.
+#[derive(Debug, Clone, PartialEq, Eq, Error, Diagnostic)]
+pub enum Lint {
+    /// Atom `atom` never matches `text`, because atom `shadowed_by` is chosen
+    /// instead. Atoms are numbered by their position in the mode.
+    #[error(
+        "atom {atom} in mode `{mode}` never matches `{text}`: atom {shadowed_by} matches it first"
+    )]
+    #[diagnostic(help("move the longer or more specific atom first, or raise its priority"))]
+    ShadowedAtom {
+        mode: SharedStr,
+        atom: usize,
+        text: String,
+        shadowed_by: usize,
+    },
+    #[error(
+        "unreachable alternative {alternative} in {location}: an earlier alternative always matches first"
+    )]
+    UnreachableChoice {
+        location: String,
+        alternative: String,
+    },
+    #[error(
+        "`{rule}` in {location} is left-recursive: it reaches itself without consuming a token"
+    )]
+    #[diagnostic(help("matching it overflows the stack; consume a token before recursing"))]
+    LeftRecursion { location: String, rule: String },
+    /// A delimiter or atom switches to a mode the language doesn't declare, so
+    /// the lexer falls back to the root mode there.
+    #[error("{location} enters unknown lexer mode `{name}`")]
+    #[diagnostic(help("declare the mode in `Language::modes`, or fix its name"))]
+    UnknownMode { location: String, name: SharedStr },
+}
+
+/// Checks the atoms and mode switches of every lexer mode, the root shape,
+/// and every macro signature of `language`.
+pub fn lint(language: &(impl Language + ?Sized)) -> Vec<Lint> {
+    let strategy = language.lex_strategy();
+    let mut lints = Vec::new();
+
+    let root = SharedStr::from(ROOT_MODE);
+    lint_atoms(&root, language.atoms(), strategy, &mut lints);
+    lint_mode_names(
+        language,
+        &root,
+        language.atoms(),
+        language.delimiters(),
+        &mut lints,
+    );
+    for mode in language.modes() {
+        lint_atoms(&mode.name, &mode.atoms, strategy, &mut lints);
+        lint_mode_names(
+            language,
+            &mode.name,
+            &mode.atoms,
+            &mode.delimiters,
+            &mut lints,
+        );
+    }
+
+    if let Some(root) = language.root() {
+        lints.extend(lint_shape(root, "the root shape"));
+    }
+    for mac in language.macros() {
+        let location = if mac.is_operator() {
+            format!("the signature of operator `{}`", mac.name())
+        } else {
+            format!("the signature of macro `{}`", mac.name())
+        };
+        lints.extend(lint_shape(mac.signature(), &location));
+    }
+    lints
+}
+
+/// Checks a single shape for unreachable alternatives and left recursion.
+///
+/// `location` names the shape in the lints, e.g. "the root shape".
+pub fn lint_shape(shape: &dyn Shape, location: &str) -> Vec<Lint> {
+    let mut analysis = Analysis {
+        location,
+        lints: Vec::new(),
+        active: Vec::new(),
+        head: Vec::new(),
+    };
+    analysis.visit(shape);
+    analysis.lints
+}
+
+/// Panics with every lint found in `language`, if there are any.
+///
+/// Meant for tests:
+///
+/// ```
+/// use mcparse::{define_language, lint::assert_lint_clean};
+///
+/// define_language! {
+///     struct Comparisons;
+///     atoms = [
+///         atom Operator = "==",
+///         atom Operator = "=",
+///     ];
+///     delimiters = [];
+/// }
+///
+/// assert_lint_clean(&Comparisons::new());
+/// ```
+#[track_caller]
+pub fn assert_lint_clean(language: &(impl Language + ?Sized)) {
+    let lints = lint(language);
+    if !lints.is_empty() {
+        let lints: Vec<_> = lints.iter().map(|lint| format!("  - {}", lint)).collect();
+        panic!("grammar has {} lint(s):\n{}", lints.len(), lints.join("\n"));
+    }
+}
+
+fn lint_atoms(
+    mode: &SharedStr,
+    atoms: &[Box<dyn Atom>],
+    strategy: LexStrategy,
+    lints: &mut Vec<Lint>,
+) {
+    for (index, atom) in atoms.iter().enumerate() {
+        for text in atom.literals() {
+            let Some(len) = match_len(atom.as_ref(), text) else {
+                continue;
+            };
+            let winner = atoms.iter().enumerate().find(|(other_index, other)| {
+                *other_index != index
+                    && match_len(other.as_ref(), text).is_some_and(|other_len| {
+                        beats(
+                            (*other_index, other.as_ref(), other_len),
+                            (index, atom.as_ref(), len),
+                            strategy,
+                        )
+                    })
+            });
+            if let Some((shadowed_by, _)) = winner {
+                lints.push(Lint::ShadowedAtom {
+                    mode: mode.clone(),
+                    atom: index,
+                    text: text.to_string(),
+                    shadowed_by,
+                });
+            }
+        }
+    }
+}
+
+/// Reports delimiters and atoms of `mode` that enter a mode `language` doesn't have.
+fn lint_mode_names(
+    language: &(impl Language + ?Sized),
+    mode: &SharedStr,
+    atoms: &[Box<dyn Atom>],
+    delimiters: &[Delimiter],
+    lints: &mut Vec<Lint>,
+) {
+    let known = |name: &SharedStr| {
+        name.as_str() == ROOT_MODE || language.modes().iter().any(|m| m.name == *name)
+    };
+
+    for delimiter in delimiters {
+        if let Some(name) = &delimiter.mode
+            && !known(name)
+        {
+            lints.push(Lint::UnknownMode {
+                location: format!("delimiter `{}` in mode `{}`", delimiter.kind, mode),
+                name: name.clone(),
+            });
+        }
+    }
+    for (index, atom) in atoms.iter().enumerate() {
+        if let Some(ModeTransition::Push(name)) = atom.mode_transition()
+            && !known(&name)
+        {
+            lints.push(Lint::UnknownMode {
+                location: format!("atom {} in mode `{}`", index, mode),
+                name,
+            });
+        }
+    }
+}
+
+/// How many bytes of `text` the atom matches on its own.
+fn match_len(atom: &dyn Atom, text: &str) -> Option<usize> {
+    atom.parse(Cursor::new(text)).map(|(_, next)| next.offset)
+}
+
+/// Whether the lexer picks `a` over `b` when both match, mirroring its order:
+/// opaque atoms first, then the language's strategy.
+fn beats(
+    (a_index, a, a_len): (usize, &dyn Atom, usize),
+    (b_index, b, b_len): (usize, &dyn Atom, usize),
+    strategy: LexStrategy,
+) -> bool {
+    if a.is_opaque() != b.is_opaque() {
+        return a.is_opaque();
+    }
+    match strategy {
+        LexStrategy::FirstMatch => a_index < b_index,
+        LexStrategy::LongestMatch => {
+            let (a_key, b_key) = ((a_len, a.priority()), (b_len, b.priority()));
+            a_key > b_key || (a_key == b_key && a_index < b_index)
+        }
+    }
+}
+
+/// What the analysis knows about a shape.
+#[derive(Debug, Clone, Copy)]
+struct Facts {
+    /// It can succeed without consuming a token.
+    nullable: bool,
+    /// It never fails.
+    infallible: bool,
+}
+
+const CONSUMES: Facts = Facts {
+    nullable: false,
+    infallible: false,
+};
+
+struct Analysis<'l> {
+    location: &'l str,
+    lints: Vec<Lint>,
+    /// Rules being visited.
+    active: Vec<&'static str>,
+    /// Rules entered since the last token was consumed.
+    head: Vec<&'static str>,
+}
+
+impl Analysis<'_> {
+    fn sequence(&mut self, parts: &[&dyn Shape]) -> Facts {
+        let mut facts = Facts {
+            nullable: true,
+            infallible: true,
+        };
+        for part in parts {
+            let part = if facts.nullable {
+                self.visit(*part)
+            } else {
+                self.after_token(*part)
+            };
+            facts.nullable &= part.nullable;
+            facts.infallible &= part.infallible;
+        }
+        facts
+    }
+
+    fn visit(&mut self, shape: &dyn Shape) -> Facts {
+        match shape.structure() {
+            Structure::Term(_) | Structure::Expr(_) | Structure::Opaque => CONSUMES,
+            Structure::Empty => Facts {
+                nullable: true,
+                infallible: true,
+            },
+            Structure::End => Facts {
+                nullable: true,
+                infallible: false,
+            },
+            Structure::Seq(parts) => self.sequence(&parts),
+            Structure::Adjacent(a, b) => self.sequence(&[a, b]),
+            Structure::Choice(parts) => {
+                // `choice(a, choice(b, c))` is one list of alternatives.
+                let mut alternatives = Vec::new();
+                let mut pending = parts;
+                pending.reverse();
+                while let Some(part) = pending.pop() {
+                    match part.structure() {
+                        Structure::Choice(nested) => pending.extend(nested.into_iter().rev()),
+                        _ => alternatives.push(part),
+                    }
+                }
+
+                let mut facts = CONSUMES;
+                let mut terms = Vec::new();
+                let mut reported = false;
+                for alternative in alternatives {
+                    let term = match alternative.structure() {
+                        Structure::Term(description) => Some(description),
+                        _ => None,
+                    };
+                    let duplicate = term.as_ref().is_some_and(|term| terms.contains(term));
+                    if (facts.infallible && !reported) || duplicate {
+                        reported |= facts.infallible;
+                        self.report(Lint::UnreachableChoice {
+                            location: self.location.to_string(),
+                            alternative: summary(alternative),
+                        });
+                    }
+                    terms.extend(term);
+
+                    let alternative = self.visit(alternative);
+                    facts.nullable |= alternative.nullable;
+                    facts.infallible |= alternative.infallible;
+                }
+                facts
+            }
+            Structure::Rep(a) => {
+                self.visit(a);
+                Facts {
+                    nullable: true,
+                    infallible: true,
+                }
+            }
+            Structure::Enter(_, inner) => {
+                self.after_token(inner);
+                CONSUMES
+            }
+            Structure::Recover(a) => {
+                self.visit(a);
+                Facts {
+                    nullable: true,
+                    infallible: true,
+                }
+            }
+            Structure::Node(_, a) | Structure::Field(_, a) => self.visit(a),
+            Structure::Rule(name, body) => {
+                if self.head.contains(&name) {
+                    self.report(Lint::LeftRecursion {
+                        location: self.location.to_string(),
+                        rule: name.to_string(),
+                    });
+                    return CONSUMES;
+                }
+                if self.active.contains(&name) {
+                    return CONSUMES;
+                }
+                self.active.push(name);
+                self.head.push(name);
+                let facts = self.visit(body.as_ref());
+                self.head.pop();
+                self.active.pop();
+                facts
+            }
+        }
+    }
+
+    /// Visits `shape` knowing a token has been consumed before it.
+    fn after_token(&mut self, shape: &dyn Shape) -> Facts {
+        let head = std::mem::take(&mut self.head);
+        let facts = self.visit(shape);
+        self.head = head;
+        facts
+    }
+
+    fn report(&mut self, lint: Lint) {
+        if !self.lints.contains(&lint) {
+            self.lints.push(lint);
+        }
+    }
+}
+
+/// A short description of a shape for messages.
+fn summary(shape: &dyn Shape) -> String {
+    match shape.structure() {
+        Structure::Term(description) => format!("term({})", description),
+        Structure::Seq(..) => "seq(..)".into(),
+        Structure::Choice(..) => "choice(..)".into(),
+        Structure::Rep(_) => "rep(..)".into(),
+        Structure::Enter(delimiter, _) => format!("enter({}, ..)", delimiter.kind),
+        Structure::Adjacent(..) => "adjacent(..)".into(),
+        Structure::Empty => "empty()".into(),
+        Structure::End => "end()".into(),
+        Structure::Expr(precedence) => format!("expr({})", precedence.0),
+        Structure::Recover(_) => "recover(..)".into(),
+        Structure::Node(kind, _) => format!("node({}, ..)", kind),
+        Structure::Field(name, _) => format!("field({}, ..)", name),
+        Structure::Rule(name, _) => format!("`{}`", name),
+        Structure::Opaque => "a custom shape".into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atom::AtomKind;
+    use crate::atoms::{KeywordAtom, LiteralAtom, RegexAtom};
+    use crate::define_language;
+    use crate::language::LexerMode;
+    use crate::shape::{
+        MatchContext, MatchResult, Precedence, choice, empty, enter, expr, opt, rep, separated,
+        seq, term, tuple,
+    };
+    use crate::token::TokenStream;
+
+    #[test]
+    fn test_shorter_literal_first_shadows_longer() {
+        define_language! {
+            struct Equals;
+            atoms = [
+                atom Whitespace = regex r"\s+",
+                atom Operator = "=",
+                atom Operator = "==",
+            ];
+            delimiters = [];
+        }
+
+        assert_eq!(
+            lint(&Equals::new()),
+            [Lint::ShadowedAtom {
+                mode: ROOT_MODE.into(),
+                atom: 2,
+                text: "==".into(),
+                shadowed_by: 1,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_longest_match_and_priority_resolve_shadowing() {
+        define_language! {
+            struct Longest;
+            atoms = [
+                atom Operator = "=",
+                atom Operator = "==",
+                atom Identifier = regex r"[a-z]+",
+                keyword "let",
+            ];
+            delimiters = [];
+            lex_strategy = LexStrategy::LongestMatch;
+        }
+        assert_lint_clean(&Longest::new());
+
+        // Under first match, the identifier atom takes every keyword.
+        define_language! {
+            struct First;
+            atoms = [
+                atom Identifier = regex r"[a-z]+",
+                keywords ["let", "fn"],
+            ];
+            delimiters = [];
+        }
+        let shadowed: Vec<_> = lint(&First::new())
+            .into_iter()
+            .map(|lint| match lint {
+                Lint::ShadowedAtom { text, .. } => text,
+                other => panic!("unexpected lint {:?}", other),
+            })
+            .collect();
+        assert_eq!(shadowed, ["let", "fn"]);
+    }
+
+    define_language! {
+        struct Modes;
+        atoms = [];
+        delimiters = [];
+    }
+
+    #[test]
+    fn test_opaque_atoms_and_modes() {
+        let lang = Modes::new()
+            .with_atom(Box::new(LiteralAtom::new(AtomKind::Operator, "//")))
+            .with_atom(Box::new(
+                RegexAtom::new(AtomKind::Comment, r"//.*").opaque(),
+            ))
+            .with_mode(
+                LexerMode::new("text")
+                    .with_atom(KeywordAtom::new(&["end"]).no_boundary())
+                    .with_atom(KeywordAtom::new(&["end", "endif"])),
+            );
+
+        assert_eq!(
+            lint(&lang),
+            [
+                Lint::ShadowedAtom {
+                    mode: ROOT_MODE.into(),
+                    atom: 0,
+                    text: "//".into(),
+                    shadowed_by: 1,
+                },
+                Lint::ShadowedAtom {
+                    mode: "text".into(),
+                    atom: 1,
+                    text: "end".into(),
+                    shadowed_by: 0,
+                },
+                Lint::ShadowedAtom {
+                    mode: "text".into(),
+                    atom: 1,
+                    text: "endif".into(),
+                    shadowed_by: 0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unknown_modes() {
+        let lang = Modes::new()
+            .with_delimiter(Delimiter::new("string", "\"", "\"").with_mode("strng"))
+            .with_delimiter(Delimiter::new("paren", "(", ")").with_mode(ROOT_MODE))
+            .with_mode(
+                LexerMode::new("string")
+                    .with_atom(
+                        LiteralAtom::new(AtomKind::Operator, "${")
+                            .with_transition(ModeTransition::Push("root".into())),
+                    )
+                    .with_atom(
+                        LiteralAtom::new(AtomKind::Operator, "{{")
+                            .with_transition(ModeTransition::Push("templat".into())),
+                    ),
+            );
+
+        assert_eq!(
+            lint(&lang),
+            [
+                Lint::UnknownMode {
+                    location: "delimiter `string` in mode `root`".into(),
+                    name: "strng".into(),
+                },
+                Lint::UnknownMode {
+                    location: "atom 1 in mode `string`".into(),
+                    name: "templat".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unreachable_choices() {
+        let shape = choice(opt(term("a")), term("b"));
+        assert_eq!(
+            lint_shape(&shape, "test"),
+            [Lint::UnreachableChoice {
+                location: "test".into(),
+                alternative: "term('b')".into(),
+            }]
+        );
+
+        let shape = seq(
+            term("x"),
+            choice(term("a"), choice(term("a"), rep(term("b")))),
+        );
+        assert_eq!(
+            lint_shape(&shape, "test"),
+            [Lint::UnreachableChoice {
+                location: "test".into(),
+                alternative: "term('a')".into(),
+            }]
+        );
+
+        let shape = choice(seq(rep(term("a")), term(";")), empty());
+        assert!(lint_shape(&shape, "test").is_empty());
+
+        let shape = tuple::choice((term("a"), choice(term("b"), term("a")), empty(), term("c")));
+        assert_eq!(
+            lint_shape(&shape, "test"),
+            [
+                Lint::UnreachableChoice {
+                    location: "test".into(),
+                    alternative: "term('a')".into(),
+                },
+                Lint::UnreachableChoice {
+                    location: "test".into(),
+                    alternative: "term('c')".into(),
+                },
+            ]
+        );
+    }
+
+    /// `list := list "," item | item`, written the way that overflows.
+    #[derive(Debug)]
+    struct LeftList;
+
+    impl LeftList {
+        fn body(&self) -> impl Shape + use<> {
+            choice(seq(LeftList, seq(term(","), term("item"))), term("item"))
+        }
+    }
+
+    impl Shape for LeftList {
+        fn match_shape<'a>(
+            &self,
+            stream: TokenStream<'a>,
+            context: &mut dyn MatchContext,
+        ) -> MatchResult<'a> {
+            self.body().match_shape(stream, context)
+        }
+
+        fn structure(&self) -> Structure<'_> {
+            Structure::Rule("LeftList", Box::new(self.body()))
+        }
+    }
+
+    /// `value := "[" value,* "]" | number`, which consumes before recursing.
+    #[derive(Debug, Clone)]
+    struct Value;
+
+    impl Value {
+        fn body(&self) -> impl Shape + use<> {
+            choice(
+                enter(
+                    crate::language::Delimiter::new("bracket", "[", "]"),
+                    opt(separated(Value, term(","))),
+                ),
+                term(AtomKind::Number),
+            )
+        }
+    }
+
+    impl Shape for Value {
+        fn match_shape<'a>(
+            &self,
+            stream: TokenStream<'a>,
+            context: &mut dyn MatchContext,
+        ) -> MatchResult<'a> {
+            self.body().match_shape(stream, context)
+        }
+
+        fn structure(&self) -> Structure<'_> {
+            Structure::Rule("Value", Box::new(self.body()))
+        }
+    }
+
+    #[test]
+    fn test_left_recursion() {
+        assert_eq!(
+            lint_shape(&seq(opt(term("-")), LeftList), "test"),
+            [Lint::LeftRecursion {
+                location: "test".into(),
+                rule: "LeftList".into(),
+            }]
+        );
+        assert!(lint_shape(&seq(Value, expr(Precedence(0))), "test").is_empty());
+    }
+
+    #[test]
+    fn test_lint_checks_root_and_signatures() {
+        define_language! {
+            struct Rooted;
+            atoms = [
+                atom Operator = ",",
+            ];
+            delimiters = [];
+            root = rep(LeftList);
+        }
+
+        assert_eq!(
+            lint(&Rooted::new()),
+            [Lint::LeftRecursion {
+                location: "the root shape".into(),
+                rule: "LeftList".into(),
+            }]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "grammar has 1 lint(s)")]
+    fn test_assert_lint_clean_panics() {
+        let lang = Modes::new()
+            .with_atom(Box::new(LiteralAtom::new(AtomKind::Operator, "-")))
+            .with_atom(Box::new(LiteralAtom::new(AtomKind::Operator, "->")));
+        assert_lint_clean(&lang);
+    }
+}
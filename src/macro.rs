@@ -1,8 +1,14 @@
 use crate::shape::{Associativity, Precedence, Shape};
-use crate::token::TokenTree;
+use crate::token::{Token, TokenTree};
 use std::fmt::Debug;
 
-pub struct MacroContext; // Placeholder
+/// Information about the invocation being expanded.
+#[derive(Debug, Clone, Default)]
+pub struct MacroContext {
+    /// The token that invoked the macro: its name for a prefix macro, or the
+    /// operator for an infix one.
+    pub name: Option<Token>,
+}
 
 #[derive(Debug, Clone)]
 pub enum ExpansionResult {
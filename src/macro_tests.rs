@@ -1,3 +1,4 @@
+use crate::shape::{Precedence, expr, rep, seq, term};
 use crate::{atom::AtomKind, define_language, language::Language};
 
 define_language! {
@@ -34,3 +35,206 @@ fn test_macro_expansion() {
     assert_eq!(delimiters[0].open, "(");
     assert_eq!(delimiters[0].close, ")");
 }
+
+define_language! {
+    struct TemplateLang;
+    atoms = [
+        atom Identifier = regex r"[a-zA-Z_]\w*",
+    ];
+    delimiters = [
+        delimiter "string" = "\"", "\"" mode "string",
+    ];
+    modes = [
+        crate::language::LexerMode::new("string")
+            .with_atom(crate::atoms::RegexAtom::new(AtomKind::String, r#"[^"]+"#)),
+    ];
+}
+
+#[test]
+fn test_macro_modes() {
+    let lang = TemplateLang::new();
+
+    assert_eq!(lang.delimiters()[0].mode.as_deref(), Some("string"));
+    assert_eq!(lang.modes().len(), 1);
+    assert_eq!(lang.modes()[0].name, "string");
+    assert!(matches!(lang.atom(1).unwrap().kind(), AtomKind::String));
+}
+
+define_language! {
+    struct BraceLang;
+    atoms = [
+        atom Identifier = regex r"[a-zA-Z_]\w*",
+    ];
+    delimiters = [
+        delimiter "brace" = "{", "}" recover_by_indentation,
+        delimiter "paren" = "(", ")",
+    ];
+}
+
+#[test]
+fn test_macro_indentation_recovery() {
+    let lang = BraceLang::new();
+    assert!(lang.delimiters()[0].recover_by_indentation);
+    assert!(!lang.delimiters()[1].recover_by_indentation);
+}
+
+define_language! {
+    struct IndentLang;
+    atoms = [
+        atom Whitespace = regex r"\s+",
+        atom Identifier = regex r"[a-zA-Z_]\w*",
+    ];
+    delimiters = [];
+    offside_rule = true;
+}
+
+#[test]
+fn test_macro_offside_rule() {
+    let lang = IndentLang::new();
+    assert!(lang.offside_rule());
+
+    let trees = crate::lexer::lex("a\n  b", &lang);
+    assert!(matches!(&trees[2], crate::token::TokenTree::Delimited(d, ..) if d.kind == "indent"));
+}
+
+define_language! {
+    struct MunchLang;
+    atoms = [
+        atom Operator = "=",
+        atom Operator = "==",
+    ];
+    delimiters = [];
+    lex_strategy = crate::language::LexStrategy::LongestMatch;
+}
+
+#[test]
+fn test_macro_lex_strategy() {
+    let lang = MunchLang::new();
+    assert_eq!(
+        lang.lex_strategy(),
+        crate::language::LexStrategy::LongestMatch
+    );
+
+    let trees = crate::lexer::lex("==", &lang);
+    assert_eq!(trees.len(), 1);
+}
+
+define_language! {
+    struct SqlLang;
+    atoms = [
+        atom Whitespace = regex r"\s+",
+        keywords [ "select", "from", "count" => Function ] ignore_ascii_case,
+        keyword "in" no_boundary,
+        atom Identifier = regex r"[a-zA-Z_]\w*",
+    ];
+    delimiters = [];
+}
+
+#[test]
+fn test_macro_keyword_options() {
+    use crate::highlighter::{HighlightStyle, Highlighter};
+    use crate::token::{Cursor, Token};
+
+    #[derive(Debug, Default)]
+    struct Styles(Vec<String>);
+    impl Highlighter for Styles {
+        fn highlight(&mut self, _token: &Token, style: HighlightStyle) {
+            self.0.push(format!("{style:?}"));
+        }
+    }
+
+    let lang = SqlLang::new();
+    let (select, _) = lang.atoms()[1].parse(Cursor::new("SELECT")).unwrap();
+    let (count, _) = lang.atoms()[1].parse(Cursor::new("Count")).unwrap();
+    assert!(lang.atoms()[1].parse(Cursor::new("fromage")).is_none());
+    let (in_, _) = lang.atoms()[2].parse(Cursor::new("index")).unwrap();
+    assert_eq!(in_.text, "in");
+
+    let mut styles = Styles::default();
+    lang.atoms()[1].highlight(&select, &mut styles);
+    lang.atoms()[1].highlight(&count, &mut styles);
+    assert_eq!(styles.0, ["Keyword", "Function"]);
+}
+
+define_language! {
+    struct PluginLang;
+    atoms = [];
+    delimiters = [];
+}
+
+#[test]
+fn test_language_built_at_runtime() {
+    use crate::atoms::{KeywordAtom, RegexAtom};
+    use crate::language::{Delimiter, LexerMode};
+    use crate::lexer::{LexError, lex_with_diagnostics};
+    use crate::token::TokenTree;
+
+    // As if read from a plugin's configuration file.
+    let config = [("block", "begin", "end"), ("quote", "<<", ">>")];
+    let keywords: Vec<String> = vec!["let".into()];
+
+    let keyword_refs: Vec<&str> = keywords.iter().map(String::as_str).collect();
+    let mut lang = PluginLang::new()
+        .with_atom(Box::new(RegexAtom::new(AtomKind::Whitespace, r"\s+")))
+        .with_atom(Box::new(KeywordAtom::new(&keyword_refs)))
+        .with_atom(Box::new(RegexAtom::new(AtomKind::Identifier, r"[a-z]+")));
+    for (kind, open, close) in config {
+        let mut delimiter = Delimiter::new(kind.to_string(), open.to_string(), close.to_string());
+        if kind == "quote" {
+            delimiter = delimiter.with_mode(String::from("text"));
+        }
+        lang = lang.with_delimiter(delimiter);
+    }
+    lang = lang.with_mode(
+        LexerMode::new(String::from("text")).with_atom(RegexAtom::new(AtomKind::String, r"[^>]+")),
+    );
+
+    let (trees, errors) = lex_with_diagnostics("begin let x <<a b>> end begin", &lang);
+    let TokenTree::Delimited(block, children, _, true) = &trees[0] else {
+        panic!("Expected closed group, got {:?}", trees[0]);
+    };
+    assert_eq!(block.kind, "block");
+    let TokenTree::Delimited(quote, text, _, true) = &children[5] else {
+        panic!("Expected quote, got {:?}", children[5]);
+    };
+    assert_eq!(quote.mode.as_deref(), Some("text"));
+    assert!(matches!(&text[..], [TokenTree::Token(t)] if t.kind == AtomKind::String));
+
+    assert!(matches!(
+        &errors[..],
+        [LexError::UnclosedDelimiter { delimiter, .. }] if delimiter == "begin"
+    ));
+}
+
+define_language! {
+    struct StatementLang;
+    atoms = [
+        atom Whitespace = regex r"\s+",
+        atom Identifier = regex r"[a-z]+",
+        atom Operator = ";",
+    ];
+    delimiters = [];
+    root = rep(seq(expr(Precedence(0)), term(";")));
+}
+
+#[test]
+fn test_macro_root_shape() {
+    use crate::lexer::lex;
+    use crate::parser::Parser;
+    use crate::token::{TokenStream, TokenTree};
+
+    assert!(StatementLang::new().root().is_some());
+
+    let lang = StatementLang::new();
+    let trees = lex("a; b;", &lang);
+    let program = Parser::new(TokenStream::new(&trees), &lang)
+        .parse_program()
+        .unwrap();
+    assert!(matches!(program, TokenTree::Group(ref statements) if statements.len() == 2));
+
+    let trees = lex("a; b", &lang);
+    let error = Parser::new(TokenStream::new(&trees), &lang)
+        .parse_program()
+        .unwrap_err();
+    assert_eq!(error.span, (3, 1).into());
+}
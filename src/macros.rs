@@ -4,6 +4,8 @@ macro_rules! define_atom {
         $(#[$meta:meta])*
         struct $name:ident;
         kind = $kind:expr;
+        $(opaque = $opaque:expr;)?
+        $(priority = $priority:expr;)?
         $(parse($input:ident) $parse_body:block)?
         $(highlight($token:ident, $highlighter:ident) $highlight_body:block)?
     ) => {
@@ -30,6 +32,18 @@ macro_rules! define_atom {
                     $highlight_body
                 )?
             }
+
+            $(
+                fn is_opaque(&self) -> bool {
+                    $opaque
+                }
+            )?
+
+            $(
+                fn priority(&self) -> i32 {
+                    $priority
+                }
+            )?
         }
     };
 }
@@ -53,6 +67,15 @@ macro_rules! define_language {
         $crate::define_language!(@atom_list_push $v, $($($rest)*)?)
     };
 
+    // Case: atom Kind = opaque regex "pattern"
+    (@atom_list_push $v:ident, atom $kind:ident = opaque regex $regex:literal $(, $($rest:tt)*)?) => {
+        $v.push(Box::new($crate::atoms::RegexAtom::new(
+            $crate::define_language!(@atom_kind $kind),
+            $regex
+        ).opaque()));
+        $crate::define_language!(@atom_list_push $v, $($($rest)*)?)
+    };
+
     // Case: atom Kind = "literal"
     (@atom_list_push $v:ident, atom $kind:ident = $literal:literal $(, $($rest:tt)*)?) => {
         $v.push(Box::new($crate::atoms::LiteralAtom::new(
@@ -62,15 +85,19 @@ macro_rules! define_language {
         $crate::define_language!(@atom_list_push $v, $($($rest)*)?)
     };
 
-    // Case: keywords [ "a", "b" ]
-    (@atom_list_push $v:ident, keywords [ $($kw:literal),* $(,)? ] $(, $($rest:tt)*)?) => {
-        $v.push(Box::new($crate::atoms::KeywordAtom::new(&[ $($kw),* ])));
+    // Case: keywords [ "a", "b" => Style ] option...
+    (@atom_list_push $v:ident, keywords [ $($kw:literal $(=> $style:ident)?),* $(,)? ] $($option:ident)* $(, $($rest:tt)*)?) => {
+        $v.push(Box::new(
+            $crate::atoms::KeywordAtom::new(&[ $($kw),* ])
+                $($(.with_style($kw, $crate::highlighter::HighlightStyle::$style))?)*
+                $(.$option())*
+        ));
         $crate::define_language!(@atom_list_push $v, $($($rest)*)?)
     };
 
-    // Case: keyword "kw"
-    (@atom_list_push $v:ident, keyword $kw:literal $(, $($rest:tt)*)?) => {
-        $v.push(Box::new($crate::atoms::KeywordAtom::new(&[$kw])));
+    // Case: keyword "kw" option...
+    (@atom_list_push $v:ident, keyword $kw:literal $($option:ident)* $(, $($rest:tt)*)?) => {
+        $v.push(Box::new($crate::atoms::KeywordAtom::new(&[$kw]) $(.$option())*));
         $crate::define_language!(@atom_list_push $v, $($($rest)*)?)
     };
 
@@ -84,13 +111,21 @@ macro_rules! define_language {
     (@delimiter_list_push $v:ident) => {};
     (@delimiter_list_push $v:ident,) => {};
 
+    // Case: delimiter "kind" = "open", "close" mode "mode"
+    (@delimiter_list_push $v:ident, delimiter $kind:literal = $open:literal, $close:literal mode $mode:literal $(, $($rest:tt)*)?) => {
+        $v.push($crate::language::Delimiter::new($kind, $open, $close).with_mode($mode));
+        $crate::define_language!(@delimiter_list_push $v, $($($rest)*)?)
+    };
+
+    // Case: delimiter "kind" = "open", "close" recover_by_indentation
+    (@delimiter_list_push $v:ident, delimiter $kind:literal = $open:literal, $close:literal recover_by_indentation $(, $($rest:tt)*)?) => {
+        $v.push($crate::language::Delimiter::new($kind, $open, $close).with_indentation_recovery());
+        $crate::define_language!(@delimiter_list_push $v, $($($rest)*)?)
+    };
+
     // Case: delimiter "kind" = "open", "close"
     (@delimiter_list_push $v:ident, delimiter $kind:literal = $open:literal, $close:literal $(, $($rest:tt)*)?) => {
-        $v.push($crate::language::Delimiter {
-            kind: $kind,
-            open: $open,
-            close: $close,
-        });
+        $v.push($crate::language::Delimiter::new($kind, $open, $close));
         $crate::define_language!(@delimiter_list_push $v, $($($rest)*)?)
     };
 
@@ -104,17 +139,20 @@ macro_rules! define_language {
     // (Removed old helpers as they are replaced by @parse_options)
 
     // --- Option Parsing (TT Muncher) ---
+    //
+    // `settings` collects options that simply overwrite a field of the generated
+    // struct, as `field = { value }` pairs.
 
     // Case: macros = [ ... ];
     (@parse_options
         meta = $meta:tt, name = $name:ident, atoms = $atoms:tt, delimiters = $delimiters:tt,
-        macros = $_old_macros:tt, binding_pass = $bp:tt, reference_pass = $rp:tt,
+        macros = $_old_macros:tt, binding_pass = $bp:tt, reference_pass = $rp:tt, settings = $settings:tt,
         input = [ macros = [ $($m:expr),* $(,)? ]; $($rest:tt)* ]
     ) => {
         $crate::define_language! { @parse_options
             meta = $meta, name = $name, atoms = $atoms, delimiters = $delimiters,
-            macros = [ $($(Box::new($m)),*)? ],
-            binding_pass = $bp, reference_pass = $rp,
+            macros = [ $(Box::new($m)),* ],
+            binding_pass = $bp, reference_pass = $rp, settings = $settings,
             input = [ $($rest)* ]
         }
     };
@@ -122,14 +160,14 @@ macro_rules! define_language {
     // Case: binding_pass = simple("kw");
     (@parse_options
         meta = $meta:tt, name = $name:ident, atoms = $atoms:tt, delimiters = $delimiters:tt,
-        macros = $macros:tt, binding_pass = $_old:tt, reference_pass = $rp:tt,
+        macros = $macros:tt, binding_pass = $_old:tt, reference_pass = $rp:tt, settings = $settings:tt,
         input = [ binding_pass = simple($kw:literal); $($rest:tt)* ]
     ) => {
         $crate::define_language! { @parse_options
             meta = $meta, name = $name, atoms = $atoms, delimiters = $delimiters,
             macros = $macros,
             binding_pass = { Box::new($crate::scoping::SimpleBindingPass::new($kw)) },
-            reference_pass = $rp,
+            reference_pass = $rp, settings = $settings,
             input = [ $($rest)* ]
         }
     };
@@ -137,14 +175,14 @@ macro_rules! define_language {
     // Case: binding_pass = expr;
     (@parse_options
         meta = $meta:tt, name = $name:ident, atoms = $atoms:tt, delimiters = $delimiters:tt,
-        macros = $macros:tt, binding_pass = $_old:tt, reference_pass = $rp:tt,
+        macros = $macros:tt, binding_pass = $_old:tt, reference_pass = $rp:tt, settings = $settings:tt,
         input = [ binding_pass = $e:expr; $($rest:tt)* ]
     ) => {
         $crate::define_language! { @parse_options
             meta = $meta, name = $name, atoms = $atoms, delimiters = $delimiters,
             macros = $macros,
             binding_pass = { Box::new($e) },
-            reference_pass = $rp,
+            reference_pass = $rp, settings = $settings,
             input = [ $($rest)* ]
         }
     };
@@ -152,7 +190,7 @@ macro_rules! define_language {
     // Case: reference_pass = simple;
     (@parse_options
         meta = $meta:tt, name = $name:ident, atoms = $atoms:tt, delimiters = $delimiters:tt,
-        macros = $macros:tt, binding_pass = $bp:tt, reference_pass = $_old:tt,
+        macros = $macros:tt, binding_pass = $bp:tt, reference_pass = $_old:tt, settings = $settings:tt,
         input = [ reference_pass = simple; $($rest:tt)* ]
     ) => {
         $crate::define_language! { @parse_options
@@ -160,6 +198,7 @@ macro_rules! define_language {
             macros = $macros,
             binding_pass = $bp,
             reference_pass = { Box::new($crate::scoping::SimpleReferencePass) },
+            settings = $settings,
             input = [ $($rest)* ]
         }
     };
@@ -167,7 +206,7 @@ macro_rules! define_language {
     // Case: reference_pass = expr;
     (@parse_options
         meta = $meta:tt, name = $name:ident, atoms = $atoms:tt, delimiters = $delimiters:tt,
-        macros = $macros:tt, binding_pass = $bp:tt, reference_pass = $_old:tt,
+        macros = $macros:tt, binding_pass = $bp:tt, reference_pass = $_old:tt, settings = $settings:tt,
         input = [ reference_pass = $e:expr; $($rest:tt)* ]
     ) => {
         $crate::define_language! { @parse_options
@@ -175,6 +214,77 @@ macro_rules! define_language {
             macros = $macros,
             binding_pass = $bp,
             reference_pass = { Box::new($e) },
+            settings = $settings,
+            input = [ $($rest)* ]
+        }
+    };
+
+    // Case: modes = [ ... ];
+    (@parse_options
+        meta = $meta:tt, name = $name:ident, atoms = $atoms:tt, delimiters = $delimiters:tt,
+        macros = $macros:tt, binding_pass = $bp:tt, reference_pass = $rp:tt, settings = [ $($settings:tt)* ],
+        input = [ modes = [ $($mode:expr),* $(,)? ]; $($rest:tt)* ]
+    ) => {
+        $crate::define_language! { @parse_options
+            meta = $meta, name = $name, atoms = $atoms, delimiters = $delimiters,
+            macros = $macros, binding_pass = $bp, reference_pass = $rp,
+            settings = [ $($settings)* modes = { vec![ $($mode),* ] } ],
+            input = [ $($rest)* ]
+        }
+    };
+
+    // Case: offside_rule = expr;
+    (@parse_options
+        meta = $meta:tt, name = $name:ident, atoms = $atoms:tt, delimiters = $delimiters:tt,
+        macros = $macros:tt, binding_pass = $bp:tt, reference_pass = $rp:tt, settings = [ $($settings:tt)* ],
+        input = [ offside_rule = $e:expr; $($rest:tt)* ]
+    ) => {
+        $crate::define_language! { @parse_options
+            meta = $meta, name = $name, atoms = $atoms, delimiters = $delimiters,
+            macros = $macros, binding_pass = $bp, reference_pass = $rp,
+            settings = [ $($settings)* offside_rule = { $e } ],
+            input = [ $($rest)* ]
+        }
+    };
+
+    // Case: attach_trivia = expr;
+    (@parse_options
+        meta = $meta:tt, name = $name:ident, atoms = $atoms:tt, delimiters = $delimiters:tt,
+        macros = $macros:tt, binding_pass = $bp:tt, reference_pass = $rp:tt, settings = [ $($settings:tt)* ],
+        input = [ attach_trivia = $e:expr; $($rest:tt)* ]
+    ) => {
+        $crate::define_language! { @parse_options
+            meta = $meta, name = $name, atoms = $atoms, delimiters = $delimiters,
+            macros = $macros, binding_pass = $bp, reference_pass = $rp,
+            settings = [ $($settings)* attach_trivia = { $e } ],
+            input = [ $($rest)* ]
+        }
+    };
+
+    // Case: lex_strategy = expr;
+    (@parse_options
+        meta = $meta:tt, name = $name:ident, atoms = $atoms:tt, delimiters = $delimiters:tt,
+        macros = $macros:tt, binding_pass = $bp:tt, reference_pass = $rp:tt, settings = [ $($settings:tt)* ],
+        input = [ lex_strategy = $e:expr; $($rest:tt)* ]
+    ) => {
+        $crate::define_language! { @parse_options
+            meta = $meta, name = $name, atoms = $atoms, delimiters = $delimiters,
+            macros = $macros, binding_pass = $bp, reference_pass = $rp,
+            settings = [ $($settings)* lex_strategy = { $e } ],
+            input = [ $($rest)* ]
+        }
+    };
+
+    // Case: root = expr;
+    (@parse_options
+        meta = $meta:tt, name = $name:ident, atoms = $atoms:tt, delimiters = $delimiters:tt,
+        macros = $macros:tt, binding_pass = $bp:tt, reference_pass = $rp:tt, settings = [ $($settings:tt)* ],
+        input = [ root = $e:expr; $($rest:tt)* ]
+    ) => {
+        $crate::define_language! { @parse_options
+            meta = $meta, name = $name, atoms = $atoms, delimiters = $delimiters,
+            macros = $macros, binding_pass = $bp, reference_pass = $rp,
+            settings = [ $($settings)* root = { Some(Box::new($e)) } ],
             input = [ $($rest)* ]
         }
     };
@@ -188,6 +298,7 @@ macro_rules! define_language {
         macros = [ $($macros:expr),* ],
         binding_pass = { $binding_pass_impl:expr },
         reference_pass = { $reference_pass_impl:expr },
+        settings = [ $($field:ident = { $value:expr })* ],
         input = []
     ) => {
         $(#[$meta])*
@@ -198,25 +309,59 @@ macro_rules! define_language {
             macros: Vec<Box<dyn $crate::r#macro::Macro>>,
             binding_pass: Box<dyn $crate::scoping::BindingPass>,
             reference_pass: Box<dyn $crate::scoping::ReferencePass>,
+            modes: Vec<$crate::language::LexerMode>,
+            offside_rule: bool,
+            attach_trivia: bool,
+            lex_strategy: $crate::language::LexStrategy,
+            root: Option<Box<dyn $crate::shape::Shape>>,
         }
 
         impl $name {
+            #[allow(clippy::vec_init_then_push)]
             pub fn new() -> Self {
                 let mut atoms: Vec<Box<dyn $crate::atom::Atom>> = Vec::new();
                 $crate::define_language!(@atom_list_push atoms, $($atoms)*);
 
                 let mut delimiters: Vec<$crate::language::Delimiter> = Vec::new();
                 $crate::define_language!(@delimiter_list_push delimiters, $($delimiters)*);
-                // Suppress unused_mut warning if delimiters is empty
-                let _ = &mut delimiters;
+                // Suppress unused_mut warnings if either list is empty
+                let _ = (&mut atoms, &mut delimiters);
 
-                Self {
+                #[allow(unused_mut)]
+                let mut language = Self {
                     atoms,
                     delimiters,
                     macros: vec![ $($macros),* ],
                     binding_pass: $binding_pass_impl,
                     reference_pass: $reference_pass_impl,
-                }
+                    modes: Vec::new(),
+                    offside_rule: false,
+                    attach_trivia: false,
+                    lex_strategy: $crate::language::LexStrategy::FirstMatch,
+                    root: None,
+                };
+                $( language.$field = $value; )*
+                language
+            }
+
+            /// Adds an atom after the declared ones, e.g. from configuration loaded
+            /// at runtime.
+            #[allow(dead_code)]
+            pub fn with_atom(mut self, atom: Box<dyn $crate::atom::Atom>) -> Self {
+                self.atoms.push(atom);
+                self
+            }
+
+            #[allow(dead_code)]
+            pub fn with_delimiter(mut self, delimiter: $crate::language::Delimiter) -> Self {
+                self.delimiters.push(delimiter);
+                self
+            }
+
+            #[allow(dead_code)]
+            pub fn with_mode(mut self, mode: $crate::language::LexerMode) -> Self {
+                self.modes.push(mode);
+                self
             }
         }
 
@@ -236,6 +381,21 @@ macro_rules! define_language {
             fn reference_pass(&self) -> &dyn $crate::scoping::ReferencePass {
                 self.reference_pass.as_ref()
             }
+            fn modes(&self) -> &[$crate::language::LexerMode] {
+                &self.modes
+            }
+            fn offside_rule(&self) -> bool {
+                self.offside_rule
+            }
+            fn attach_trivia(&self) -> bool {
+                self.attach_trivia
+            }
+            fn lex_strategy(&self) -> $crate::language::LexStrategy {
+                self.lex_strategy
+            }
+            fn root(&self) -> Option<&dyn $crate::shape::Shape> {
+                self.root.as_deref()
+            }
         }
     };
 
@@ -255,6 +415,7 @@ macro_rules! define_language {
             macros = [],
             binding_pass = { Box::new($crate::scoping::NoOpBindingPass) },
             reference_pass = { Box::new($crate::scoping::NoOpReferencePass) },
+            settings = [],
             input = [ $($rest)* ]
         }
     };
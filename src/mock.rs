@@ -1,6 +1,6 @@
 use crate::atom::{Atom, AtomKind};
 use crate::highlighter::{HighlightStyle, Highlighter};
-use crate::language::{Delimiter, Language};
+use crate::language::{Delimiter, Language, LexStrategy, LexerMode};
 use crate::r#macro::Macro;
 use crate::scoping::{BindingPass, NoOpBindingPass, NoOpReferencePass, ReferencePass};
 use crate::token::{Cursor, SourceLocation, Token};
@@ -28,11 +28,12 @@ impl Atom for WhitespaceAtom {
             let token = Token {
                 kind: AtomKind::Whitespace,
                 text,
-                location: SourceLocation {
-                    span: (input.offset, len).into(),
-                },
+                location: SourceLocation::new(input.offset, len),
                 atom_index: None,
                 binding: None,
+                leading_trivia: Vec::new(),
+                trailing_trivia: Vec::new(),
+                value: None,
             };
             Some((token, input.advance(len)))
         } else {
@@ -76,11 +77,12 @@ impl Atom for IdentifierAtom {
             let token = Token {
                 kind: AtomKind::Identifier,
                 text,
-                location: SourceLocation {
-                    span: (input.offset, len).into(),
-                },
+                location: SourceLocation::new(input.offset, len),
                 atom_index: None,
                 binding: None,
+                leading_trivia: Vec::new(),
+                trailing_trivia: Vec::new(),
+                value: None,
             };
             Some((token, input.advance(len)))
         } else {
@@ -93,50 +95,9 @@ impl Atom for IdentifierAtom {
     }
 }
 
-#[derive(Debug)]
-pub struct KeywordAtom {
-    keywords: Vec<String>,
-}
-
-impl KeywordAtom {
-    pub fn new(keywords: &[&str]) -> Self {
-        Self {
-            keywords: keywords.iter().map(|s| s.to_string()).collect(),
-        }
-    }
-}
-
-impl Atom for KeywordAtom {
-    fn kind(&self) -> AtomKind {
-        AtomKind::Identifier
-    }
-
-    fn parse<'a>(&self, input: Cursor<'a>) -> Option<(Token, Cursor<'a>)> {
-        for kw in &self.keywords {
-            if input.rest.starts_with(kw) {
-                // Check boundary
-                let next_char = input.rest[kw.len()..].chars().next();
-                if next_char.map_or(true, |c| !c.is_alphanumeric() && c != '_') {
-                    let token = Token {
-                        kind: AtomKind::Identifier,
-                        text: kw.clone(),
-                        location: SourceLocation {
-                            span: (input.offset, kw.len()).into(),
-                        },
-                        atom_index: None,
-                        binding: None,
-                    };
-                    return Some((token, input.advance(kw.len())));
-                }
-            }
-        }
-        None
-    }
-
-    fn highlight(&self, token: &Token, highlighter: &mut dyn Highlighter) {
-        highlighter.highlight(token, HighlightStyle::Keyword);
-    }
-}
+// The mock language uses the real keyword atom, so tests exercise its boundary
+// and case options.
+pub use crate::atoms::KeywordAtom;
 
 #[derive(Debug)]
 pub struct SymbolAtom {
@@ -162,11 +123,12 @@ impl Atom for SymbolAtom {
                 let token = Token {
                     kind: AtomKind::Operator,
                     text: sym.clone(),
-                    location: SourceLocation {
-                        span: (input.offset, sym.len()).into(),
-                    },
+                    location: SourceLocation::new(input.offset, sym.len()),
                     atom_index: None,
                     binding: None,
+                    leading_trivia: Vec::new(),
+                    trailing_trivia: Vec::new(),
+                    value: None,
                 };
                 return Some((token, input.advance(sym.len())));
             }
@@ -186,6 +148,16 @@ pub struct MockLanguage {
     macros: Vec<Box<dyn Macro>>,
     binding_pass: Box<dyn BindingPass>,
     reference_pass: Box<dyn ReferencePass>,
+    modes: Vec<LexerMode>,
+    offside_rule: bool,
+    attach_trivia: bool,
+    lex_strategy: LexStrategy,
+}
+
+impl Default for MockLanguage {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MockLanguage {
@@ -196,14 +168,14 @@ impl MockLanguage {
                 Box::new(KeywordAtom::new(&["let"])),
                 Box::new(IdentifierAtom),
             ],
-            delimiters: vec![Delimiter {
-                kind: "paren",
-                open: "(",
-                close: ")",
-            }],
+            delimiters: vec![Delimiter::new("paren", "(", ")")],
             macros: vec![],
             binding_pass: Box::new(NoOpBindingPass),
             reference_pass: Box::new(NoOpReferencePass),
+            modes: vec![],
+            offside_rule: false,
+            attach_trivia: false,
+            lex_strategy: LexStrategy::FirstMatch,
         }
     }
 
@@ -221,6 +193,36 @@ impl MockLanguage {
         self.atoms.insert(1, Box::new(SymbolAtom::new(&[symbol]))); // Insert before identifier
         self
     }
+
+    pub fn with_atom(mut self, atom: Box<dyn Atom>) -> Self {
+        self.atoms.insert(1, atom); // Insert before identifier
+        self
+    }
+
+    pub fn with_delimiter(mut self, delimiter: Delimiter) -> Self {
+        self.delimiters.push(delimiter);
+        self
+    }
+
+    pub fn with_mode(mut self, mode: LexerMode) -> Self {
+        self.modes.push(mode);
+        self
+    }
+
+    pub fn with_offside_rule(mut self) -> Self {
+        self.offside_rule = true;
+        self
+    }
+
+    pub fn with_trivia_attachment(mut self) -> Self {
+        self.attach_trivia = true;
+        self
+    }
+
+    pub fn with_lex_strategy(mut self, strategy: LexStrategy) -> Self {
+        self.lex_strategy = strategy;
+        self
+    }
 }
 
 impl Language for MockLanguage {
@@ -243,4 +245,20 @@ impl Language for MockLanguage {
     fn reference_pass(&self) -> &dyn ReferencePass {
         self.reference_pass.as_ref()
     }
+
+    fn modes(&self) -> &[LexerMode] {
+        &self.modes
+    }
+
+    fn offside_rule(&self) -> bool {
+        self.offside_rule
+    }
+
+    fn attach_trivia(&self) -> bool {
+        self.attach_trivia
+    }
+
+    fn lex_strategy(&self) -> LexStrategy {
+        self.lex_strategy
+    }
 }
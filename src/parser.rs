@@ -1,7 +1,10 @@
 use crate::atom::AtomKind;
 use crate::language::Language;
+use crate::lexer::tree_span;
 use crate::r#macro::{ExpansionResult, MacroContext};
-use crate::shape::{Associativity, MatchContext, MatchResult, ParseError, Precedence};
+use crate::shape::{
+    Associativity, MatchContext, MatchResult, MemoTable, ParseError, Precedence, skip_trivia,
+};
 use crate::token::{TokenStream, TokenTree};
 
 /// The main parser struct.
@@ -11,12 +14,23 @@ pub struct Parser<'a, L: Language> {
     #[allow(dead_code)] // stream in struct might be used for initial entry point
     stream: TokenStream<'a>,
     language: &'a L,
+    /// Results of `memo` shapes within this parser's stream, built on first use.
+    memo: Option<MemoTable>,
 }
 
 impl<'a, L: Language> Parser<'a, L> {
     /// Creates a new parser for the given token stream and language.
     pub fn new(stream: TokenStream<'a>, language: &'a L) -> Self {
-        Self { stream, language }
+        Self {
+            stream,
+            language,
+            memo: None,
+        }
+    }
+
+    /// Drops the results cached by `memo` shapes.
+    pub fn clear_memo(&mut self) {
+        self.memo = None;
     }
 
     /// Parses the entire stream into a single `TokenTree`.
@@ -28,31 +42,37 @@ impl<'a, L: Language> Parser<'a, L> {
         Ok(tree)
     }
 
+    /// Parses the entire stream as a program, using the language's root shape.
+    ///
+    /// Falls back to a single expression when the language has no root (see
+    /// [`Language::root`]). Unlike [`parse`](Self::parse), input left over after
+    /// the program is an error rather than ignored.
+    pub fn parse_program(&mut self) -> Result<TokenTree, ParseError> {
+        let language = self.language;
+        let (tree, rest) = match language.root() {
+            Some(root) => root.match_shape(self.stream.clone(), self)?,
+            None => self.parse_expression(self.stream.clone(), Precedence(0))?,
+        };
+
+        if let Some(extra) = skip_trivia(rest, self).first() {
+            let span = tree_span(extra).unwrap_or_else(|| (0, 0).into());
+            return Err(ParseError::new(
+                span,
+                "Unexpected input after the program".into(),
+            ));
+        }
+        Ok(tree)
+    }
+
     /// Parses the "head" of an expression.
     /// This handles prefix macros and simple terms.
     fn parse_head<'s>(&mut self, stream: TokenStream<'s>) -> MatchResult<'s> {
-        let mut current_stream = stream;
-
-        // Skip whitespace
-        while let Some(tree) = current_stream.first() {
-            if let TokenTree::Token(token) = tree
-                && token.kind == AtomKind::Whitespace {
-                    current_stream = current_stream.advance(1);
-                    continue;
-                }
-            break;
-        }
+        let current_stream = skip_trivia(stream, self);
 
         // Check for prefix macros
-        let next_token_text = if let Some(TokenTree::Token(token)) = current_stream.first() {
-            Some(token.text.as_str())
-        } else {
-            None
-        };
-
-        if let Some(text) = next_token_text {
+        if let Some(TokenTree::Token(name)) = current_stream.first() {
             for mac in self.language.macros() {
-                if !mac.is_operator() && mac.name() == text {
+                if !mac.is_operator() && mac.name() == name.text {
                     // Found prefix macro
                     let stream_after_name = current_stream.advance(1); // Consume name
 
@@ -60,7 +80,9 @@ impl<'a, L: Language> Parser<'a, L> {
                     let (args, next_stream) =
                         mac.signature().match_shape(stream_after_name, self)?;
 
-                    let context = MacroContext;
+                    let context = MacroContext {
+                        name: Some(name.clone()),
+                    };
                     match mac.expand(args, None, &context) {
                         ExpansionResult::Ok(expanded) => return Ok((expanded, next_stream)),
                         ExpansionResult::Error(msg) => {
@@ -91,26 +113,17 @@ impl<'a, L: Language> MatchContext for Parser<'a, L> {
         loop {
             let mut matched_op = None;
 
-            // Peek at next token (skipping whitespace)
-            let mut peek_stream = current_stream.clone();
-            while let Some(tree) = peek_stream.first() {
-                if let TokenTree::Token(token) = tree
-                    && token.kind == AtomKind::Whitespace {
-                        peek_stream = peek_stream.advance(1);
-                        continue;
-                    }
-                break;
-            }
+            // Peek at next token (skipping trivia)
+            let peek_stream = skip_trivia(current_stream.clone(), self);
 
-            let next_token_text = if let Some(TokenTree::Token(token)) = peek_stream.first() {
-                Some(token.text.as_str())
-            } else {
-                None
+            let operator = match peek_stream.first() {
+                Some(TokenTree::Token(token)) => Some(token),
+                _ => None,
             };
 
-            if let Some(text) = next_token_text {
+            if let Some(operator) = operator {
                 for mac in self.language.macros() {
-                    if mac.is_operator() && mac.name() == text {
+                    if mac.is_operator() && mac.name() == operator.text {
                         if mac.precedence() < min_prec {
                             continue;
                         }
@@ -137,7 +150,9 @@ impl<'a, L: Language> MatchContext for Parser<'a, L> {
                 current_stream = next_stream;
 
                 // Expand
-                let context = MacroContext;
+                let context = MacroContext {
+                    name: operator.cloned(),
+                };
                 match mac.expand(args, Some(lhs.clone()), &context) {
                     ExpansionResult::Ok(expanded) => {
                         lhs = expanded;
@@ -151,15 +166,25 @@ impl<'a, L: Language> MatchContext for Parser<'a, L> {
 
         Ok((lhs, current_stream))
     }
+
+    fn is_trivia(&self, kind: &AtomKind) -> bool {
+        self.language.is_trivia(kind)
+    }
+
+    fn memo_table(&mut self) -> Option<&mut MemoTable> {
+        let trees = self.stream.trees;
+        Some(self.memo.get_or_insert_with(|| MemoTable::for_trees(trees)))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::atoms::RegexAtom;
     use crate::lexer::lex;
     use crate::r#macro::{ExpansionResult, Macro, MacroContext};
     use crate::mock::MockLanguage;
-    use crate::shape::{Precedence, Shape, expr, recover, seq, term};
+    use crate::shape::{Precedence, Shape, expr, memo, recover, seq, term};
     use crate::token::TokenTree;
 
     #[derive(Debug)]
@@ -276,6 +301,50 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_program_reports_trailing_input() {
+        let lang = MockLanguage::new()
+            .with_symbol("+")
+            .with_macro(Box::new(PlusMacro::new()));
+
+        let trees = lex("a + b", &lang);
+        let mut parser = Parser::new(TokenStream::new(&trees), &lang);
+        assert!(parser.parse_program().is_ok());
+
+        // `parse` stops after `a + b`; `parse_program` rejects the rest.
+        let trees = lex("a + b c d", &lang);
+        let mut parser = Parser::new(TokenStream::new(&trees), &lang);
+        let error = parser.parse_program().unwrap_err();
+        assert_eq!(error.span, (6, 1).into());
+    }
+
+    #[test]
+    fn test_parse_skips_comments() {
+        let lang = MockLanguage::new()
+            .with_symbol("+")
+            .with_atom(Box::new(RegexAtom::new(AtomKind::Comment, r"/\*.*?\*/")))
+            .with_macro(Box::new(PlusMacro::new()));
+
+        let input = "/* a */ a /* b */ + /* c */ b";
+        let trees = lex(input, &lang);
+        let stream = TokenStream::new(&trees);
+        let mut parser = Parser::new(stream, &lang);
+
+        let result = parser.parse().unwrap();
+
+        let TokenTree::Group(items) = result else {
+            panic!("Expected Group, got {:?}", result);
+        };
+        let texts: Vec<_> = items
+            .iter()
+            .map(|item| match item {
+                TokenTree::Token(t) => t.text.as_str(),
+                other => panic!("Expected token, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(texts, ["a", "b"]);
+    }
+
     #[test]
     fn test_recover() {
         // A macro that expects "foo" then "bar".
@@ -330,10 +399,45 @@ mod tests {
 
         let result = parser.parse().unwrap();
 
-        if let TokenTree::Error(msg) = result {
+        if let TokenTree::Error(msg, _) = result {
             assert!(msg.contains("skipped"));
         } else {
             panic!("Expected Error, got {:?}", result);
         }
     }
+
+    #[test]
+    fn test_parser_keeps_memo_table() {
+        let lang = MockLanguage::new();
+        let trees = lex("a b", &lang);
+        let stream = TokenStream::new(&trees);
+        let mut parser = Parser::new(stream.clone(), &lang);
+
+        let shape = memo(seq(term("a"), term("b")));
+        let (first, _) = shape.match_shape(stream.clone(), &mut parser).unwrap();
+        let (second, rest) = shape.match_shape(stream, &mut parser).unwrap();
+        assert_eq!(first.to_sexp(), second.to_sexp());
+        assert!(rest.is_empty());
+        assert_eq!(parser.memo.as_ref().unwrap().len(), 1);
+
+        parser.clear_memo();
+        assert!(parser.memo.is_none());
+    }
+
+    #[test]
+    fn test_parser_memo_ignores_other_buffers() {
+        let lang = MockLanguage::new();
+        let mut parser = Parser::new(TokenStream::new(&[]), &lang);
+        let shape = memo(term(AtomKind::Identifier));
+
+        // Each buffer is dropped before the next is lexed, so they may share an address.
+        for input in ["a", "b", "c"] {
+            let trees = lex(input, &lang);
+            let (tree, _) = shape
+                .match_shape(TokenStream::new(&trees), &mut parser)
+                .unwrap();
+            assert_eq!(tree.text(), input);
+        }
+        assert!(parser.memo.as_ref().unwrap().is_empty());
+    }
 }
@@ -1,5 +1,5 @@
 use crate::atom::AtomKind;
-use crate::token::{BindingId, TokenTree};
+use crate::token::{BindingId, SyntaxNode, TokenTree};
 use std::collections::HashMap;
 use std::fmt::Debug;
 
@@ -101,10 +101,15 @@ pub trait BindingPass: Debug + Send + Sync {
                         return true;
                     }
                 }
-                TokenTree::Group(children) => {
-                    if self.collect_scope_at(children, offset, scope) {
-                        return true;
-                    }
+                TokenTree::Group(children) | TokenTree::Node(SyntaxNode { children, .. })
+                    if self.collect_scope_at(children, offset, scope) =>
+                {
+                    return true;
+                }
+                TokenTree::Field(_, value)
+                    if self.collect_scope_at(std::slice::from_ref(value), offset, scope) =>
+                {
+                    return true;
                 }
                 _ => {}
             }
@@ -135,17 +140,31 @@ impl ReferencePass for NoOpReferencePass {
     fn resolve_references(&self, _tokens: &mut [TokenTree], _scope: &mut ScopeStack) {}
 }
 
+/// Binds the identifier after each occurrence of a keyword, as in `let x`.
+///
+/// Trivia between the keyword and the identifier is skipped. It defaults to
+/// [`AtomKind::is_trivia`]; a language that overrides
+/// [`Language::is_trivia`](crate::Language::is_trivia) should pass the same
+/// predicate to [`SimpleBindingPass::with_trivia`].
 #[derive(Debug)]
 pub struct SimpleBindingPass {
     keyword: String,
+    is_trivia: fn(&AtomKind) -> bool,
 }
 
 impl SimpleBindingPass {
     pub fn new(keyword: &str) -> Self {
         Self {
             keyword: keyword.to_string(),
+            is_trivia: AtomKind::is_trivia,
         }
     }
+
+    /// Sets which token kinds are trivia, to match the language's.
+    pub fn with_trivia(mut self, is_trivia: fn(&AtomKind) -> bool) -> Self {
+        self.is_trivia = is_trivia;
+        self
+    }
 }
 
 impl BindingPass for SimpleBindingPass {
@@ -153,7 +172,13 @@ impl BindingPass for SimpleBindingPass {
         let mut i = 0;
         while i < tokens.len() {
             // Handle recursion
-            let is_container = matches!(tokens[i], TokenTree::Delimited(..) | TokenTree::Group(..));
+            let is_container = matches!(
+                tokens[i],
+                TokenTree::Delimited(..)
+                    | TokenTree::Group(..)
+                    | TokenTree::Node(..)
+                    | TokenTree::Field(..)
+            );
 
             if is_container {
                 match &mut tokens[i] {
@@ -162,9 +187,12 @@ impl BindingPass for SimpleBindingPass {
                         self.identify_bindings(children, scope);
                         scope.pop();
                     }
-                    TokenTree::Group(children) => {
+                    TokenTree::Group(children) | TokenTree::Node(SyntaxNode { children, .. }) => {
                         self.identify_bindings(children, scope);
                     }
+                    TokenTree::Field(_, value) => {
+                        self.identify_bindings(std::slice::from_mut(value.as_mut()), scope);
+                    }
                     _ => unreachable!(),
                 }
                 i += 1;
@@ -179,16 +207,16 @@ impl BindingPass for SimpleBindingPass {
             };
 
             if is_keyword {
-                // Look ahead for identifier, skipping whitespace
+                // Look ahead for identifier, skipping trivia
                 let mut j = i + 1;
                 while j < tokens.len() {
-                    let is_whitespace = if let TokenTree::Token(t) = &tokens[j] {
-                        matches!(t.kind, AtomKind::Whitespace)
+                    let is_trivia = if let TokenTree::Token(t) = &tokens[j] {
+                        (self.is_trivia)(&t.kind)
                     } else {
                         false
                     };
 
-                    if is_whitespace {
+                    if is_trivia {
                         j += 1;
                         continue;
                     }
@@ -265,7 +293,14 @@ impl BindingPass for SimpleBindingPass {
             }
 
             // Handle Group recursion (transparent)
-            if let TokenTree::Group(children) = current_token {
+            let transparent = match current_token {
+                TokenTree::Group(children) | TokenTree::Node(SyntaxNode { children, .. }) => {
+                    Some(children.as_slice())
+                }
+                TokenTree::Field(_, value) => Some(std::slice::from_ref(value.as_ref())),
+                _ => None,
+            };
+            if let Some(children) = transparent {
                 if self.collect_scope_at(children, offset, scope) {
                     return true;
                 }
@@ -282,16 +317,16 @@ impl BindingPass for SimpleBindingPass {
             };
 
             if is_keyword {
-                // Look ahead for identifier
+                // Look ahead for identifier, skipping trivia
                 let mut j = i + 1;
                 while j < tokens.len() {
-                    let is_whitespace = if let TokenTree::Token(t) = &tokens[j] {
-                        matches!(t.kind, AtomKind::Whitespace)
+                    let is_trivia = if let TokenTree::Token(t) = &tokens[j] {
+                        (self.is_trivia)(&t.kind)
                     } else {
                         false
                     };
 
-                    if is_whitespace {
+                    if is_trivia {
                         j += 1;
                         continue;
                     }
@@ -330,9 +365,12 @@ impl ReferencePass for SimpleReferencePass {
                     self.resolve_references(children, scope);
                     scope.pop();
                 }
-                TokenTree::Group(children) => {
+                TokenTree::Group(children) | TokenTree::Node(SyntaxNode { children, .. }) => {
                     self.resolve_references(children, scope);
                 }
+                TokenTree::Field(_, value) => {
+                    self.resolve_references(std::slice::from_mut(value.as_mut()), scope);
+                }
                 TokenTree::Token(token) => {
                     if matches!(token.kind, AtomKind::Identifier) {
                         if let Some(id) = token.binding {
@@ -364,3 +402,67 @@ pub fn scope_tokens(tokens: &mut [TokenTree], language: &impl crate::Language) {
         .reference_pass()
         .resolve_references(tokens, &mut scope);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token::Token;
+
+    fn tokens(parts: &[(&str, AtomKind)]) -> Vec<TokenTree> {
+        let mut offset = 0;
+        parts
+            .iter()
+            .map(|(text, kind)| {
+                let token = Token::new(kind.clone(), text, offset);
+                offset += text.len();
+                TokenTree::Token(token)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_binding_skips_comments_after_keyword() {
+        let pass = SimpleBindingPass::new("let");
+        let mut trees = tokens(&[
+            ("let", AtomKind::Identifier),
+            (" ", AtomKind::Whitespace),
+            ("/* c */", AtomKind::Comment),
+            (" ", AtomKind::Whitespace),
+            ("x", AtomKind::Identifier),
+            (" ", AtomKind::Whitespace),
+            ("y", AtomKind::Identifier),
+        ]);
+
+        pass.identify_bindings(&mut trees, &mut ScopeStack::new());
+        assert!(matches!(&trees[4], TokenTree::Token(t) if t.binding.is_some()));
+
+        let mut scope = ScopeStack::new();
+        assert!(pass.collect_scope_at(&trees, 14, &mut scope));
+        assert_eq!(scope.names(), vec!["x".to_string()]);
+    }
+
+    #[test]
+    fn test_binding_skips_language_trivia() {
+        let newline = || AtomKind::Other("Newline".into());
+        let trees = tokens(&[
+            ("let", AtomKind::Identifier),
+            ("\n", newline()),
+            ("x", AtomKind::Identifier),
+            (" ", AtomKind::Whitespace),
+            ("y", AtomKind::Identifier),
+        ]);
+        let bound = |pass: &SimpleBindingPass| {
+            let mut trees = trees.clone();
+            pass.identify_bindings(&mut trees, &mut ScopeStack::new());
+            let mut scope = ScopeStack::new();
+            pass.collect_scope_at(&trees, 6, &mut scope);
+            let binding = matches!(&trees[2], TokenTree::Token(t) if t.binding.is_some());
+            (binding, scope.names())
+        };
+
+        assert_eq!(bound(&SimpleBindingPass::new("let")), (false, vec![]));
+        let pass = SimpleBindingPass::new("let")
+            .with_trivia(|kind| kind.is_trivia() || *kind == AtomKind::Other("Newline".into()));
+        assert_eq!(bound(&pass), (true, vec!["x".to_string()]));
+    }
+}
@@ -1,21 +1,42 @@
 use crate::atom::AtomKind;
-use crate::language::Delimiter;
-use crate::token::{Token, TokenStream, TokenTree};
+use crate::language::{Delimiter, SharedStr};
+use crate::lexer::{tree_location, tree_span};
+use crate::token::{SourceLocation, SyntaxNode, Token, TokenStream, TokenTree};
 use miette::{Diagnostic, SourceSpan};
+use std::any::TypeId;
+use std::collections::HashMap;
 use std::fmt::Debug;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use thiserror::Error;
 
+pub mod tuple;
+pub mod typed;
+
 #[derive(Debug, Clone, PartialEq, Eq, Error, Diagnostic)]
 #[error("{message}")]
 pub struct ParseError {
     #[label("here")]
     pub span: SourceSpan,
     pub message: String,
+    /// Whether the input matched the grammar but a semantic action rejected it,
+    /// as in [`typed::TypedShape::try_map`]. Typed shapes never backtrack past
+    /// such an error, so they take the same alternatives as `match_shape`.
+    pub semantic: bool,
 }
 
 impl ParseError {
     pub fn new(span: SourceSpan, message: String) -> Self {
-        Self { span, message }
+        Self {
+            span,
+            message,
+            semantic: false,
+        }
+    }
+
+    /// Marks this error as [semantic](ParseError::semantic).
+    pub fn into_semantic(mut self) -> Self {
+        self.semantic = true;
+        self
     }
 }
 
@@ -55,7 +76,9 @@ pub enum Associativity {
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Precedence(pub u32);
 
-pub type MatchResult<'a> = Result<(TokenTree, TokenStream<'a>), ParseError>;
+/// The result of matching a shape: its output and the rest of the stream.
+/// Typed shapes (see [`typed`]) produce other outputs.
+pub type MatchResult<'a, T = TokenTree> = Result<(T, TokenStream<'a>), ParseError>;
 
 /// Context provided to shapes during matching.
 /// Allows shapes to perform complex logic like expression parsing with precedence.
@@ -65,6 +88,101 @@ pub trait MatchContext {
         stream: TokenStream<'a>,
         precedence: Precedence,
     ) -> MatchResult<'a>;
+
+    /// Whether tokens of `kind` are trivia, which shapes skip.
+    fn is_trivia(&self, kind: &AtomKind) -> bool {
+        kind.is_trivia()
+    }
+
+    /// The table [`memo`] shapes cache their results in, if this context
+    /// keeps one. Without a table, `memo` shapes match as usual.
+    fn memo_table(&mut self) -> Option<&mut MemoTable> {
+        None
+    }
+}
+
+/// Cached results of [`memo`] shapes, keyed by shape and stream position.
+///
+/// Positions are addresses in a token buffer, and a new buffer may reuse the
+/// memory of a dropped one. A table from [`MemoTable::for_trees`] only caches
+/// results within the trees it was made for; one from [`MemoTable::new`]
+/// caches any stream, and must be cleared before it is used with another buffer.
+#[derive(Debug, Default)]
+pub struct MemoTable {
+    entries: HashMap<MemoKey, Result<(TokenTree, usize), ParseError>>,
+    /// The sorted address ranges of the buffers this table caches results
+    /// for, or `None` for any buffer.
+    buffers: Option<Vec<(usize, usize)>>,
+}
+
+/// A memoized shape, and the address and length of the stream it matched.
+type MemoKey = (MemoId, usize, usize);
+
+impl MemoTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A table for streams within `trees` and the groups inside them. Results
+    /// for other streams aren't cached, so the table can be used with any
+    /// stream while `trees` lives.
+    pub fn for_trees(trees: &[TokenTree]) -> Self {
+        fn collect(trees: &[TokenTree], buffers: &mut Vec<(usize, usize)>) {
+            let range = trees.as_ptr_range();
+            buffers.push((range.start as usize, range.end as usize));
+            for tree in trees {
+                match tree {
+                    TokenTree::Delimited(_, children, _, _)
+                    | TokenTree::Group(children)
+                    | TokenTree::Node(SyntaxNode { children, .. }) => collect(children, buffers),
+                    _ => {}
+                }
+            }
+        }
+
+        let mut buffers = Vec::new();
+        collect(trees, &mut buffers);
+        buffers.sort_unstable();
+        Self {
+            entries: HashMap::new(),
+            buffers: Some(buffers),
+        }
+    }
+
+    /// Whether results for `stream` may be cached in this table.
+    fn covers(&self, stream: &TokenStream) -> bool {
+        let Some(buffers) = &self.buffers else {
+            return true;
+        };
+        let range = stream.trees.as_ptr_range();
+        let (start, end) = (range.start as usize, range.end as usize);
+        let index = buffers.partition_point(|&(buffer_start, _)| buffer_start <= start);
+        index > 0 && end <= buffers[index - 1].1
+    }
+
+    /// The number of cached results.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// Skips the trivia tokens at the start of `stream`.
+pub fn skip_trivia<'a>(stream: TokenStream<'a>, context: &dyn MatchContext) -> TokenStream<'a> {
+    let mut stream = stream;
+    while let Some(TokenTree::Token(token)) = stream.first()
+        && context.is_trivia(&token.kind)
+    {
+        stream = stream.advance(1);
+    }
+    stream
 }
 
 pub struct NoOpMatchContext;
@@ -111,6 +229,69 @@ pub trait Shape: Debug + Send + Sync {
     ) -> Vec<CompletionItem> {
         vec![]
     }
+
+    /// Describes this shape's parts for grammar analysis (see [`crate::lint`]).
+    ///
+    /// Shapes that build their body in `match_shape`, like recursive grammar
+    /// rules, should return [`Structure::Rule`] with that body.
+    fn structure(&self) -> Structure<'_> {
+        Structure::Opaque
+    }
+}
+
+/// The parts of a shape, as seen by grammar analysis.
+#[derive(Debug)]
+pub enum Structure<'s> {
+    /// Matches one token or group, described by its matcher.
+    Term(String),
+    /// The shapes matched in turn.
+    Seq(Vec<&'s dyn Shape>),
+    /// The alternatives, in the order they are tried.
+    Choice(Vec<&'s dyn Shape>),
+    Rep(&'s dyn Shape),
+    Enter(&'s Delimiter, &'s dyn Shape),
+    Adjacent(&'s dyn Shape, &'s dyn Shape),
+    Empty,
+    End,
+    Expr(Precedence),
+    Recover(&'s dyn Shape),
+    /// Wraps its result in a node of the given kind.
+    Node(&'s SharedStr, &'s dyn Shape),
+    /// Labels its result as a field of the enclosing node.
+    Field(&'s SharedStr, &'s dyn Shape),
+    /// A named shape whose body may refer back to it. The name identifies the
+    /// rule when looking for cycles.
+    Rule(&'static str, Box<dyn Shape>),
+    /// A shape the analysis cannot see into, assumed to consume a token.
+    Opaque,
+}
+
+/// Lets shapes chosen at runtime be combined like any other.
+impl Shape for Box<dyn Shape> {
+    fn match_shape<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+    ) -> MatchResult<'a> {
+        self.as_ref().match_shape(stream, context)
+    }
+
+    fn adjacency(&self) -> AdjacencyConstraint {
+        self.as_ref().adjacency()
+    }
+
+    fn complete<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+        cursor: usize,
+    ) -> Vec<CompletionItem> {
+        self.as_ref().complete(stream, context, cursor)
+    }
+
+    fn structure(&self) -> Structure<'_> {
+        self.as_ref().structure()
+    }
 }
 
 // Matcher Trait
@@ -177,6 +358,24 @@ impl Matcher for &str {
     }
 }
 
+impl Matcher for String {
+    fn matches(&self, tree: &TokenTree) -> bool {
+        Matcher::matches(&self.as_str(), tree)
+    }
+
+    fn describe(&self) -> String {
+        Matcher::describe(&self.as_str())
+    }
+
+    fn suggest(&self, current_token: &Token) -> Vec<CompletionItem> {
+        Matcher::suggest(&self.as_str(), current_token)
+    }
+
+    fn suggest_insertion(&self) -> Vec<CompletionItem> {
+        Matcher::suggest_insertion(&self.as_str())
+    }
+}
+
 impl Matcher for Delimiter {
     fn matches(&self, tree: &TokenTree) -> bool {
         match tree {
@@ -194,7 +393,7 @@ impl Matcher for Delimiter {
 
 // term
 /// Matches a single token or tree using the provided `Matcher`.
-/// Implicitly skips leading whitespace.
+/// Implicitly skips leading trivia (whitespace and comments).
 #[derive(Debug, Clone)]
 pub struct Term<M: Matcher>(pub M);
 
@@ -202,20 +401,9 @@ impl<M: Matcher> Shape for Term<M> {
     fn match_shape<'a>(
         &self,
         stream: TokenStream<'a>,
-        _context: &mut dyn MatchContext,
+        context: &mut dyn MatchContext,
     ) -> MatchResult<'a> {
-        let mut current_stream = stream;
-
-        // Skip whitespace
-        while let Some(tree) = current_stream.first() {
-            if let TokenTree::Token(token) = tree
-                && token.kind == AtomKind::Whitespace
-            {
-                current_stream = current_stream.advance(1);
-                continue;
-            }
-            break;
-        }
+        let current_stream = skip_trivia(stream, context);
 
         if let Some(tree) = current_stream.first() {
             if self.0.matches(tree) {
@@ -226,7 +414,12 @@ impl<M: Matcher> Shape for Term<M> {
                 TokenTree::Token(t) => (t.location.span, t.kind.to_string()),
                 TokenTree::Delimited(d, _, loc, _) => (loc.span, format!("Delimiter {}", d.kind)),
                 TokenTree::Group(_) => ((0, 0).into(), "Group".to_string()),
-                TokenTree::Error(_) => ((0, 0).into(), "Error".to_string()),
+                TokenTree::Node(node) => (node.location.span, format!("Node {}", node.kind)),
+                TokenTree::Field(name, value) => (
+                    tree_span(value).unwrap_or_else(|| (0, 0).into()),
+                    format!("Field {}", name),
+                ),
+                TokenTree::Error(_, loc) => (loc.span, "Error".to_string()),
                 TokenTree::Empty => ((0, 0).into(), "Empty".to_string()),
             };
 
@@ -245,15 +438,15 @@ impl<M: Matcher> Shape for Term<M> {
     fn complete<'a>(
         &self,
         stream: TokenStream<'a>,
-        _context: &mut dyn MatchContext,
+        context: &mut dyn MatchContext,
         cursor: usize,
     ) -> Vec<CompletionItem> {
         let mut current_stream = stream;
 
-        // Skip whitespace
+        // Skip trivia
         while let Some(tree) = current_stream.first() {
             if let TokenTree::Token(token) = tree
-                && token.kind == AtomKind::Whitespace
+                && context.is_trivia(&token.kind)
             {
                 if token.location.contains(cursor) {
                     return self.0.suggest_insertion();
@@ -274,10 +467,8 @@ impl<M: Matcher> Shape for Term<M> {
                         return self.0.suggest_insertion();
                     }
                 }
-                TokenTree::Delimited(_, _, loc, _) => {
-                    if loc.span.offset() >= cursor {
-                        return self.0.suggest_insertion();
-                    }
+                TokenTree::Delimited(_, _, loc, _) if loc.span.offset() >= cursor => {
+                    return self.0.suggest_insertion();
                 }
                 _ => {}
             }
@@ -287,6 +478,10 @@ impl<M: Matcher> Shape for Term<M> {
 
         vec![]
     }
+
+    fn structure(&self) -> Structure<'_> {
+        Structure::Term(self.0.describe())
+    }
 }
 
 pub fn term<M: Matcher>(matcher: M) -> Term<M> {
@@ -295,7 +490,7 @@ pub fn term<M: Matcher>(matcher: M) -> Term<M> {
 
 // seq
 /// Matches shape `A` followed by shape `B`.
-/// Implicitly skips whitespace between `A` and `B` (because `B`'s first term will skip it).
+/// Implicitly skips trivia between `A` and `B` (because `B`'s first term will skip it).
 #[derive(Debug, Clone)]
 pub struct Seq<A, B>(pub A, pub B);
 
@@ -339,6 +534,10 @@ impl<A: Shape, B: Shape> Shape for Seq<A, B> {
             }
         }
     }
+
+    fn structure(&self) -> Structure<'_> {
+        Structure::Seq(vec![&self.0, &self.1])
+    }
 }
 
 pub fn seq<A: Shape, B: Shape>(a: A, b: B) -> Seq<A, B> {
@@ -372,6 +571,10 @@ impl<A: Shape, B: Shape> Shape for Choice<A, B> {
         items.extend(self.1.complete(stream, context, cursor));
         items
     }
+
+    fn structure(&self) -> Structure<'_> {
+        Structure::Choice(vec![&self.0, &self.1])
+    }
 }
 
 pub fn choice<A: Shape, B: Shape>(a: A, b: B) -> Choice<A, B> {
@@ -383,16 +586,18 @@ pub fn choice<A: Shape, B: Shape>(a: A, b: B) -> Choice<A, B> {
 #[derive(Debug, Clone)]
 pub struct Rep<A>(pub A);
 
-impl<A: Shape> Shape for Rep<A> {
-    fn match_shape<'a>(
+impl<A> Rep<A> {
+    /// Matches `A` as many times as possible, using `item` to match it once.
+    pub(crate) fn repeat<'a, T>(
         &self,
         stream: TokenStream<'a>,
         context: &mut dyn MatchContext,
-    ) -> MatchResult<'a> {
+        mut item: impl FnMut(&A, TokenStream<'a>, &mut dyn MatchContext) -> MatchResult<'a, T>,
+    ) -> (Vec<T>, TokenStream<'a>) {
         let mut current_stream = stream;
         let mut results = Vec::new();
 
-        while let Ok((res, next_stream)) = self.0.match_shape(current_stream.clone(), context) {
+        while let Ok((res, next_stream)) = item(&self.0, current_stream.clone(), context) {
             if next_stream.trees.len() == current_stream.trees.len() {
                 // Matched empty, break to avoid infinite loop
                 results.push(res);
@@ -403,7 +608,20 @@ impl<A: Shape> Shape for Rep<A> {
             current_stream = next_stream;
         }
 
-        Ok((TokenTree::Group(results), current_stream))
+        (results, current_stream)
+    }
+}
+
+impl<A: Shape> Shape for Rep<A> {
+    fn match_shape<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+    ) -> MatchResult<'a> {
+        let (results, rest) = self.repeat(stream, context, |a, stream, context| {
+            a.match_shape(stream, context)
+        });
+        Ok((TokenTree::Group(results), rest))
     }
 
     fn complete<'a>(
@@ -434,6 +652,10 @@ impl<A: Shape> Shape for Rep<A> {
         }
         vec![]
     }
+
+    fn structure(&self) -> Structure<'_> {
+        Structure::Rep(&self.0)
+    }
 }
 
 pub fn rep<A: Shape>(a: A) -> Rep<A> {
@@ -442,48 +664,33 @@ pub fn rep<A: Shape>(a: A) -> Rep<A> {
 
 // enter
 /// Matches a delimited group (e.g., `(...)`) and then matches the `inner` shape against the content of that group.
-/// Implicitly skips leading whitespace before the group.
+/// Implicitly skips leading trivia before the group.
 #[derive(Debug, Clone)]
 pub struct Enter<S>(pub Delimiter, pub S);
 
-impl<S: Shape> Shape for Enter<S> {
-    fn match_shape<'a>(
+impl<S> Enter<S> {
+    /// Enters the delimited group and matches its contents with `inner`.
+    pub(crate) fn enter_with<'a, T>(
         &self,
         stream: TokenStream<'a>,
         context: &mut dyn MatchContext,
-    ) -> MatchResult<'a> {
-        // 1. Match delimiter (skipping whitespace)
-        let mut current_stream = stream;
-
-        while let Some(tree) = current_stream.first() {
-            if let TokenTree::Token(token) = tree
-                && token.kind == AtomKind::Whitespace
-            {
-                current_stream = current_stream.advance(1);
-                continue;
-            }
-            break;
-        }
+        inner: impl FnOnce(&S, TokenStream<'a>, &mut dyn MatchContext) -> MatchResult<'a, T>,
+    ) -> MatchResult<'a, T> {
+        // 1. Match delimiter (skipping trivia)
+        let current_stream = skip_trivia(stream, context);
 
-        if let Some(TokenTree::Delimited(d, content, loc, _)) = current_stream.first()
+        if let Some(TokenTree::Delimited(d, content, loc, _)) = current_stream.trees.first()
             && d.kind == self.0.kind
         {
             // 2. Create new stream from content
             let inner_stream = TokenStream::new(content);
 
             // 3. Match inner
-            let (res, remaining_inner) = self.1.match_shape(inner_stream, context)?;
+            let (res, remaining_inner) = inner(&self.1, inner_stream, context)?;
 
             // 4. Ensure inner consumed everything (Implicit Exit/End)
-            let mut check_stream = remaining_inner;
-            while let Some(tree) = check_stream.first() {
-                if let TokenTree::Token(token) = tree
-                    && token.kind == AtomKind::Whitespace
-                {
-                    check_stream = check_stream.advance(1);
-                    continue;
-                }
-                // Found non-whitespace, so inner didn't consume everything
+            if let Some(tree) = skip_trivia(remaining_inner, context).first() {
+                // Found non-trivia, so inner didn't consume everything
                 let span = match tree {
                     TokenTree::Token(t) => t.location.span,
                     TokenTree::Delimited(_, _, loc, _) => loc.span,
@@ -505,6 +712,18 @@ impl<S: Shape> Shape for Enter<S> {
             format!("Expected {}", self.0.describe()),
         ))
     }
+}
+
+impl<S: Shape> Shape for Enter<S> {
+    fn match_shape<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+    ) -> MatchResult<'a> {
+        self.enter_with(stream, context, |inner, stream, context| {
+            inner.match_shape(stream, context)
+        })
+    }
 
     fn complete<'a>(
         &self,
@@ -512,18 +731,7 @@ impl<S: Shape> Shape for Enter<S> {
         context: &mut dyn MatchContext,
         cursor: usize,
     ) -> Vec<CompletionItem> {
-        let mut current_stream = stream;
-
-        // Skip whitespace
-        while let Some(tree) = current_stream.first() {
-            if let TokenTree::Token(token) = tree
-                && token.kind == AtomKind::Whitespace
-            {
-                current_stream = current_stream.advance(1);
-                continue;
-            }
-            break;
-        }
+        let current_stream = skip_trivia(stream, context);
 
         if let Some(TokenTree::Delimited(d, content, loc, _)) = current_stream.first()
             && d.kind == self.0.kind
@@ -534,6 +742,10 @@ impl<S: Shape> Shape for Enter<S> {
         }
         vec![]
     }
+
+    fn structure(&self) -> Structure<'_> {
+        Structure::Enter(&self.0, &self.1)
+    }
 }
 
 pub fn enter<S: Shape>(delimiter: Delimiter, inner: S) -> Enter<S> {
@@ -541,7 +753,7 @@ pub fn enter<S: Shape>(delimiter: Delimiter, inner: S) -> Enter<S> {
 }
 
 // adjacent
-/// Matches shape `A` followed by shape `B` with **no** intervening whitespace.
+/// Matches shape `A` followed by shape `B` with **no** intervening trivia.
 /// Used for tight binding (e.g., `obj.prop`).
 #[derive(Debug, Clone)]
 pub struct Adjacent<A, B>(pub A, pub B);
@@ -553,20 +765,38 @@ impl<A: Shape, B: Shape> Shape for Adjacent<A, B> {
         context: &mut dyn MatchContext,
     ) -> MatchResult<'a> {
         let (res_a, stream_after_a) = self.0.match_shape(stream, context)?;
-
-        // Check for whitespace at the start of stream_after_a
-        if let Some(TokenTree::Token(token)) = stream_after_a.first()
-            && token.kind == AtomKind::Whitespace
-        {
-            return Err(ParseError::new(
-                token.location.span,
-                "Unexpected whitespace".into(),
-            ));
-        }
-
+        reject_trivia(&stream_after_a, context)?;
         let (res_b, stream_after_b) = self.1.match_shape(stream_after_a, context)?;
         Ok((TokenTree::Group(vec![res_a, res_b]), stream_after_b))
     }
+
+    fn structure(&self) -> Structure<'_> {
+        Structure::Adjacent(&self.0, &self.1)
+    }
+}
+
+/// Fails if there is trivia at the start of `stream`, for `adjacent`: either
+/// a trivia token, or trivia attached to the tokens on either side.
+pub(crate) fn reject_trivia(
+    stream: &TokenStream,
+    context: &dyn MatchContext,
+) -> Result<(), ParseError> {
+    let trailing = match stream.previous() {
+        Some(TokenTree::Token(previous)) => previous.trailing_trivia.first(),
+        _ => None,
+    };
+    let trivia = match stream.first() {
+        Some(TokenTree::Token(token)) if context.is_trivia(&token.kind) => Some(token),
+        Some(TokenTree::Token(token)) => trailing.or(token.leading_trivia.first()),
+        _ => trailing,
+    };
+    match trivia {
+        Some(token) => Err(ParseError::new(
+            token.location.span,
+            format!("Unexpected {}", token.kind),
+        )),
+        None => Ok(()),
+    }
 }
 
 pub fn adjacent<A: Shape, B: Shape>(a: A, b: B) -> Adjacent<A, B> {
@@ -586,6 +816,10 @@ impl Shape for Empty {
     ) -> MatchResult<'a> {
         Ok((TokenTree::Empty, stream))
     }
+
+    fn structure(&self) -> Structure<'_> {
+        Structure::Empty
+    }
 }
 
 pub fn empty() -> Empty {
@@ -601,16 +835,10 @@ impl Shape for End {
     fn match_shape<'a>(
         &self,
         stream: TokenStream<'a>,
-        _context: &mut dyn MatchContext,
+        context: &mut dyn MatchContext,
     ) -> MatchResult<'a> {
-        let mut current_stream = stream;
-        while let Some(tree) = current_stream.first() {
-            if let TokenTree::Token(token) = tree
-                && token.kind == AtomKind::Whitespace
-            {
-                current_stream = current_stream.advance(1);
-                continue;
-            }
+        let current_stream = skip_trivia(stream, context);
+        if let Some(tree) = current_stream.first() {
             let span = match tree {
                 TokenTree::Token(t) => t.location.span,
                 TokenTree::Delimited(_, _, loc, _) => loc.span,
@@ -620,6 +848,10 @@ impl Shape for End {
         }
         Ok((TokenTree::Empty, current_stream))
     }
+
+    fn structure(&self) -> Structure<'_> {
+        Structure::End
+    }
 }
 
 pub fn end() -> End {
@@ -639,6 +871,10 @@ impl Shape for Expr {
     ) -> MatchResult<'a> {
         context.parse_expression(stream, self.0)
     }
+
+    fn structure(&self) -> Structure<'_> {
+        Structure::Expr(self.0)
+    }
 }
 
 pub fn expr(precedence: Precedence) -> Expr {
@@ -648,8 +884,37 @@ pub fn expr(precedence: Precedence) -> Expr {
 // Derived
 
 /// Matches `A` optionally. Equivalent to `choice(a, empty())`.
-pub fn opt<A: Shape + Clone>(a: A) -> Choice<A, Empty> {
-    choice(a, empty())
+#[derive(Debug, Clone)]
+pub struct Opt<A>(pub A);
+
+impl<A: Shape> Shape for Opt<A> {
+    fn match_shape<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+    ) -> MatchResult<'a> {
+        match self.0.match_shape(stream.clone(), context) {
+            Ok(res) => Ok(res),
+            Err(_) => Ok((TokenTree::Empty, stream)),
+        }
+    }
+
+    fn complete<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+        cursor: usize,
+    ) -> Vec<CompletionItem> {
+        self.0.complete(stream, context, cursor)
+    }
+
+    fn structure(&self) -> Structure<'_> {
+        Structure::Choice(vec![&self.0, &Empty])
+    }
+}
+
+pub fn opt<A: Shape>(a: A) -> Opt<A> {
+    Opt(a)
 }
 
 /// Matches `item` separated by `sep`.
@@ -659,7 +924,7 @@ pub fn separated<A: Shape + Clone, S: Shape + Clone>(item: A, sep: S) -> Seq<A,
     seq(item.clone(), rep(seq(sep, item)))
 }
 
-/// Matches `A` joined by adjacency (no whitespace).
+/// Matches `A` joined by adjacency (no intervening trivia).
 pub fn joined<A: Shape + Clone>(a: A) -> Seq<A, Rep<Adjacent<Empty, A>>> {
     // seq(a, rep(adjacent(empty(), a)))
     seq(a.clone(), rep(adjacent(empty(), a)))
@@ -667,20 +932,23 @@ pub fn joined<A: Shape + Clone>(a: A) -> Seq<A, Rep<Adjacent<Empty, A>>> {
 
 // recover
 /// Tries to match `S`. If it fails, skips tokens until `M` matches (or EOF),
-/// and returns a `TokenTree::Error`.
+/// and returns a `TokenTree::Error` located at the skipped tokens.
 #[derive(Debug, Clone)]
 pub struct Recover<S, M>(pub S, pub M);
 
-impl<S: Shape, M: Matcher> Shape for Recover<S, M> {
-    fn match_shape<'a>(
+impl<S, M: Matcher> Recover<S, M> {
+    /// Matches `S` with `attempt`. On failure, skips to the terminator and
+    /// returns a `TokenTree::Error` instead.
+    pub(crate) fn recover_with<'a, T>(
         &self,
         stream: TokenStream<'a>,
         context: &mut dyn MatchContext,
-    ) -> MatchResult<'a> {
-        match self.0.match_shape(stream.clone(), context) {
-            Ok(res) => Ok(res),
+        attempt: impl FnOnce(&S, TokenStream<'a>, &mut dyn MatchContext) -> MatchResult<'a, T>,
+    ) -> (Result<T, TokenTree>, TokenStream<'a>) {
+        match attempt(&self.0, stream.clone(), context) {
+            Ok((res, rest)) => (Ok(res), rest),
             Err(_) => {
-                let mut current_stream = stream;
+                let mut current_stream = stream.clone();
                 let mut skipped_count = 0;
 
                 while let Some(tree) = current_stream.first() {
@@ -694,22 +962,285 @@ impl<S: Shape, M: Matcher> Shape for Recover<S, M> {
                     skipped_count += 1;
                 }
 
+                let location =
+                    skipped_location(&stream.trees[..skipped_count], &current_stream, context);
                 if skipped_count > 0 {
-                    Ok((
-                        TokenTree::Error(format!("Parse error, skipped {} tokens", skipped_count)),
-                        current_stream,
-                    ))
+                    let message = format!("Parse error, skipped {} tokens", skipped_count);
+                    (Err(TokenTree::Error(message, location)), current_stream)
                 } else {
                     // If we didn't skip anything and still failed (and didn't match terminator immediately),
                     // it means we are at EOF or terminator.
                     // If we are at terminator, we return Error but don't consume terminator.
-                    Ok((TokenTree::Error("Parse error".to_string()), current_stream))
+                    let message = "Parse error".to_string();
+                    (Err(TokenTree::Error(message, location)), current_stream)
                 }
             }
         }
     }
 }
 
+impl<S: Shape, M: Matcher> Shape for Recover<S, M> {
+    fn match_shape<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+    ) -> MatchResult<'a> {
+        let (res, rest) = self.recover_with(stream, context, |shape, stream, context| {
+            shape.match_shape(stream, context)
+        });
+        Ok((res.unwrap_or_else(|error| error), rest))
+    }
+
+    fn structure(&self) -> Structure<'_> {
+        Structure::Recover(&self.0)
+    }
+}
+
+/// The location of the `skipped` trees, without trivia. If only trivia was
+/// skipped, an empty location where the stream continues: at the next tree,
+/// or else at the end of the previous one.
+fn skipped_location(
+    skipped: &[TokenTree],
+    rest: &TokenStream,
+    context: &dyn MatchContext,
+) -> SourceLocation {
+    let mut located = skipped
+        .iter()
+        .filter(|tree| !matches!(tree, TokenTree::Token(t) if context.is_trivia(&t.kind)))
+        .filter_map(tree_location);
+    if let Some(first) = located.next() {
+        let last = located.last().unwrap_or_else(|| first.clone());
+        let end = last.span.offset() + last.span.len();
+        return SourceLocation {
+            span: SourceSpan::new(first.span.offset().into(), end - first.span.offset()),
+            file: first.file,
+        };
+    }
+
+    let next = rest
+        .first()
+        .and_then(tree_location)
+        .map(|next| (next.span.offset(), next.file));
+    let previous = || {
+        rest.previous()
+            .and_then(tree_location)
+            .map(|previous| (previous.span.offset() + previous.span.len(), previous.file))
+    };
+    match next.or_else(previous) {
+        Some((offset, file)) => SourceLocation {
+            span: SourceSpan::new(offset.into(), 0),
+            file,
+        },
+        None => SourceLocation::new(0, 0),
+    }
+}
+
 pub fn recover<S: Shape, M: Matcher>(shape: S, terminator: M) -> Recover<S, M> {
     Recover(shape, terminator)
 }
+
+// node
+/// Matches `S` and wraps the result in a `TokenTree::Node` of kind `.0`.
+///
+/// A `Group` result becomes the node's children, so
+/// `node("let", tuple::seq((..)))` has one child per element.
+#[derive(Debug, Clone)]
+pub struct Node<S>(pub SharedStr, pub S);
+
+impl<S: Shape> Shape for Node<S> {
+    fn match_shape<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+    ) -> MatchResult<'a> {
+        let start = skip_trivia(stream.clone(), context);
+        let (tree, rest) = self.1.match_shape(stream, context)?;
+        let children = match tree {
+            TokenTree::Group(children) => children,
+            TokenTree::Empty => Vec::new(),
+            tree => vec![tree],
+        };
+
+        let first = children.iter().find_map(tree_location);
+        let last = children.iter().rev().find_map(tree_location);
+        let location = match (first, last) {
+            (Some(first), Some(last)) => {
+                let end = last.span.offset() + last.span.len();
+                SourceLocation {
+                    span: SourceSpan::new(first.span.offset().into(), end - first.span.offset()),
+                    file: first.file,
+                }
+            }
+            // Nothing was consumed: an empty node where the match started.
+            _ => match start.first().and_then(tree_location) {
+                Some(next) => SourceLocation {
+                    span: SourceSpan::new(next.span.offset().into(), 0),
+                    file: next.file,
+                },
+                None => SourceLocation::new(0, 0),
+            },
+        };
+
+        let node = SyntaxNode {
+            kind: self.0.clone(),
+            children,
+            location,
+        };
+        Ok((TokenTree::Node(node), rest))
+    }
+
+    fn adjacency(&self) -> AdjacencyConstraint {
+        self.1.adjacency()
+    }
+
+    fn complete<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+        cursor: usize,
+    ) -> Vec<CompletionItem> {
+        self.1.complete(stream, context, cursor)
+    }
+
+    fn structure(&self) -> Structure<'_> {
+        Structure::Node(&self.0, &self.1)
+    }
+}
+
+/// Wraps the result of `shape` in a syntax node of the given kind,
+/// e.g. `node("let", tuple::seq((term("let"), field("name", term(Identifier)))))`.
+pub fn node<S: Shape>(kind: impl Into<SharedStr>, shape: S) -> Node<S> {
+    Node(kind.into(), shape)
+}
+
+// field
+/// Matches `S` and labels the result `.0`, so the enclosing node can find it
+/// with [`SyntaxNode::field`].
+#[derive(Debug, Clone)]
+pub struct Field<S>(pub SharedStr, pub S);
+
+impl<S: Shape> Shape for Field<S> {
+    fn match_shape<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+    ) -> MatchResult<'a> {
+        let (tree, rest) = self.1.match_shape(stream, context)?;
+        Ok((TokenTree::Field(self.0.clone(), Box::new(tree)), rest))
+    }
+
+    fn adjacency(&self) -> AdjacencyConstraint {
+        self.1.adjacency()
+    }
+
+    fn complete<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+        cursor: usize,
+    ) -> Vec<CompletionItem> {
+        self.1.complete(stream, context, cursor)
+    }
+
+    fn structure(&self) -> Structure<'_> {
+        Structure::Field(&self.0, &self.1)
+    }
+}
+
+/// Labels the result of `shape` as the field `name` of the enclosing node.
+pub fn field<S: Shape>(name: impl Into<SharedStr>, shape: S) -> Field<S> {
+    Field(name.into(), shape)
+}
+
+// memo
+/// Identifies a memoized shape. Zero-sized shapes, such as a unit struct for
+/// a recursive rule, are all alike, so they share their type's entries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum MemoId {
+    Type(TypeId),
+    Instance(usize),
+}
+
+static NEXT_MEMO_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Matches `S`, caching the result per stream position in the context's
+/// [`MemoTable`]. See [`memo`].
+#[derive(Debug, Clone)]
+pub struct Memo<S> {
+    id: MemoId,
+    shape: S,
+}
+
+impl<S: Shape> Shape for Memo<S> {
+    fn match_shape<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+    ) -> MatchResult<'a> {
+        let key = (self.id, stream.trees.as_ptr() as usize, stream.trees.len());
+        if let Some(cached) = context
+            .memo_table()
+            .filter(|table| table.covers(&stream))
+            .and_then(|table| table.entries.get(&key))
+        {
+            return match cached {
+                Ok((tree, consumed)) => Ok((tree.clone(), stream.advance(*consumed))),
+                Err(error) => Err(error.clone()),
+            };
+        }
+
+        let result = self.shape.match_shape(stream.clone(), context);
+        let Some(table) = context.memo_table().filter(|table| table.covers(&stream)) else {
+            return result;
+        };
+        match &result {
+            Ok((tree, rest)) => {
+                // Only a rest that is a suffix of `stream` can be rebuilt from it.
+                if let Some(consumed) = stream.trees.len().checked_sub(rest.trees.len())
+                    && std::ptr::eq(rest.trees, &stream.trees[consumed..])
+                {
+                    table.entries.insert(key, Ok((tree.clone(), consumed)));
+                }
+            }
+            Err(error) => {
+                table.entries.insert(key, Err(error.clone()));
+            }
+        }
+        result
+    }
+
+    fn adjacency(&self) -> AdjacencyConstraint {
+        self.shape.adjacency()
+    }
+
+    fn complete<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+        cursor: usize,
+    ) -> Vec<CompletionItem> {
+        self.shape.complete(stream, context, cursor)
+    }
+
+    fn structure(&self) -> Structure<'_> {
+        self.shape.structure()
+    }
+}
+
+/// Caches the results of `shape`, so that backtracking over it (e.g. in a
+/// [`choice`] whose alternatives share a prefix) reuses the first match
+/// instead of repeating it.
+///
+/// Results are kept in the context's [`MemoTable`], and only when it has one
+/// (the [`Parser`](crate::Parser) does). Clones of a memo shape share their
+/// entries, so build it once and reuse it rather than calling `memo` again.
+/// A shape should only be memoized if its result depends on nothing but the
+/// stream.
+pub fn memo<S: Shape + 'static>(shape: S) -> Memo<S> {
+    let id = if size_of::<S>() == 0 {
+        MemoId::Type(TypeId::of::<S>())
+    } else {
+        MemoId::Instance(NEXT_MEMO_ID.fetch_add(1, Ordering::Relaxed))
+    };
+    Memo { id, shape }
+}
@@ -0,0 +1,146 @@
+//! N-ary `seq` and `choice` over tuples of shapes.
+//!
+//! `seq((a, b, c))` matches like `seq(a, seq(b, c))`, but produces a single
+//! `TokenTree::Group` with one child per element instead of nested groups.
+//! `choice((a, b, c))` tries each alternative in order. Tuples of up to twelve
+//! shapes are supported.
+
+use super::{CompletionItem, MatchContext, MatchResult, Shape, Structure};
+use crate::token::{TokenStream, TokenTree};
+use std::fmt::Debug;
+
+/// A tuple of shapes.
+pub trait Shapes: Debug + Send + Sync {
+    /// The number of shapes in the tuple.
+    const LEN: usize;
+
+    /// Returns the shape at `index`, which must be less than `LEN`.
+    fn get(&self, index: usize) -> &dyn Shape;
+}
+
+macro_rules! impl_shapes {
+    ($len:literal => $($index:tt $name:ident),+) => {
+        impl<$($name: Shape),+> Shapes for ($($name,)+) {
+            const LEN: usize = $len;
+
+            fn get(&self, index: usize) -> &dyn Shape {
+                match index {
+                    $($index => &self.$index,)+
+                    _ => panic!("index {} out of range for {} shapes", index, $len),
+                }
+            }
+        }
+    };
+}
+
+impl_shapes!(1 => 0 A);
+impl_shapes!(2 => 0 A, 1 B);
+impl_shapes!(3 => 0 A, 1 B, 2 C);
+impl_shapes!(4 => 0 A, 1 B, 2 C, 3 D);
+impl_shapes!(5 => 0 A, 1 B, 2 C, 3 D, 4 E);
+impl_shapes!(6 => 0 A, 1 B, 2 C, 3 D, 4 E, 5 F);
+impl_shapes!(7 => 0 A, 1 B, 2 C, 3 D, 4 E, 5 F, 6 G);
+impl_shapes!(8 => 0 A, 1 B, 2 C, 3 D, 4 E, 5 F, 6 G, 7 H);
+impl_shapes!(9 => 0 A, 1 B, 2 C, 3 D, 4 E, 5 F, 6 G, 7 H, 8 I);
+impl_shapes!(10 => 0 A, 1 B, 2 C, 3 D, 4 E, 5 F, 6 G, 7 H, 8 I, 9 J);
+impl_shapes!(11 => 0 A, 1 B, 2 C, 3 D, 4 E, 5 F, 6 G, 7 H, 8 I, 9 J, 10 K);
+impl_shapes!(12 => 0 A, 1 B, 2 C, 3 D, 4 E, 5 F, 6 G, 7 H, 8 I, 9 J, 10 K, 11 L);
+
+fn shapes<T: Shapes>(tuple: &T) -> Vec<&dyn Shape> {
+    (0..T::LEN).map(|index| tuple.get(index)).collect()
+}
+
+/// Matches each shape of the tuple in turn.
+#[derive(Debug, Clone)]
+pub struct Seq<T>(pub T);
+
+impl<T: Shapes> Shape for Seq<T> {
+    fn match_shape<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+    ) -> MatchResult<'a> {
+        let mut results = Vec::with_capacity(T::LEN);
+        let mut current_stream = stream;
+        for index in 0..T::LEN {
+            let (res, next_stream) = self.0.get(index).match_shape(current_stream, context)?;
+            results.push(res);
+            current_stream = next_stream;
+        }
+        Ok((TokenTree::Group(results), current_stream))
+    }
+
+    fn complete<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+        cursor: usize,
+    ) -> Vec<CompletionItem> {
+        // The same walk as the nested binary `Seq::complete`.
+        let mut current_stream = stream;
+        for index in 0..T::LEN - 1 {
+            let shape = self.0.get(index);
+            match shape.match_shape(current_stream.clone(), context) {
+                Ok((_, next_stream)) => {
+                    let items = shape.complete(current_stream, context, cursor);
+                    if !items.is_empty() {
+                        return items;
+                    }
+                    current_stream = next_stream;
+                }
+                Err(_) => return shape.complete(current_stream, context, cursor),
+            }
+        }
+        self.0
+            .get(T::LEN - 1)
+            .complete(current_stream, context, cursor)
+    }
+
+    fn structure(&self) -> Structure<'_> {
+        Structure::Seq(shapes(&self.0))
+    }
+}
+
+/// Matches a sequence of shapes as a single group, e.g. `seq((a, b, c))`.
+pub fn seq<T: Shapes>(shapes: T) -> Seq<T> {
+    Seq(shapes)
+}
+
+/// Ordered choice: tries each shape of the tuple in turn.
+#[derive(Debug, Clone)]
+pub struct Choice<T>(pub T);
+
+impl<T: Shapes> Shape for Choice<T> {
+    fn match_shape<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+    ) -> MatchResult<'a> {
+        for index in 0..T::LEN - 1 {
+            if let Ok(res) = self.0.get(index).match_shape(stream.clone(), context) {
+                return Ok(res);
+            }
+        }
+        self.0.get(T::LEN - 1).match_shape(stream, context)
+    }
+
+    fn complete<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+        cursor: usize,
+    ) -> Vec<CompletionItem> {
+        (0..T::LEN)
+            .flat_map(|index| self.0.get(index).complete(stream.clone(), context, cursor))
+            .collect()
+    }
+
+    fn structure(&self) -> Structure<'_> {
+        Structure::Choice(shapes(&self.0))
+    }
+}
+
+/// Tries each shape in order, e.g. `choice((a, b, c))`.
+pub fn choice<T: Shapes>(shapes: T) -> Choice<T> {
+    Choice(shapes)
+}
@@ -0,0 +1,588 @@
+//! Shapes that produce Rust values.
+//!
+//! A [`TypedShape`] is a [`Shape`] that can also match to a value of its
+//! `Output` type, so semantic actions run while matching instead of in a
+//! second pass over the `TokenTree`. The combinators produce structured
+//! outputs:
+//!
+//! | Shape | Output |
+//! | --- | --- |
+//! | `term(..)`, `expr(..)`, [`raw`] | the matched `TokenTree` |
+//! | `seq(a, b)`, `adjacent(a, b)` | `(A, B)` |
+//! | `tuple::seq((a, b, ..))` | `(A, B, ..)` |
+//! | `choice(a, b)`, `tuple::choice((a, b, ..))` | `A`, which every alternative must share |
+//! | `opt(a)` | `Option<A>` |
+//! | `rep(a)` | `Vec<A>` |
+//! | `enter(d, a)`, `node(k, a)`, `field(n, a)` | `A` |
+//! | `recover(a, t)` | `Option<A>`, `None` when it recovered |
+//! | `empty()`, `end()` | `()` |
+//! | `ast::<T>()` | `T` |
+//!
+//! [`TypedShape::map`], [`TypedShape::try_map`] and [`TypedShape::and_then`]
+//! turn those into your own values:
+//!
+//! ```rust
+//! use mcparse::atom::AtomKind;
+//! use mcparse::shape::typed::TypedShape;
+//! use mcparse::shape::{NoOpMatchContext, opt, seq, term};
+//! use mcparse::token::TokenStream;
+//! use mcparse::{define_language, lexer::lex};
+//!
+//! define_language! {
+//!     struct Numbers;
+//!     atoms = [
+//!         atom Whitespace = regex r"\s+",
+//!         atom Operator = "-",
+//!         atom Number = regex r"[0-9]+",
+//!     ];
+//!     delimiters = [];
+//! }
+//!
+//! let number = term(AtomKind::Number).try_map(|tree| tree.text().parse::<i64>());
+//! let signed = seq(opt(term("-")), number).map(|(minus, n)| if minus.is_some() { -n } else { n });
+//!
+//! let trees = lex("- 42", &Numbers::new());
+//! let (value, _) = signed
+//!     .match_typed(TokenStream::new(&trees), &mut NoOpMatchContext)
+//!     .unwrap();
+//! assert_eq!(value, -42);
+//!
+//! let trees = lex("99999999999999999999", &Numbers::new());
+//! let error = signed
+//!     .match_typed(TokenStream::new(&trees), &mut NoOpMatchContext)
+//!     .unwrap_err();
+//! assert_eq!(error.span, (0, 20).into());
+//! ```
+//!
+//! Every typed shape is still a `Shape`, so completion, recovery and lints see
+//! the same grammar. Plain matching with `match_shape` only checks syntax:
+//! semantic actions run, and `try_map`/`and_then` can fail, only in
+//! `match_typed`. Their errors are [semantic](ParseError::semantic), and
+//! `choice`, `opt` and `rep` pass them on instead of trying something else, so
+//! both kinds of matching take the same alternatives. `recover` still recovers
+//! from them. An action that should let a choice move on has to be part of the
+//! grammar, e.g. a custom `Shape`, rather than a `try_map`.
+
+use super::{
+    AdjacencyConstraint, Adjacent, Choice, CompletionItem, Empty, End, Enter, Expr, Field,
+    MatchContext, MatchResult, Matcher, Node, Opt, ParseError, Recover, Rep, Seq, Shape, Structure,
+    Term, reject_trivia, skip_trivia, tuple,
+};
+use crate::ast::{Ast, AstShape};
+use crate::lexer::tree_location;
+use crate::token::{TokenStream, TokenTree};
+use miette::SourceSpan;
+use std::fmt::{self, Debug, Display};
+
+/// A shape that matches to a value of type `Output`.
+pub trait TypedShape: Shape {
+    type Output;
+
+    /// Matches like [`Shape::match_shape`], producing an `Output`.
+    fn match_typed<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+    ) -> MatchResult<'a, Self::Output>;
+
+    /// Converts the output with `f`.
+    fn map<T, F>(self, f: F) -> Map<Self, F>
+    where
+        Self: Sized,
+        F: Fn(Self::Output) -> T + Send + Sync,
+    {
+        Map(self, f)
+    }
+
+    /// Converts the output with `f`, failing the match if `f` fails. The
+    /// error is [semantic](ParseError::semantic) and reported at the span of
+    /// the matched input.
+    fn try_map<T, E, F>(self, f: F) -> TryMap<Self, F>
+    where
+        Self: Sized,
+        E: Display,
+        F: Fn(Self::Output) -> Result<T, E> + Send + Sync,
+    {
+        TryMap(self, f)
+    }
+
+    /// Converts the output with `f`, which reports its own `ParseError`s. They
+    /// are marked [semantic](ParseError::semantic).
+    fn and_then<T, F>(self, f: F) -> AndThen<Self, F>
+    where
+        Self: Sized,
+        F: Fn(Self::Output) -> Result<T, ParseError> + Send + Sync,
+    {
+        AndThen(self, f)
+    }
+}
+
+/// The span of the non-trivia input between `stream` and `rest`, or an empty
+/// span at the next token if nothing was consumed.
+fn consumed_span(
+    stream: &TokenStream,
+    rest: &TokenStream,
+    context: &dyn MatchContext,
+) -> SourceSpan {
+    let consumed = &stream.trees[..stream.trees.len() - rest.trees.len()];
+    let significant = |tree: &&TokenTree| match tree {
+        TokenTree::Token(token) => !context.is_trivia(&token.kind),
+        _ => true,
+    };
+    let first = consumed.iter().filter(significant).find_map(tree_location);
+    let last = consumed
+        .iter()
+        .rev()
+        .filter(significant)
+        .find_map(tree_location);
+    match (first, last) {
+        (Some(first), Some(last)) => {
+            let end = last.span.offset() + last.span.len();
+            SourceSpan::new(first.span.offset().into(), end - first.span.offset())
+        }
+        _ => {
+            let offset = skip_trivia(rest.clone(), context)
+                .trees
+                .first()
+                .and_then(tree_location)
+                .map_or(0, |location| location.span.offset());
+            SourceSpan::new(offset.into(), 0)
+        }
+    }
+}
+
+macro_rules! forward_shape {
+    () => {
+        fn match_shape<'a>(
+            &self,
+            stream: TokenStream<'a>,
+            context: &mut dyn MatchContext,
+        ) -> MatchResult<'a> {
+            self.0.match_shape(stream, context)
+        }
+
+        fn adjacency(&self) -> AdjacencyConstraint {
+            self.0.adjacency()
+        }
+
+        fn complete<'a>(
+            &self,
+            stream: TokenStream<'a>,
+            context: &mut dyn MatchContext,
+            cursor: usize,
+        ) -> Vec<CompletionItem> {
+            self.0.complete(stream, context, cursor)
+        }
+
+        fn structure(&self) -> Structure<'_> {
+            self.0.structure()
+        }
+    };
+}
+
+// map
+/// Converts the output of `S` with a function. See [`TypedShape::map`].
+#[derive(Clone)]
+pub struct Map<S, F>(pub S, pub F);
+
+impl<S: Debug, F> Debug for Map<S, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Map").field(&self.0).finish_non_exhaustive()
+    }
+}
+
+impl<S: TypedShape, T, F: Fn(S::Output) -> T + Send + Sync> Shape for Map<S, F> {
+    forward_shape!();
+}
+
+impl<S: TypedShape, T, F: Fn(S::Output) -> T + Send + Sync> TypedShape for Map<S, F> {
+    type Output = T;
+
+    fn match_typed<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+    ) -> MatchResult<'a, T> {
+        let (value, rest) = self.0.match_typed(stream, context)?;
+        Ok(((self.1)(value), rest))
+    }
+}
+
+// try_map
+/// Converts the output of `S` with a fallible function. See
+/// [`TypedShape::try_map`].
+#[derive(Clone)]
+pub struct TryMap<S, F>(pub S, pub F);
+
+impl<S: Debug, F> Debug for TryMap<S, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("TryMap")
+            .field(&self.0)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S, T, E, F> Shape for TryMap<S, F>
+where
+    S: TypedShape,
+    E: Display,
+    F: Fn(S::Output) -> Result<T, E> + Send + Sync,
+{
+    forward_shape!();
+}
+
+impl<S, T, E, F> TypedShape for TryMap<S, F>
+where
+    S: TypedShape,
+    E: Display,
+    F: Fn(S::Output) -> Result<T, E> + Send + Sync,
+{
+    type Output = T;
+
+    fn match_typed<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+    ) -> MatchResult<'a, T> {
+        let (value, rest) = self.0.match_typed(stream.clone(), context)?;
+        match (self.1)(value) {
+            Ok(value) => Ok((value, rest)),
+            Err(error) => Err(ParseError::new(
+                consumed_span(&stream, &rest, context),
+                error.to_string(),
+            )
+            .into_semantic()),
+        }
+    }
+}
+
+// and_then
+/// Converts the output of `S` with a function that reports its own errors.
+/// See [`TypedShape::and_then`].
+#[derive(Clone)]
+pub struct AndThen<S, F>(pub S, pub F);
+
+impl<S: Debug, F> Debug for AndThen<S, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("AndThen")
+            .field(&self.0)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S, T, F> Shape for AndThen<S, F>
+where
+    S: TypedShape,
+    F: Fn(S::Output) -> Result<T, ParseError> + Send + Sync,
+{
+    forward_shape!();
+}
+
+impl<S, T, F> TypedShape for AndThen<S, F>
+where
+    S: TypedShape,
+    F: Fn(S::Output) -> Result<T, ParseError> + Send + Sync,
+{
+    type Output = T;
+
+    fn match_typed<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+    ) -> MatchResult<'a, T> {
+        let (value, rest) = self.0.match_typed(stream, context)?;
+        let value = (self.1)(value).map_err(ParseError::into_semantic)?;
+        Ok((value, rest))
+    }
+}
+
+// raw
+/// Uses any shape as a typed shape whose output is the matched tree.
+#[derive(Debug, Clone)]
+pub struct Raw<S>(pub S);
+
+impl<S: Shape> Shape for Raw<S> {
+    forward_shape!();
+}
+
+impl<S: Shape> TypedShape for Raw<S> {
+    type Output = TokenTree;
+
+    fn match_typed<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+    ) -> MatchResult<'a> {
+        self.0.match_shape(stream, context)
+    }
+}
+
+/// Wraps a shape that is not typed, like a custom `Shape`, so that it can be
+/// combined with typed shapes. Its output is the matched tree.
+pub fn raw<S: Shape>(shape: S) -> Raw<S> {
+    Raw(shape)
+}
+
+// Outputs of the built-in shapes.
+
+impl<M: Matcher> TypedShape for Term<M> {
+    type Output = TokenTree;
+
+    fn match_typed<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+    ) -> MatchResult<'a> {
+        self.match_shape(stream, context)
+    }
+}
+
+impl TypedShape for Expr {
+    type Output = TokenTree;
+
+    fn match_typed<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+    ) -> MatchResult<'a> {
+        self.match_shape(stream, context)
+    }
+}
+
+impl TypedShape for Box<dyn Shape> {
+    type Output = TokenTree;
+
+    fn match_typed<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+    ) -> MatchResult<'a> {
+        self.match_shape(stream, context)
+    }
+}
+
+impl TypedShape for Empty {
+    type Output = ();
+
+    fn match_typed<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        _context: &mut dyn MatchContext,
+    ) -> MatchResult<'a, ()> {
+        Ok(((), stream))
+    }
+}
+
+impl TypedShape for End {
+    type Output = ();
+
+    fn match_typed<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+    ) -> MatchResult<'a, ()> {
+        let (_, rest) = self.match_shape(stream, context)?;
+        Ok(((), rest))
+    }
+}
+
+impl<A: TypedShape, B: TypedShape> TypedShape for Seq<A, B> {
+    type Output = (A::Output, B::Output);
+
+    fn match_typed<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+    ) -> MatchResult<'a, Self::Output> {
+        let (a, rest) = self.0.match_typed(stream, context)?;
+        let (b, rest) = self.1.match_typed(rest, context)?;
+        Ok(((a, b), rest))
+    }
+}
+
+impl<A: TypedShape, B: TypedShape> TypedShape for Adjacent<A, B> {
+    type Output = (A::Output, B::Output);
+
+    fn match_typed<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+    ) -> MatchResult<'a, Self::Output> {
+        let (a, rest) = self.0.match_typed(stream, context)?;
+        reject_trivia(&rest, context)?;
+        let (b, rest) = self.1.match_typed(rest, context)?;
+        Ok(((a, b), rest))
+    }
+}
+
+impl<A: TypedShape, B: TypedShape<Output = A::Output>> TypedShape for Choice<A, B> {
+    type Output = A::Output;
+
+    fn match_typed<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+    ) -> MatchResult<'a, Self::Output> {
+        match self.0.match_typed(stream.clone(), context) {
+            Err(error) if !error.semantic => self.1.match_typed(stream, context),
+            result => result,
+        }
+    }
+}
+
+impl<A: TypedShape> TypedShape for Opt<A> {
+    type Output = Option<A::Output>;
+
+    fn match_typed<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+    ) -> MatchResult<'a, Self::Output> {
+        match self.0.match_typed(stream.clone(), context) {
+            Ok((value, rest)) => Ok((Some(value), rest)),
+            Err(error) if error.semantic => Err(error),
+            Err(_) => Ok((None, stream)),
+        }
+    }
+}
+
+impl<A: TypedShape> TypedShape for Rep<A> {
+    type Output = Vec<A::Output>;
+
+    fn match_typed<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+    ) -> MatchResult<'a, Self::Output> {
+        let mut semantic = None;
+        let (items, rest) = self.repeat(stream, context, |a, stream, context| {
+            a.match_typed(stream, context)
+                .inspect_err(|error| semantic = error.semantic.then(|| error.clone()))
+        });
+        match semantic {
+            Some(error) => Err(error),
+            None => Ok((items, rest)),
+        }
+    }
+}
+
+impl<S: TypedShape> TypedShape for Enter<S> {
+    type Output = S::Output;
+
+    fn match_typed<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+    ) -> MatchResult<'a, Self::Output> {
+        self.enter_with(stream, context, |inner, stream, context| {
+            inner.match_typed(stream, context)
+        })
+    }
+}
+
+impl<S: TypedShape, M: Matcher> TypedShape for Recover<S, M> {
+    type Output = Option<S::Output>;
+
+    fn match_typed<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+    ) -> MatchResult<'a, Self::Output> {
+        let (res, rest) = self.recover_with(stream, context, |shape, stream, context| {
+            shape.match_typed(stream, context)
+        });
+        Ok((res.ok(), rest))
+    }
+}
+
+impl<S: TypedShape> TypedShape for Node<S> {
+    type Output = S::Output;
+
+    fn match_typed<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+    ) -> MatchResult<'a, Self::Output> {
+        self.1.match_typed(stream, context)
+    }
+}
+
+impl<S: TypedShape> TypedShape for Field<S> {
+    type Output = S::Output;
+
+    fn match_typed<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+    ) -> MatchResult<'a, Self::Output> {
+        self.1.match_typed(stream, context)
+    }
+}
+
+impl<T: Ast> TypedShape for AstShape<T> {
+    type Output = T;
+
+    fn match_typed<'a>(
+        &self,
+        stream: TokenStream<'a>,
+        context: &mut dyn MatchContext,
+    ) -> MatchResult<'a, T> {
+        T::parse(stream, context)
+    }
+}
+
+macro_rules! impl_typed_tuples {
+    ($($first:ident $(, $name:ident)*;)+) => {$(
+        #[allow(non_snake_case)]
+        impl<$first: TypedShape $(, $name: TypedShape)*> TypedShape for tuple::Seq<($first, $($name,)*)> {
+            type Output = ($first::Output, $($name::Output,)*);
+
+            fn match_typed<'a>(
+                &self,
+                stream: TokenStream<'a>,
+                context: &mut dyn MatchContext,
+            ) -> MatchResult<'a, Self::Output> {
+                let ($first, $($name,)*) = &self.0;
+                let ($first, rest) = $first.match_typed(stream, context)?;
+                $(let ($name, rest) = $name.match_typed(rest, context)?;)*
+                Ok((($first, $($name,)*), rest))
+            }
+        }
+
+        #[allow(non_snake_case)]
+        impl<$first: TypedShape $(, $name: TypedShape<Output = $first::Output>)*> TypedShape
+            for tuple::Choice<($first, $($name,)*)>
+        {
+            type Output = $first::Output;
+
+            fn match_typed<'a>(
+                &self,
+                stream: TokenStream<'a>,
+                context: &mut dyn MatchContext,
+            ) -> MatchResult<'a, Self::Output> {
+                let ($first, $($name,)*) = &self.0;
+                let result = $first.match_typed(stream.clone(), context);
+                $(let result = match result {
+                    Err(error) if !error.semantic => $name.match_typed(stream.clone(), context),
+                    result => result,
+                };)*
+                result
+            }
+        }
+    )+};
+}
+
+impl_typed_tuples! {
+    A;
+    A, B;
+    A, B, C;
+    A, B, C, D;
+    A, B, C, D, E;
+    A, B, C, D, E, F;
+    A, B, C, D, E, F, G;
+    A, B, C, D, E, F, G, H;
+    A, B, C, D, E, F, G, H, I;
+    A, B, C, D, E, F, G, H, I, J;
+    A, B, C, D, E, F, G, H, I, J, K;
+    A, B, C, D, E, F, G, H, I, J, K, L;
+}
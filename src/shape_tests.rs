@@ -1,20 +1,52 @@
 #[cfg(test)]
 mod tests {
     use crate::atom::AtomKind;
-    use crate::shape::{NoOpMatchContext, Shape, choice, seq, term};
+    use crate::incremental::GreenTree;
+    use crate::language::Delimiter;
+    use crate::lexer::lex;
+    use crate::mock::MockLanguage;
+    use crate::shape::typed::{TypedShape, raw};
+    use crate::shape::{
+        MatchContext, MatchResult, MemoTable, NoOpMatchContext, ParseError, Precedence, Shape,
+        adjacent, choice, empty, end, enter, field, joined, memo, node, opt, recover, rep, seq,
+        term, tuple,
+    };
     use crate::token::{SourceLocation, Token, TokenStream, TokenTree};
-    use miette::SourceSpan;
+    use std::cell::Cell;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
 
     fn mock_token(text: &str, offset: usize) -> TokenTree {
-        TokenTree::Token(Token {
-            kind: AtomKind::Identifier,
-            text: text.to_string(),
-            location: SourceLocation {
-                span: SourceSpan::new(offset.into(), text.len().into()),
-            },
-            atom_index: None,
-            binding: None,
-        })
+        TokenTree::Token(Token::new(AtomKind::Identifier, text, offset))
+    }
+
+    /// Lays out `parts` one after another, as tokens of the given kinds.
+    fn tokens(parts: &[(&str, AtomKind)]) -> Vec<TokenTree> {
+        let mut offset = 0;
+        parts
+            .iter()
+            .map(|(text, kind)| {
+                let token = Token::new(kind.clone(), text, offset);
+                offset += text.len();
+                TokenTree::Token(token)
+            })
+            .collect()
+    }
+
+    /// A context in which comments are significant.
+    struct KeepComments;
+    impl MatchContext for KeepComments {
+        fn parse_expression<'a>(
+            &mut self,
+            stream: TokenStream<'a>,
+            precedence: Precedence,
+        ) -> MatchResult<'a> {
+            NoOpMatchContext.parse_expression(stream, precedence)
+        }
+
+        fn is_trivia(&self, kind: &AtomKind) -> bool {
+            *kind == AtomKind::Whitespace
+        }
     }
 
     #[test]
@@ -72,4 +104,488 @@ mod tests {
         assert!(labels.contains(&"let".to_string()));
         assert!(labels.contains(&"left".to_string()));
     }
+
+    #[test]
+    fn test_shapes_skip_comments() {
+        let trees = tokens(&[
+            ("/* x */", AtomKind::Comment),
+            ("a", AtomKind::Identifier),
+            (" ", AtomKind::Whitespace),
+            ("// y\n", AtomKind::Comment),
+            ("b", AtomKind::Identifier),
+            ("// z", AtomKind::Comment),
+        ]);
+        let shape = seq(term("a"), seq(term("b"), end()));
+        assert!(
+            shape
+                .match_shape(TokenStream::new(&trees), &mut NoOpMatchContext)
+                .is_ok()
+        );
+
+        let group = vec![TokenTree::Delimited(
+            Delimiter::new("paren", "(", ")"),
+            trees.clone(),
+            SourceLocation::new(0, 20),
+            true,
+        )];
+        let shape = seq(
+            enter(Delimiter::new("paren", "(", ")"), seq(term("a"), term("b"))),
+            end(),
+        );
+        assert!(
+            shape
+                .match_shape(TokenStream::new(&group), &mut NoOpMatchContext)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_adjacent_rejects_comment() {
+        let trees = tokens(&[
+            ("a", AtomKind::Identifier),
+            ("/**/", AtomKind::Comment),
+            ("b", AtomKind::Identifier),
+        ]);
+        let err = adjacent(term("a"), term("b"))
+            .match_shape(TokenStream::new(&trees), &mut NoOpMatchContext)
+            .unwrap_err();
+        assert_eq!(err.message, "Unexpected comment");
+    }
+
+    #[test]
+    fn test_adjacent_rejects_attached_trivia() {
+        let paren = Delimiter::new("paren", "(", ")");
+        let lang = MockLanguage::new()
+            .with_symbol(".")
+            .with_delimiter(paren.clone())
+            .with_trivia_attachment();
+        let matches = |shape: &dyn Shape, input: &str| {
+            let trees = lex(input, &lang);
+            let rest = shape
+                .match_shape(TokenStream::new(&trees), &mut NoOpMatchContext)
+                .map(|(_, rest)| rest.trees.len());
+            rest == Ok(0)
+        };
+
+        // The space trails `a`.
+        assert!(matches(&adjacent(term("a"), term(".")), "a."));
+        assert!(!matches(&adjacent(term("a"), term(".")), "a ."));
+        assert!(matches(&joined(term(".")), ".."));
+        assert!(!matches(&joined(term(".")), ". ."));
+        // The space leads `.`, since groups don't take trivia.
+        assert!(matches(&adjacent(term(paren.clone()), term(".")), "(a)."));
+        assert!(!matches(&adjacent(term(paren), term(".")), "(a) ."));
+    }
+
+    #[test]
+    fn test_context_chooses_trivia() {
+        let trees = tokens(&[
+            (" ", AtomKind::Whitespace),
+            ("/// doc", AtomKind::Comment),
+            ("a", AtomKind::Identifier),
+        ]);
+        let stream = TokenStream::new(&trees);
+
+        assert!(
+            term("a")
+                .match_shape(stream.clone(), &mut KeepComments)
+                .is_err()
+        );
+        let (doc, rest) = term(AtomKind::Comment)
+            .match_shape(stream, &mut KeepComments)
+            .unwrap();
+        assert!(matches!(doc, TokenTree::Token(t) if t.text == "/// doc"));
+        assert!(term("a").match_shape(rest, &mut KeepComments).is_ok());
+    }
+
+    #[test]
+    fn test_tuple_seq_flattens() {
+        let trees = tokens(&[
+            ("a", AtomKind::Identifier),
+            (" ", AtomKind::Whitespace),
+            ("b", AtomKind::Identifier),
+            (" ", AtomKind::Whitespace),
+            ("c", AtomKind::Identifier),
+        ]);
+        let shape = tuple::seq((term("a"), term("b"), term("c")));
+        let (tree, rest) = shape
+            .match_shape(TokenStream::new(&trees), &mut NoOpMatchContext)
+            .unwrap();
+        let TokenTree::Group(children) = tree else {
+            panic!("expected a group, got {:?}", tree);
+        };
+        let texts: Vec<_> = children
+            .iter()
+            .map(|child| match child {
+                TokenTree::Token(t) => t.text.as_str(),
+                other => panic!("expected a token, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(texts, ["a", "b", "c"]);
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn test_tuple_choice_tries_in_order() {
+        let trees = tokens(&[("c", AtomKind::Identifier)]);
+        let shape = tuple::choice((term("a"), term("b"), term("c"), term("c")));
+        let (tree, _) = shape
+            .match_shape(TokenStream::new(&trees), &mut NoOpMatchContext)
+            .unwrap();
+        assert!(matches!(tree, TokenTree::Token(t) if t.text == "c"));
+
+        let trees = tokens(&[("d", AtomKind::Identifier)]);
+        assert!(
+            shape
+                .match_shape(TokenStream::new(&trees), &mut NoOpMatchContext)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_tuple_completion_matches_nested() {
+        let nested_seq = seq(term("function"), seq(term("name"), term("body")));
+        let tuple_seq = tuple::seq((term("function"), term("name"), term("body")));
+        let nested_choice = choice(term("let"), choice(term("left"), term("lemma")));
+        let tuple_choice = tuple::choice((term("let"), term("left"), term("lemma")));
+
+        let inputs = [
+            (vec![mock_token("fun", 0)], 1),
+            (vec![mock_token("function", 0), mock_token("na", 9)], 10),
+            (
+                vec![
+                    mock_token("function", 0),
+                    mock_token("name", 9),
+                    mock_token("bo", 14),
+                ],
+                15,
+            ),
+            (vec![mock_token("le", 0)], 1),
+        ];
+        for (trees, cursor) in &inputs {
+            let stream = TokenStream::new(trees);
+            let mut ctx = NoOpMatchContext;
+            assert_eq!(
+                tuple_seq.complete(stream.clone(), &mut ctx, *cursor),
+                nested_seq.complete(stream.clone(), &mut ctx, *cursor)
+            );
+            assert_eq!(
+                tuple_choice.complete(stream.clone(), &mut ctx, *cursor),
+                nested_choice.complete(stream, &mut ctx, *cursor)
+            );
+        }
+    }
+
+    #[test]
+    fn test_node_kind_span_and_fields() {
+        let trees = tokens(&[
+            (" ", AtomKind::Whitespace),
+            ("let", AtomKind::Identifier),
+            (" ", AtomKind::Whitespace),
+            ("x", AtomKind::Identifier),
+            (" ", AtomKind::Whitespace),
+            ("=", AtomKind::Operator),
+            (" ", AtomKind::Whitespace),
+            ("1", AtomKind::Number),
+            (" ", AtomKind::Whitespace),
+        ]);
+        let shape = node(
+            "let",
+            tuple::seq((
+                term("let"),
+                field("name", term(AtomKind::Identifier)),
+                term("="),
+                field("value", term(AtomKind::Number)),
+            )),
+        );
+        let (tree, _) = shape
+            .match_shape(TokenStream::new(&trees), &mut NoOpMatchContext)
+            .unwrap();
+
+        let TokenTree::Node(node) = &tree else {
+            panic!("expected a node, got {:?}", tree);
+        };
+        assert_eq!(node.kind, "let");
+        assert_eq!(node.children.len(), 4);
+        assert_eq!(node.location.span, (1, 9).into());
+        assert_eq!(node.field("name").unwrap().text(), "x");
+        assert_eq!(node.field("value").unwrap().text(), "1");
+        // Skipped trivia is not part of the tree, as with groups.
+        assert_eq!(tree.text(), "letx=1");
+        assert_eq!(GreenTree::from_token_tree(&tree).text(), tree.text());
+    }
+
+    #[test]
+    fn test_empty_node_sits_at_next_token() {
+        let trees = tokens(&[(" ", AtomKind::Whitespace), ("a", AtomKind::Identifier)]);
+        let (tree, rest) = node("nothing", empty())
+            .match_shape(TokenStream::new(&trees), &mut NoOpMatchContext)
+            .unwrap();
+        let TokenTree::Node(node) = tree else {
+            panic!("expected a node");
+        };
+        assert!(node.children.is_empty());
+        assert_eq!(node.location.span, (1, 0).into());
+        assert_eq!(rest.trees.len(), 2);
+    }
+
+    fn number() -> impl TypedShape<Output = i64> + Clone {
+        term(AtomKind::Number).try_map(|tree| tree.text().parse::<i64>())
+    }
+
+    #[test]
+    fn test_typed_outputs() {
+        let list = Delimiter::new("list", "[", "]");
+        let trees = vec![
+            TokenTree::Delimited(
+                list.clone(),
+                tokens(&[
+                    ("1", AtomKind::Number),
+                    (" ", AtomKind::Whitespace),
+                    ("x", AtomKind::Identifier),
+                    (" ", AtomKind::Whitespace),
+                    ("3", AtomKind::Number),
+                ]),
+                SourceLocation::new(0, 7),
+                true,
+            ),
+            mock_token("done", 7),
+        ];
+        let item = choice(
+            number(),
+            term(AtomKind::Identifier).map(|tree| tree.text().len() as i64 * 10),
+        );
+        let shape = tuple::seq((enter(list, rep(item)), opt(term(",")), raw(term("done"))));
+
+        let ((items, comma, done), rest) = shape
+            .match_typed(TokenStream::new(&trees), &mut NoOpMatchContext)
+            .unwrap();
+        assert_eq!(items, [1, 10, 3]);
+        assert!(comma.is_none());
+        assert_eq!(done.text(), "done");
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn test_typed_errors_and_recovery() {
+        let trees = tokens(&[
+            ("7", AtomKind::Number),
+            (";", AtomKind::Operator),
+            (" ", AtomKind::Whitespace),
+            ("99999999999999999999", AtomKind::Number),
+            (";", AtomKind::Operator),
+            ("4", AtomKind::Number),
+            (";", AtomKind::Operator),
+        ]);
+
+        let error = tuple::seq((number(), term(";"), number()))
+            .match_typed(TokenStream::new(&trees), &mut NoOpMatchContext)
+            .unwrap_err();
+        assert_eq!(error.span, (3, 20).into());
+        assert_eq!(error.message, "number too large to fit in target type");
+
+        let odd = number().and_then(|n| {
+            if n % 2 == 1 {
+                Ok(n)
+            } else {
+                Err(ParseError::new((0, 0).into(), format!("{} is even", n)))
+            }
+        });
+        let statements = rep(seq(recover(odd.clone(), ";"), term(";")));
+        let (values, rest) = statements
+            .match_typed(TokenStream::new(&trees), &mut NoOpMatchContext)
+            .unwrap();
+        let values: Vec<_> = values.into_iter().map(|(value, _)| value).collect();
+        assert_eq!(values, [Some(7), None, None]);
+        assert!(rest.is_empty());
+
+        // Plain matching checks syntax only.
+        assert!(
+            odd.match_shape(TokenStream::new(&trees[5..]), &mut NoOpMatchContext)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_typed_choice_keeps_semantic_errors() {
+        let trees = tokens(&[
+            ("99999999999999999999", AtomKind::Number),
+            (" ", AtomKind::Whitespace),
+            ("1", AtomKind::Number),
+        ]);
+        let stream = || TokenStream::new(&trees);
+        let fallback = term(AtomKind::Number).map(|_| 0);
+
+        // Plain matching picks the first alternative, so typed matching must
+        // report its error rather than fall back to the second.
+        let shape = choice(number(), fallback.clone());
+        let (tree, _) = shape.match_shape(stream(), &mut NoOpMatchContext).unwrap();
+        assert_eq!(tree.text(), "99999999999999999999");
+        let error = shape
+            .match_typed(stream(), &mut NoOpMatchContext)
+            .unwrap_err();
+        assert!(error.semantic);
+        assert_eq!(error.span, (0, 20).into());
+
+        let error = tuple::choice((number(), fallback.clone(), fallback))
+            .match_typed(stream(), &mut NoOpMatchContext)
+            .unwrap_err();
+        assert!(error.semantic);
+
+        let error = seq(opt(number()), number())
+            .match_typed(stream(), &mut NoOpMatchContext)
+            .unwrap_err();
+        assert!(error.semantic);
+
+        let error = rep(number())
+            .match_typed(stream(), &mut NoOpMatchContext)
+            .unwrap_err();
+        assert_eq!(error.span, (0, 20).into());
+
+        // Syntax errors still backtrack.
+        let (value, _) = choice(term("-").map(|_| -1), number())
+            .match_typed(TokenStream::new(&trees[2..]), &mut NoOpMatchContext)
+            .unwrap();
+        assert_eq!(value, 1);
+    }
+
+    #[test]
+    fn test_typed_adjacent_rejects_attached_trivia() {
+        let lang = MockLanguage::new()
+            .with_symbol(".")
+            .with_trivia_attachment();
+        let shape = adjacent(term("a"), term("."));
+
+        let trees = lex("a.", &lang);
+        assert!(
+            shape
+                .match_typed(TokenStream::new(&trees), &mut NoOpMatchContext)
+                .is_ok()
+        );
+        let trees = lex("a .", &lang);
+        let err = shape
+            .match_typed(TokenStream::new(&trees), &mut NoOpMatchContext)
+            .unwrap_err();
+        assert_eq!(err.message, "Unexpected whitespace");
+    }
+
+    #[test]
+    fn test_typed_shapes_complete_like_their_syntax() {
+        let trees = vec![mock_token("function", 0), mock_token("na", 9)];
+        let plain = seq(term("function"), term("name"));
+        let typed = seq(term("function"), term("name")).map(|(_, name)| name.text());
+        let mut ctx = NoOpMatchContext;
+        assert_eq!(
+            typed.complete(TokenStream::new(&trees), &mut ctx, 10),
+            plain.complete(TokenStream::new(&trees), &mut ctx, 10)
+        );
+    }
+
+    /// A context that keeps a memo table.
+    #[derive(Default)]
+    struct Memoizing(MemoTable);
+    impl MatchContext for Memoizing {
+        fn parse_expression<'a>(
+            &mut self,
+            stream: TokenStream<'a>,
+            precedence: Precedence,
+        ) -> MatchResult<'a> {
+            NoOpMatchContext.parse_expression(stream, precedence)
+        }
+
+        fn memo_table(&mut self) -> Option<&mut MemoTable> {
+            Some(&mut self.0)
+        }
+    }
+
+    /// Matches `.0`, counting its attempts in `.1`.
+    #[derive(Debug, Clone)]
+    struct Counted(&'static str, Arc<AtomicUsize>);
+    impl Shape for Counted {
+        fn match_shape<'a>(
+            &self,
+            stream: TokenStream<'a>,
+            context: &mut dyn MatchContext,
+        ) -> MatchResult<'a> {
+            self.1.fetch_add(1, Ordering::Relaxed);
+            term(self.0).match_shape(stream, context)
+        }
+    }
+
+    #[test]
+    fn test_memo_reuses_results_when_backtracking() {
+        let trees = vec![mock_token("a", 0), mock_token("c", 1)];
+        let count = Arc::new(AtomicUsize::new(0));
+        let a = memo(Counted("a", count.clone()));
+        let shape = tuple::choice((
+            seq(a.clone(), term("b")),
+            seq(a.clone(), term("c")),
+            seq(a, term("d")),
+        ));
+
+        let (tree, rest) = shape
+            .match_shape(TokenStream::new(&trees), &mut NoOpMatchContext)
+            .unwrap();
+        assert_eq!(count.load(Ordering::Relaxed), 2);
+
+        count.store(0, Ordering::Relaxed);
+        let mut ctx = Memoizing::default();
+        let (memoized, memoized_rest) = shape
+            .match_shape(TokenStream::new(&trees), &mut ctx)
+            .unwrap();
+        assert_eq!(count.load(Ordering::Relaxed), 1);
+        assert_eq!(memoized.to_sexp(), tree.to_sexp());
+        assert_eq!(memoized_rest.trees.len(), rest.trees.len());
+        assert_eq!(ctx.0.len(), 1);
+    }
+
+    #[test]
+    fn test_memo_caches_failures_per_position() {
+        let trees = vec![mock_token("x", 0), mock_token("x", 1)];
+        let count = Arc::new(AtomicUsize::new(0));
+        let a = memo(Counted("a", count.clone()));
+        let mut ctx = Memoizing::default();
+
+        let stream = TokenStream::new(&trees);
+        for _ in 0..2 {
+            assert!(a.match_shape(stream.clone(), &mut ctx).is_err());
+            assert!(a.match_shape(stream.advance(1), &mut ctx).is_err());
+        }
+        assert_eq!(count.load(Ordering::Relaxed), 2);
+
+        // Another memo of an equal shape keeps its own entries.
+        let b = memo(Counted("a", count.clone()));
+        assert!(b.match_shape(stream, &mut ctx).is_err());
+        assert_eq!(count.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn test_memo_of_unit_struct_shares_entries() {
+        thread_local! {
+            static CALLS: Cell<usize> = const { Cell::new(0) };
+        }
+
+        #[derive(Debug, Clone, Copy)]
+        struct Rule;
+        impl Shape for Rule {
+            fn match_shape<'a>(
+                &self,
+                stream: TokenStream<'a>,
+                context: &mut dyn MatchContext,
+            ) -> MatchResult<'a> {
+                CALLS.with(|calls| calls.set(calls.get() + 1));
+                term("a").match_shape(stream, context)
+            }
+        }
+
+        let trees = vec![mock_token("a", 0)];
+        let mut ctx = Memoizing::default();
+        memo(Rule)
+            .match_shape(TokenStream::new(&trees), &mut ctx)
+            .unwrap();
+        memo(Rule)
+            .match_shape(TokenStream::new(&trees), &mut ctx)
+            .unwrap();
+        assert_eq!(CALLS.with(Cell::get), 1);
+    }
 }
@@ -0,0 +1,230 @@
+//! Multiple source files with non-overlapping spans.
+//!
+//! A [`SourceMap`] lays its files out one after another in a single offset space,
+//! so a [`SourceSpan`] identifies both a file and a position within it. Lexing a
+//! file through the map (see [`crate::lexer::lex_file`]) produces spans in that
+//! space, and the map implements miette's [`SourceCode`], so any diagnostic from
+//! any file renders with that file's name and contents.
+
+use crate::token::Cursor;
+use miette::{MietteError, MietteSpanContents, SourceCode, SourceSpan, SpanContents};
+
+/// Identifies a file in a [`SourceMap`].
+///
+/// The default id is the first file of a map, and is also used for input that
+/// was lexed on its own with [`crate::lexer::lex`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct FileId(pub usize);
+
+/// A named source file in a [`SourceMap`].
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    pub name: String,
+    pub text: String,
+    /// The offset of the file's first byte in the map.
+    pub base: usize,
+}
+
+impl SourceFile {
+    /// The span covering the whole file, in the map's offsets.
+    pub fn span(&self) -> SourceSpan {
+        SourceSpan::new(self.base.into(), self.text.len())
+    }
+
+    /// Whether `offset` falls within the file, including the position at its end.
+    pub fn contains(&self, offset: usize) -> bool {
+        offset >= self.base && offset <= self.base + self.text.len()
+    }
+}
+
+/// A set of source files sharing one offset space.
+///
+/// ```rust
+/// use mcparse::source_map::SourceMap;
+///
+/// let mut files = SourceMap::new();
+/// let main = files.add("main.conf", "include \"lib.conf\"");
+/// let lib = files.add("lib.conf", "x = 1");
+///
+/// // Each file starts after the previous one, so spans can't collide.
+/// assert_eq!(files.file(lib).base, files.file(main).text.len() + 1);
+/// assert_eq!(files.lookup(files.file(lib).base + 2), Some(lib));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a file and returns its id.
+    pub fn add(&mut self, name: impl Into<String>, text: impl Into<String>) -> FileId {
+        // Leave a gap after each file, so a span at the end of one file can't be
+        // mistaken for the start of the next.
+        let base = self
+            .files
+            .last()
+            .map_or(0, |file| file.base + file.text.len() + 1);
+        self.files.push(SourceFile {
+            name: name.into(),
+            text: text.into(),
+            base,
+        });
+        FileId(self.files.len() - 1)
+    }
+
+    /// Returns the file with the given id.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` is not from this map.
+    pub fn file(&self, id: FileId) -> &SourceFile {
+        &self.files[id.0]
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = (FileId, &SourceFile)> {
+        self.files.iter().enumerate().map(|(i, f)| (FileId(i), f))
+    }
+
+    /// Finds the file containing `offset`.
+    pub fn lookup(&self, offset: usize) -> Option<FileId> {
+        let index = self.files.partition_point(|f| f.base <= offset);
+        let index = index.checked_sub(1)?;
+        self.files[index].contains(offset).then_some(FileId(index))
+    }
+
+    /// Returns the text of `span`, if it lies within a single file.
+    pub fn text(&self, span: SourceSpan) -> Option<&str> {
+        let file = self.file(self.lookup(span.offset())?);
+        let start = span.offset() - file.base;
+        file.text.get(start..start + span.len())
+    }
+
+    /// A cursor at the start of `id`, positioned at its offset in the map.
+    pub(crate) fn cursor(&self, id: FileId) -> Cursor<'_> {
+        let file = self.file(id);
+        Cursor {
+            rest: &file.text,
+            offset: file.base,
+        }
+    }
+}
+
+impl SourceCode for SourceMap {
+    fn read_span<'a>(
+        &'a self,
+        span: &SourceSpan,
+        context_lines_before: usize,
+        context_lines_after: usize,
+    ) -> Result<Box<dyn SpanContents<'a> + 'a>, MietteError> {
+        let file = self
+            .lookup(span.offset())
+            .map(|id| self.file(id))
+            .ok_or(MietteError::OutOfBounds)?;
+        let local = SourceSpan::new((span.offset() - file.base).into(), span.len());
+        let contents =
+            file.text
+                .as_str()
+                .read_span(&local, context_lines_before, context_lines_after)?;
+
+        let offset = contents.span().offset() + file.base;
+        Ok(Box::new(MietteSpanContents::new_named(
+            file.name.clone(),
+            contents.data(),
+            SourceSpan::new(offset.into(), contents.span().len()),
+            contents.line(),
+            contents.column(),
+            contents.line_count(),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::language::Delimiter;
+    use crate::lexer::{LexError, lex_file, lex_with_diagnostics};
+    use crate::mock::MockLanguage;
+    use crate::token::TokenTree;
+    use miette::{GraphicalReportHandler, GraphicalTheme, Report};
+
+    fn two_files() -> (SourceMap, FileId, FileId) {
+        let mut files = SourceMap::new();
+        let a = files.add("a.txt", "foo (bar)");
+        let b = files.add("b.txt", "baz\n(qux");
+        (files, a, b)
+    }
+
+    #[test]
+    fn test_lookup() {
+        let (files, a, b) = two_files();
+        assert_eq!(files.lookup(0), Some(a));
+        assert_eq!(files.lookup(9), Some(a));
+        assert_eq!(files.lookup(10), Some(b));
+        assert_eq!(files.lookup(18), Some(b));
+        assert_eq!(files.lookup(19), None);
+        assert_eq!(files.text(SourceSpan::new(14.into(), 4)), Some("(qux"));
+    }
+
+    #[test]
+    fn test_lex_file_uses_map_offsets() {
+        let (files, a, b) = two_files();
+        let (trees_a, _) = lex_file(&files, a, &MockLanguage::new());
+        let (trees_b, _) = lex_file(&files, b, &MockLanguage::new());
+
+        let TokenTree::Token(foo) = &trees_a[0] else {
+            panic!("Expected token");
+        };
+        assert_eq!((foo.location.span.offset(), foo.location.file), (0, a));
+
+        let TokenTree::Token(baz) = &trees_b[0] else {
+            panic!("Expected token");
+        };
+        assert_eq!((baz.location.span.offset(), baz.location.file), (10, b));
+        assert_eq!(files.text(baz.location.span), Some("baz"));
+
+        let TokenTree::Delimited(_, children, location, false) = &trees_b[2] else {
+            panic!("Expected unclosed group");
+        };
+        assert_eq!(location.file, b);
+        assert!(matches!(&children[0], TokenTree::Token(t) if t.location.file == b));
+    }
+
+    #[test]
+    fn test_lex_file_starts_a_line_at_the_file_start() {
+        let lang = MockLanguage::new()
+            .with_delimiter(Delimiter::new("brace", "{", "}").with_indentation_recovery());
+        let text = "  a {\n    b\n  c";
+        let mut files = SourceMap::new();
+        files.add("a.txt", "x");
+        let b = files.add("b.txt", text);
+
+        // The first line is indented like the `c` line, so `c` closes the group.
+        let (alone, _) = lex_with_diagnostics(text, &lang);
+        let (in_map, _) = lex_file(&files, b, &lang);
+        let sexp =
+            |trees: &[TokenTree]| -> Vec<String> { trees.iter().map(|t| t.to_sexp()).collect() };
+        assert_eq!(sexp(&in_map), sexp(&alone));
+        assert!(matches!(in_map.last(), Some(TokenTree::Token(t)) if t.text == "c"));
+    }
+
+    #[test]
+    fn test_diagnostics_render_with_file_name() {
+        let (files, _, b) = two_files();
+        let (_, errors) = lex_file(&files, b, &MockLanguage::new());
+        assert!(matches!(&errors[..], [LexError::UnclosedDelimiter { .. }]));
+
+        let report = Report::new(errors[0].clone()).with_source_code(files);
+        let mut out = String::new();
+        GraphicalReportHandler::new_themed(GraphicalTheme::unicode_nocolor())
+            .render_report(&mut out, report.as_ref())
+            .unwrap();
+
+        assert!(out.contains("[b.txt:2:1]"), "{out}");
+        assert!(out.contains("(qux"), "{out}");
+        assert!(!out.contains("foo"), "{out}");
+    }
+}
@@ -0,0 +1,454 @@
+//! Lexing input as it is read, without holding the whole document.
+//!
+//! [`TokenReader`] (for [`std::io::Read`]) and [`AsyncTokenReader`] (for tokio's
+//! [`AsyncRead`]) yield top-level `TokenTree`s as soon as they are complete, with
+//! the same offsets that [`crate::lexer::lex`] would give them. Only the text
+//! that hasn't been emitted yet is kept: the unfinished tree at the end of the
+//! input, plus a lookahead window. Both guarantees have limits, described below.
+//!
+//! ## The lookahead window
+//!
+//! Atoms can't say whether more input would change their answer: a string atom
+//! fails on `"abc` until the closing quote arrives. So a tree is only emitted once
+//! [`DEFAULT_LOOKAHEAD`] bytes (or the amount given to `with_lookahead`) of input
+//! follow it. The trees are exactly those of batch lexing as long as no token,
+//! and no failed attempt to match one, needs to see further ahead than that.
+//!
+//! Past the window, streaming and batch lexing can disagree. With the atoms
+//! `x[^y]*y` and `x`, in that order, an `x` followed by more than the window of
+//! text before its `y` is emitted as a lone `x` token, where batch lexing gives
+//! one long token. Choose a window longer than any token the language allows.
+//!
+//! ## Languages that aren't streamed
+//!
+//! Languages using the offside rule or attaching trivia, or whose top-level atoms
+//! switch lexer modes, depend on state that can't be carried between reads. For
+//! them the whole input is buffered and lexed at the end, so nothing is emitted
+//! early and memory grows with the input.
+
+use crate::language::Language;
+use crate::lexer::{LexError, Lexer, last_line_indentation, tree_span};
+use crate::token::{Cursor, TokenTree};
+use std::collections::VecDeque;
+use std::io::{self, Read};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// How many bytes of input must follow a tree before it is emitted.
+pub const DEFAULT_LOOKAHEAD: usize = 64 * 1024;
+
+/// How many bytes are read at a time.
+const CHUNK_SIZE: usize = 8 * 1024;
+
+/// The reader-independent part of a streaming lexer.
+struct StreamLexer<'l, L: Language + ?Sized> {
+    language: &'l L,
+    /// Input that has been read but not emitted, starting at offset `base`.
+    buffer: String,
+    base: usize,
+    /// The start of a UTF-8 character split across reads.
+    partial: Vec<u8>,
+    /// The indentation of the line that `buffer` starts on.
+    line_indentation: usize,
+    /// Lex again once the buffer reaches this length. Doubling it each time
+    /// keeps the cost of re-lexing unfinished trees linear.
+    next_lex: usize,
+    lookahead: usize,
+    /// Whether the language needs the whole input before anything is emitted.
+    whole_input: bool,
+    ready: VecDeque<TokenTree>,
+    errors: Vec<LexError>,
+    finished: bool,
+}
+
+impl<'l, L: Language + ?Sized> StreamLexer<'l, L> {
+    fn new(language: &'l L) -> Self {
+        let whole_input = language.offside_rule()
+            || language.attach_trivia()
+            || language
+                .atoms()
+                .iter()
+                .any(|atom| atom.mode_transition().is_some());
+        Self {
+            language,
+            buffer: String::new(),
+            base: 0,
+            partial: Vec::new(),
+            line_indentation: 0,
+            next_lex: 2 * DEFAULT_LOOKAHEAD,
+            lookahead: DEFAULT_LOOKAHEAD,
+            whole_input,
+            ready: VecDeque::new(),
+            errors: Vec::new(),
+            finished: false,
+        }
+    }
+
+    fn set_lookahead(&mut self, lookahead: usize) {
+        self.lookahead = lookahead;
+        self.next_lex = 2 * lookahead.max(1);
+    }
+
+    /// Appends bytes that were read, lexing if enough input has built up.
+    fn push(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.partial.extend_from_slice(bytes);
+        let valid = match std::str::from_utf8(&self.partial) {
+            Ok(text) => text.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        };
+        // The prefix was just validated.
+        self.buffer
+            .push_str(std::str::from_utf8(&self.partial[..valid]).unwrap());
+        self.partial.drain(..valid);
+
+        if !self.whole_input && self.buffer.len() >= self.next_lex {
+            self.lex(false);
+        }
+        Ok(())
+    }
+
+    /// Lexes the rest of the input once the reader is exhausted.
+    fn finish(&mut self) -> io::Result<()> {
+        self.finished = true;
+        if !self.partial.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "stream did not contain valid UTF-8",
+            ));
+        }
+        self.lex(true);
+        Ok(())
+    }
+
+    fn lex(&mut self, at_end: bool) {
+        let cursor = Cursor {
+            rest: &self.buffer,
+            offset: self.base,
+        };
+        let mut lexer = Lexer::new(self.language);
+        if self.base > 0 {
+            lexer = lexer.on_line_indented(self.line_indentation);
+        }
+        let (trees, errors) = lexer.lex_with_diagnostics(cursor);
+
+        if at_end {
+            self.ready.extend(trees);
+            self.errors.extend(errors);
+            self.buffer.clear();
+            return;
+        }
+
+        // Emit the trees followed by a full lookahead window, but never the last
+        // tree, which may still grow.
+        let limit = (self.base + self.buffer.len()).saturating_sub(self.lookahead);
+        let complete = trees
+            .iter()
+            .take(trees.len().saturating_sub(1))
+            .take_while(|tree| tree_span(tree).is_some_and(|s| s.offset() + s.len() <= limit))
+            .count();
+
+        if complete > 0 {
+            let emitted = &trees[..complete];
+            let cut = tree_span(&trees[complete]).map_or(limit, |s| s.offset());
+            let input_start = (self.base == 0).then_some(0);
+            if let Some(width) = last_line_indentation(emitted, input_start) {
+                self.line_indentation = width;
+            }
+            self.errors
+                .extend(errors.into_iter().filter(|e| e.span().offset() < cut));
+            self.buffer.drain(..cut - self.base);
+            self.base = cut;
+            self.ready.extend(trees.into_iter().take(complete));
+        }
+
+        self.next_lex = (2 * self.buffer.len()).max(2 * self.lookahead.max(1));
+    }
+}
+
+/// Lexes a [`Read`] lazily, yielding each top-level tree once it is complete.
+///
+/// The trees match those of [`crate::lexer::lex`] only while no token needs
+/// more than the lookahead window to be matched, and languages using the offside
+/// rule, trivia attachment or top-level mode switches are buffered whole. See the
+/// [module documentation](self) for details.
+///
+/// ```rust
+/// use mcparse::define_language;
+/// use mcparse::stream::TokenReader;
+///
+/// define_language! {
+///     struct Config;
+///     atoms = [
+///         atom Whitespace = regex r"\s+",
+///         atom Identifier = regex r"[a-z]+",
+///     ];
+///     delimiters = [
+///         delimiter "brace" = "{", "}",
+///     ];
+/// }
+///
+/// let language = Config::new();
+/// let input = "a { b } c".as_bytes();
+/// let trees: Vec<_> = TokenReader::new(input, &language)
+///     .collect::<Result<_, _>>()
+///     .unwrap();
+/// assert_eq!(trees.len(), 5);
+/// ```
+pub struct TokenReader<'l, R, L: Language + ?Sized> {
+    reader: R,
+    lexer: StreamLexer<'l, L>,
+}
+
+impl<'l, R: Read, L: Language + ?Sized> TokenReader<'l, R, L> {
+    pub fn new(reader: R, language: &'l L) -> Self {
+        Self {
+            reader,
+            lexer: StreamLexer::new(language),
+        }
+    }
+
+    /// Sets how many bytes of input must follow a tree before it is emitted.
+    ///
+    /// Tokens longer than this may be split where batch lexing wouldn't split them.
+    pub fn with_lookahead(mut self, lookahead: usize) -> Self {
+        self.lexer.set_lookahead(lookahead);
+        self
+    }
+
+    /// The diagnostics for the trees emitted so far.
+    pub fn errors(&self) -> &[LexError] {
+        &self.lexer.errors
+    }
+}
+
+impl<R: Read, L: Language + ?Sized> Iterator for TokenReader<'_, R, L> {
+    type Item = io::Result<TokenTree>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut chunk = [0; CHUNK_SIZE];
+        loop {
+            if let Some(tree) = self.lexer.ready.pop_front() {
+                return Some(Ok(tree));
+            }
+            if self.lexer.finished {
+                return None;
+            }
+            let result = match self.reader.read(&mut chunk) {
+                Ok(0) => self.lexer.finish(),
+                Ok(n) => self.lexer.push(&chunk[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+/// Lexes an [`AsyncRead`] lazily, yielding each top-level tree once it is complete.
+///
+/// This is the async counterpart of [`TokenReader`], with the same limits (see
+/// the [module documentation](self)):
+///
+/// ```rust,ignore
+/// let mut trees = AsyncTokenReader::new(file, &language);
+/// while let Some(tree) = trees.next().await {
+///     handle(tree?);
+/// }
+/// ```
+pub struct AsyncTokenReader<'l, R, L: Language + ?Sized> {
+    reader: R,
+    lexer: StreamLexer<'l, L>,
+}
+
+impl<'l, R: AsyncRead + Unpin, L: Language + ?Sized> AsyncTokenReader<'l, R, L> {
+    pub fn new(reader: R, language: &'l L) -> Self {
+        Self {
+            reader,
+            lexer: StreamLexer::new(language),
+        }
+    }
+
+    /// Sets how many bytes of input must follow a tree before it is emitted.
+    ///
+    /// Tokens longer than this may be split where batch lexing wouldn't split them.
+    pub fn with_lookahead(mut self, lookahead: usize) -> Self {
+        self.lexer.set_lookahead(lookahead);
+        self
+    }
+
+    /// The diagnostics for the trees emitted so far.
+    pub fn errors(&self) -> &[LexError] {
+        &self.lexer.errors
+    }
+
+    /// Returns the next top-level tree, or `None` at the end of the input.
+    pub async fn next(&mut self) -> Option<io::Result<TokenTree>> {
+        let mut chunk = [0; CHUNK_SIZE];
+        loop {
+            if let Some(tree) = self.lexer.ready.pop_front() {
+                return Some(Ok(tree));
+            }
+            if self.lexer.finished {
+                return None;
+            }
+            let result = match self.reader.read(&mut chunk).await {
+                Ok(0) => self.lexer.finish(),
+                Ok(n) => self.lexer.push(&chunk[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atom::AtomKind;
+    use crate::atoms::RegexAtom;
+    use crate::language::Delimiter;
+    use crate::lexer::lex_with_diagnostics;
+    use crate::mock::MockLanguage;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use tokio::io::ReadBuf;
+
+    /// A reader that returns at most `size` bytes per read, splitting characters.
+    struct Trickle<'a> {
+        data: &'a [u8],
+        size: usize,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.size.min(buf.len()).min(self.data.len());
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    /// Like [`Trickle`], but every other poll is pending.
+    struct AsyncTrickle<'a> {
+        trickle: Trickle<'a>,
+        pending: bool,
+    }
+
+    impl AsyncRead for AsyncTrickle<'_> {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            self.pending = !self.pending;
+            if self.pending {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            let n = self.trickle.read(buf.initialize_unfilled())?;
+            buf.advance(n);
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn language() -> MockLanguage {
+        MockLanguage::new()
+            .with_delimiter(Delimiter::new("brace", "{", "}"))
+            .with_atom(Box::new(
+                RegexAtom::new(AtomKind::String, r#""[^"]*""#).opaque(),
+            ))
+    }
+
+    fn input() -> String {
+        (0..50)
+            .map(|i| format!("item{i} {{ naïve (\"}} é {i}\") }} ?? \n"))
+            .collect::<String>()
+            + "tail { unclosed"
+    }
+
+    fn debug(trees: &[TokenTree]) -> Vec<String> {
+        trees.iter().map(|t| format!("{t:?}")).collect()
+    }
+
+    #[test]
+    fn test_token_reader_matches_batch_lexing() {
+        let lang = language();
+        let input = input();
+        let (expected, expected_errors) = lex_with_diagnostics(&input, &lang);
+
+        let mut reader = TokenReader::new(
+            Trickle {
+                data: input.as_bytes(),
+                size: 7,
+            },
+            &lang,
+        )
+        .with_lookahead(16);
+        let trees: Vec<TokenTree> = reader.by_ref().collect::<Result<_, _>>().unwrap();
+
+        assert_eq!(debug(&trees), debug(&expected));
+        assert_eq!(reader.errors(), &expected_errors[..]);
+    }
+
+    #[test]
+    fn test_token_reader_emits_before_end_of_input() {
+        let lang = language();
+        let input = input();
+        let mut reader = TokenReader::new(
+            Trickle {
+                data: input.as_bytes(),
+                size: 7,
+            },
+            &lang,
+        )
+        .with_lookahead(16);
+
+        reader.next().unwrap().unwrap();
+        // The first tree came from a partial read, and was dropped from the buffer.
+        assert!(!reader.lexer.finished);
+        assert!(reader.lexer.base > 0);
+    }
+
+    #[test]
+    fn test_token_reader_rejects_invalid_utf8() {
+        let lang = language();
+        let mut reader = TokenReader::new(&b"ab \xff"[..], &lang);
+        let err = reader.find_map(Result::err).unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn test_async_token_reader_matches_batch_lexing() {
+        let lang = language();
+        let input = input();
+        let (expected, expected_errors) = lex_with_diagnostics(&input, &lang);
+
+        let trickle = Trickle {
+            data: input.as_bytes(),
+            size: 7,
+        };
+        let mut reader = AsyncTokenReader::new(
+            AsyncTrickle {
+                trickle,
+                pending: false,
+            },
+            &lang,
+        )
+        .with_lookahead(16);
+
+        let mut trees = vec![reader.next().await.unwrap().unwrap()];
+        assert!(!reader.lexer.finished);
+        assert!(reader.lexer.base > 0);
+        while let Some(tree) = reader.next().await {
+            trees.push(tree.unwrap());
+        }
+
+        assert_eq!(debug(&trees), debug(&expected));
+        assert_eq!(reader.errors(), &expected_errors[..]);
+    }
+}
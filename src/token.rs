@@ -1,5 +1,6 @@
 use crate::atom::AtomKind;
-use crate::language::Delimiter;
+use crate::language::{Delimiter, SharedStr};
+use crate::source_map::FileId;
 use miette::SourceSpan;
 
 /// A unique identifier for a variable binding.
@@ -11,12 +12,15 @@ pub struct BindingId(pub usize);
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SourceLocation {
     pub span: SourceSpan,
+    /// The file the span points into. Set by the lexer; see [`crate::source_map::SourceMap`].
+    pub file: FileId,
 }
 
 impl SourceLocation {
     pub fn new(start: usize, len: usize) -> Self {
         Self {
             span: (start, len).into(),
+            file: FileId::default(),
         }
     }
 
@@ -28,6 +32,26 @@ impl SourceLocation {
     }
 }
 
+/// The value of a literal token, decoded by the atom that lexed it.
+///
+/// Decoding is best-effort: a literal with errors still gets a value where one
+/// makes sense (e.g. an invalid escape is kept as written), and the errors are
+/// reported by [`crate::lexer::lex_with_diagnostics`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum LiteralValue {
+    /// A string with its quotes removed and escapes resolved.
+    String(String),
+    Integer {
+        value: u128,
+        radix: u32,
+        suffix: Option<String>,
+    },
+    Float {
+        value: f64,
+        suffix: Option<String>,
+    },
+}
+
 #[derive(Debug, Clone)]
 /// A single atomic unit of code (identifier, keyword, operator, etc.).
 pub struct Token {
@@ -39,6 +63,13 @@ pub struct Token {
     pub atom_index: Option<usize>,
     /// The ID of the variable binding this token refers to or defines.
     pub binding: Option<BindingId>,
+    /// Trivia before this token, when the language attaches trivia
+    /// (see [`crate::Language::attach_trivia`]).
+    pub leading_trivia: Vec<Token>,
+    /// Trivia after this token, up to and including the end of its line.
+    pub trailing_trivia: Vec<Token>,
+    /// The decoded value, for literals whose atom provides one.
+    pub value: Option<LiteralValue>,
 }
 
 impl Token {
@@ -49,8 +80,85 @@ impl Token {
             location: SourceLocation::new(offset, text.len()),
             atom_index: None,
             binding: None,
+            leading_trivia: Vec::new(),
+            trailing_trivia: Vec::new(),
+            value: None,
+        }
+    }
+
+    pub fn with_value(mut self, value: LiteralValue) -> Self {
+        self.value = Some(value);
+        self
+    }
+
+    /// The decoded contents of a string literal.
+    pub fn string_value(&self) -> Option<&str> {
+        match &self.value {
+            Some(LiteralValue::String(s)) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The value of an integer literal.
+    pub fn integer_value(&self) -> Option<u128> {
+        match self.value {
+            Some(LiteralValue::Integer { value, .. }) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The value of a float literal.
+    pub fn float_value(&self) -> Option<f64> {
+        match self.value {
+            Some(LiteralValue::Float { value, .. }) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The radix of a number literal. Floats are always decimal.
+    pub fn radix(&self) -> Option<u32> {
+        match self.value {
+            Some(LiteralValue::Integer { radix, .. }) => Some(radix),
+            Some(LiteralValue::Float { .. }) => Some(10),
+            _ => None,
+        }
+    }
+
+    /// The type suffix of a number literal, such as `u8` in `255u8`.
+    pub fn suffix(&self) -> Option<&str> {
+        match &self.value {
+            Some(LiteralValue::Integer { suffix, .. } | LiteralValue::Float { suffix, .. }) => {
+                suffix.as_deref()
+            }
+            _ => None,
         }
     }
+
+    /// The span of the token together with its attached trivia.
+    pub fn full_span(&self) -> SourceSpan {
+        let start = self
+            .leading_trivia
+            .first()
+            .map_or(self.location.span.offset(), |t| t.location.span.offset());
+        let end = self
+            .trailing_trivia
+            .last()
+            .map_or(&self.location, |t| &t.location);
+        SourceSpan::new(start.into(), end.span.offset() + end.span.len() - start)
+    }
+
+    /// The text of the token together with its attached trivia.
+    pub fn full_text(&self) -> String {
+        let mut text = String::new();
+        for trivia in &self.leading_trivia {
+            text.push_str(&trivia.text);
+        }
+        text.push_str(&self.text);
+        for trivia in &self.trailing_trivia {
+            text.push_str(&trivia.text);
+        }
+        text
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -60,15 +168,80 @@ pub enum TokenTree {
     Token(Token),
     Delimited(Delimiter, Vec<TokenTree>, SourceLocation, bool),
     Group(Vec<TokenTree>), // For sequences
-    Error(String),
+    /// A kinded syntax node, built by [`crate::shape::node`].
+    Node(SyntaxNode),
+    /// A named child of the enclosing node, built by [`crate::shape::field`].
+    Field(SharedStr, Box<TokenTree>),
+    /// Input that [`crate::shape::recover`] skipped, with the reason and the
+    /// location of the skipped tokens.
+    Error(String, SourceLocation),
     Empty,
 }
 
+/// A syntax node: what a shape matched, labelled with a kind such as `"let"`.
+#[derive(Debug, Clone)]
+pub struct SyntaxNode {
+    pub kind: SharedStr,
+    pub children: Vec<TokenTree>,
+    /// The span from the first to the last token of the node, without trivia.
+    pub location: SourceLocation,
+}
+
+impl SyntaxNode {
+    /// Returns the first child field called `name`.
+    pub fn field(&self, name: &str) -> Option<&TokenTree> {
+        self.fields(name).into_iter().next()
+    }
+
+    /// Returns every child field called `name`, in source order.
+    ///
+    /// Fields are looked up through groups and delimited trees, but not in
+    /// nested nodes, which own their fields.
+    pub fn fields(&self, name: &str) -> Vec<&TokenTree> {
+        fn collect<'t>(trees: &'t [TokenTree], name: &str, found: &mut Vec<&'t TokenTree>) {
+            for tree in trees {
+                match tree {
+                    TokenTree::Field(field, value) if *field == name => found.push(value),
+                    TokenTree::Group(children) | TokenTree::Delimited(_, children, _, _) => {
+                        collect(children, name, found)
+                    }
+                    _ => {}
+                }
+            }
+        }
+        let mut found = Vec::new();
+        collect(&self.children, name, &mut found);
+        found
+    }
+}
+
 impl TokenTree {
     pub fn empty() -> Self {
         TokenTree::Empty
     }
 
+    /// Reconstructs the source text of this tree, including any attached trivia.
+    pub fn text(&self) -> String {
+        match self {
+            TokenTree::Token(t) => t.full_text(),
+            TokenTree::Delimited(d, children, _, is_closed) => {
+                let mut s = String::new();
+                s.push_str(&d.open);
+                for child in children {
+                    s.push_str(&child.text());
+                }
+                if *is_closed {
+                    s.push_str(&d.close);
+                }
+                s
+            }
+            TokenTree::Group(children) => children.iter().map(|c| c.text()).collect(),
+            TokenTree::Node(node) => node.children.iter().map(|c| c.text()).collect(),
+            TokenTree::Field(_, value) => value.text(),
+            TokenTree::Error(..) | TokenTree::Empty => String::new(),
+        }
+    }
+
     pub fn to_sexp(&self) -> String {
         match self {
             TokenTree::Token(t) => format!("{:?}", t.text),
@@ -80,7 +253,12 @@ impl TokenTree {
                 let inner: Vec<String> = children.iter().map(|c| c.to_sexp()).collect();
                 format!("(group {})", inner.join(" "))
             }
-            TokenTree::Error(msg) => format!("(error {:?})", msg),
+            TokenTree::Node(node) => {
+                let inner: Vec<String> = node.children.iter().map(|c| c.to_sexp()).collect();
+                format!("({} {})", node.kind, inner.join(" "))
+            }
+            TokenTree::Field(name, value) => format!("{}:{}", name, value.to_sexp()),
+            TokenTree::Error(msg, _) => format!("(error {:?})", msg),
             TokenTree::Empty => "(empty)".to_string(),
         }
     }
@@ -116,11 +294,22 @@ impl<'a> Cursor<'a> {
 #[derive(Debug, Clone)]
 pub struct TokenStream<'a> {
     pub trees: &'a [TokenTree],
+    /// The tree just before `trees`, if the stream was advanced past it.
+    previous: Option<&'a TokenTree>,
 }
 
 impl<'a> TokenStream<'a> {
     pub fn new(trees: &'a [TokenTree]) -> Self {
-        Self { trees }
+        Self {
+            trees,
+            previous: None,
+        }
+    }
+
+    /// The tree just before the stream, if it was advanced past one. Shapes
+    /// use it to see trivia attached to the end of the previous token.
+    pub fn previous(&self) -> Option<&'a TokenTree> {
+        self.previous
     }
 
     pub fn is_empty(&self) -> bool {
@@ -134,6 +323,10 @@ impl<'a> TokenStream<'a> {
     pub fn advance(&self, n: usize) -> Self {
         Self {
             trees: &self.trees[n..],
+            previous: match n {
+                0 => self.previous,
+                n => Some(&self.trees[n - 1]),
+            },
         }
     }
 }
@@ -2,18 +2,18 @@
 mod tests {
     use crate::atom::AtomKind;
     use crate::language::Delimiter;
-    use crate::token::{SourceLocation, Token, TokenTree};
-    use miette::SourceSpan;
+    use crate::token::{SourceLocation, SyntaxNode, Token, TokenTree};
 
     fn mock_token(text: &str) -> TokenTree {
         TokenTree::Token(Token {
             kind: AtomKind::Identifier,
             text: text.to_string(),
-            location: SourceLocation {
-                span: SourceSpan::new(0usize.into(), 0usize.into()),
-            },
+            location: SourceLocation::new(0usize, 0usize),
             atom_index: None,
             binding: None,
+            leading_trivia: Vec::new(),
+            trailing_trivia: Vec::new(),
+            value: None,
         })
     }
 
@@ -34,19 +34,56 @@ mod tests {
     #[test]
     fn test_sexp_delimited() {
         let t1 = mock_token("key");
-        let d = Delimiter {
-            kind: "brace",
-            open: "{",
-            close: "}",
-        };
-        let tree = TokenTree::Delimited(
+        let d = Delimiter::new("brace", "{", "}");
+        let tree = TokenTree::Delimited(d, vec![t1], SourceLocation::new(0usize, 0usize), true);
+        assert_eq!(tree.to_sexp(), "(brace \"key\")");
+    }
+
+    fn mock_node(kind: &'static str, children: Vec<TokenTree>) -> TokenTree {
+        TokenTree::Node(SyntaxNode {
+            kind: kind.into(),
+            children,
+            location: SourceLocation::new(0usize, 0usize),
+        })
+    }
+
+    fn field(name: &'static str, tree: TokenTree) -> TokenTree {
+        TokenTree::Field(name.into(), Box::new(tree))
+    }
+
+    #[test]
+    fn test_sexp_node() {
+        let node = mock_node(
+            "let",
+            vec![mock_token("let"), field("name", mock_token("x"))],
+        );
+        assert_eq!(node.to_sexp(), "(let \"let\" name:\"x\")");
+    }
+
+    #[test]
+    fn test_node_fields() {
+        let d = Delimiter::new("paren", "(", ")");
+        let args = TokenTree::Delimited(
             d,
-            vec![t1],
-            SourceLocation {
-                span: SourceSpan::new(0usize.into(), 0usize.into()),
-            },
+            vec![field("arg", mock_token("a")), field("arg", mock_token("b"))],
+            SourceLocation::new(0usize, 0usize),
             true,
         );
-        assert_eq!(tree.to_sexp(), "(brace \"key\")");
+        let inner = mock_node("call", vec![field("callee", mock_token("g"))]);
+        let TokenTree::Node(node) = mock_node(
+            "call",
+            vec![
+                TokenTree::Group(vec![field("callee", mock_token("f"))]),
+                args,
+                field("arg", inner),
+            ],
+        ) else {
+            unreachable!()
+        };
+
+        assert_eq!(node.field("callee").unwrap().to_sexp(), "\"f\"");
+        let args: Vec<_> = node.fields("arg").iter().map(|a| a.to_sexp()).collect();
+        assert_eq!(args, ["\"a\"", "\"b\"", "(call callee:\"g\")"]);
+        assert!(node.field("missing").is_none());
     }
 }
@@ -0,0 +1,229 @@
+//! Attaching trivia to the tokens around it.
+//!
+//! By default, whitespace and comments are tokens of their own, siblings of the
+//! tokens they sit between. A tool that moves or rewrites a token then has to find
+//! its comments separately. When [`Language::attach_trivia`] is enabled, the lexer
+//! instead stores trivia on the neighbouring tokens, in the style of Roslyn:
+//!
+//! - A token's `trailing_trivia` is the trivia after it on the same line,
+//!   including the line break.
+//! - The rest of the trivia before a token is its `leading_trivia`.
+//!
+//! Attachment never crosses a delimiter, since groups aren't tokens. Trivia
+//! before a group's closer or before a nested group trails the previous token of
+//! the same group instead, and trivia in a group without tokens stays as sibling
+//! tokens. Either way, no text is lost: [`TokenTree::text`] reproduces the input.
+
+use crate::atom::AtomKind;
+use crate::language::Language;
+use crate::token::{SourceLocation, Token, TokenTree};
+use std::mem;
+
+/// Moves the trivia in `trees`, and in every group within them, onto the
+/// neighbouring tokens.
+///
+/// ```rust
+/// use mcparse::{define_language, lexer::lex, token::TokenTree};
+///
+/// define_language! {
+///     struct Lang;
+///     atoms = [
+///         atom Whitespace = regex r"\s+",
+///         atom Comment = regex r"//[^\n]*",
+///         atom Identifier = regex r"[a-z]+",
+///     ];
+///     delimiters = [];
+///     attach_trivia = true;
+/// }
+///
+/// let input = "// doc\nfoo // note\nbar";
+/// let trees = lex(input, &Lang::new());
+/// let TokenTree::Token(foo) = &trees[0] else { panic!() };
+/// assert_eq!(foo.leading_trivia[0].text, "// doc");
+/// assert_eq!(foo.trailing_trivia[1].text, "// note");
+///
+/// let text: String = trees.iter().map(TokenTree::text).collect();
+/// assert_eq!(text, input);
+/// ```
+pub fn attach_trivia(trees: Vec<TokenTree>, language: &(impl Language + ?Sized)) -> Vec<TokenTree> {
+    let mut out: Vec<TokenTree> = Vec::with_capacity(trees.len());
+    // Trivia waiting for the next token.
+    let mut pending: Vec<Token> = Vec::new();
+    // Whether the last tree in `out` is a token whose line hasn't ended yet.
+    let mut line_open = false;
+
+    for tree in trees {
+        match tree {
+            TokenTree::Token(token) if language.is_trivia(&token.kind) => {
+                let Some(TokenTree::Token(previous)) = out.last_mut().filter(|_| line_open) else {
+                    pending.push(token);
+                    continue;
+                };
+                match token.text.find('\n') {
+                    None => previous.trailing_trivia.push(token),
+                    Some(i) if i + 1 == token.text.len() => {
+                        previous.trailing_trivia.push(token);
+                        line_open = false;
+                    }
+                    Some(i) if token.kind == AtomKind::Whitespace => {
+                        let (head, tail) = split_token(token, i + 1);
+                        previous.trailing_trivia.push(head);
+                        pending.push(tail);
+                        line_open = false;
+                    }
+                    // A comment spanning lines leads the next token.
+                    Some(_) => {
+                        pending.push(token);
+                        line_open = false;
+                    }
+                }
+            }
+            TokenTree::Token(mut token) => {
+                token.leading_trivia = mem::take(&mut pending);
+                out.push(TokenTree::Token(token));
+                line_open = true;
+            }
+            TokenTree::Delimited(delimiter, children, location, is_closed) => {
+                flush(&mut out, &mut pending);
+                let children = attach_trivia(children, language);
+                out.push(TokenTree::Delimited(
+                    delimiter, children, location, is_closed,
+                ));
+                line_open = false;
+            }
+            tree => {
+                flush(&mut out, &mut pending);
+                out.push(tree);
+                line_open = false;
+            }
+        }
+    }
+    flush(&mut out, &mut pending);
+    out
+}
+
+/// Gives trivia that no token follows to the token before it, or leaves it in
+/// place if there is none.
+fn flush(out: &mut Vec<TokenTree>, pending: &mut Vec<Token>) {
+    if pending.is_empty() {
+        return;
+    }
+    match out.last_mut() {
+        Some(TokenTree::Token(previous)) => previous.trailing_trivia.append(pending),
+        _ => out.extend(pending.drain(..).map(TokenTree::Token)),
+    }
+}
+
+/// Splits a trivia token in two at byte `at` of its text.
+fn split_token(token: Token, at: usize) -> (Token, Token) {
+    let start = token.location.span.offset();
+    let part = |text: &str, offset: usize| Token {
+        text: text.to_string(),
+        location: SourceLocation {
+            span: (offset, text.len()).into(),
+            file: token.location.file,
+        },
+        ..token.clone()
+    };
+    let (head, tail) = token.text.split_at(at);
+    (part(head, start), part(tail, start + at))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::atom::AtomKind;
+    use crate::atoms::RegexAtom;
+    use crate::incremental::GreenTree;
+    use crate::language::Delimiter;
+    use crate::lexer::lex;
+    use crate::mock::MockLanguage;
+    use crate::token::{Token, TokenTree};
+
+    fn language() -> MockLanguage {
+        MockLanguage::new()
+            .with_atom(Box::new(RegexAtom::new(AtomKind::Comment, r"//[^\n]*")))
+            .with_delimiter(Delimiter::new("brace", "{", "}"))
+            .with_trivia_attachment()
+    }
+
+    fn texts(trivia: &[Token]) -> Vec<&str> {
+        trivia.iter().map(|t| t.text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_leading_and_trailing_trivia() {
+        let input = "// a\n\n  foo // b\n  // c\n  bar  ";
+        let trees = lex(input, &language());
+        assert_eq!(trees.len(), 2);
+
+        let TokenTree::Token(foo) = &trees[0] else {
+            panic!("Expected token");
+        };
+        assert_eq!(texts(&foo.leading_trivia), ["// a", "\n\n  "]);
+        assert_eq!(texts(&foo.trailing_trivia), [" ", "// b", "\n"]);
+
+        let TokenTree::Token(bar) = &trees[1] else {
+            panic!("Expected token");
+        };
+        assert_eq!(texts(&bar.leading_trivia), ["  ", "// c", "\n  "]);
+        assert_eq!(texts(&bar.trailing_trivia), ["  "]);
+
+        // The split whitespace keeps its offsets.
+        let newline = &foo.trailing_trivia[2];
+        assert_eq!(newline.location.span.offset(), 16);
+        assert_eq!(foo.full_span(), (0, 17).into());
+
+        let text: String = trees.iter().map(TokenTree::text).collect();
+        assert_eq!(text, input);
+    }
+
+    #[test]
+    fn test_trivia_stays_within_groups() {
+        let input = "foo // a\n{ // b\n  bar\n  // c\n} baz\n{ // d\n}";
+        let trees = lex(input, &language());
+
+        let TokenTree::Token(foo) = &trees[0] else {
+            panic!("Expected token");
+        };
+        assert_eq!(texts(&foo.trailing_trivia), [" ", "// a", "\n"]);
+
+        // Trivia after the opener leads the group's first token, and trivia before
+        // the closer trails its last.
+        let TokenTree::Delimited(_, children, _, true) = &trees[1] else {
+            panic!("Expected group");
+        };
+        let [TokenTree::Token(bar)] = &children[..] else {
+            panic!("Expected one token, got {:?}", children);
+        };
+        assert_eq!(texts(&bar.leading_trivia), [" ", "// b", "\n  "]);
+        assert_eq!(texts(&bar.trailing_trivia), ["\n", "  ", "// c", "\n"]);
+
+        // A group with no tokens keeps its trivia as siblings.
+        let TokenTree::Delimited(_, children, _, true) = &trees[3] else {
+            panic!("Expected group");
+        };
+        assert_eq!(children.len(), 3);
+
+        let text: String = trees.iter().map(TokenTree::text).collect();
+        assert_eq!(text, input);
+    }
+
+    #[test]
+    fn test_green_tree_keeps_trivia() {
+        let input = "foo // a\n{ bar }";
+        let trees = lex(input, &language());
+        let green = GreenTree::Group(trees.iter().map(GreenTree::from_token_tree).collect());
+
+        assert_eq!(green.text(), input);
+        assert_eq!(green.width(), input.len());
+        let GreenTree::Group(children) = &green else {
+            panic!("Expected group");
+        };
+        let GreenTree::Token(foo) = &children[0] else {
+            panic!("Expected token");
+        };
+        assert_eq!(foo.text, "foo");
+        assert_eq!(foo.leading_width(), 0);
+        assert_eq!(foo.width(), "foo // a\n".len());
+    }
+}